@@ -1,4 +1,4 @@
-use convcom::{AiService, Config, GitService, ModelName};
+use convcom::{AiService, Config, DiffRenderer, GitService, ModelName};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -10,7 +10,7 @@ async fn main() -> anyhow::Result<()> {
             println!("✅ Configuration loaded successfully");
 
             // Test AI service creation
-            match AiService::new(config.groq_api_key, config.anthropic_api_key) {
+            match AiService::from_config(&config) {
                 Ok(ai_service) => {
                     println!("✅ AI Service created successfully");
 
@@ -23,13 +23,16 @@ MODIFIED: README.md
                     println!("🤖 Testing AI call with model: {}", ModelName::default());
 
                     match ai_service
-                        .generate_commit_message(test_prompt.to_string(), ModelName::default())
+                        .generate_commit_message(
+                            test_prompt.to_string(),
+                            &[ModelName::default().into()],
+                        )
                         .await
                     {
-                        Ok(message) => {
+                        Ok(answer) => {
                             println!("✅ AI Service working! Generated message:");
                             println!("---");
-                            println!("{message}");
+                            println!("{}", answer.completion.content);
                             println!("---");
 
                             // Test Git service if we're in a git repo
@@ -38,32 +41,24 @@ MODIFIED: README.md
                                 Ok(git_service) => {
                                     println!("✅ Git Service created successfully");
 
-                                    match git_service.get_staged_files() {
+                                    match git_service.get_changes() {
                                         Ok(staged_files) => {
                                             println!(
                                                 "✅ Found {} staged files",
                                                 staged_files.len()
                                             );
                                             for file in &staged_files {
-                                                let status = git_service
-                                                    .get_file_status(file)
-                                                    .unwrap_or('?');
-                                                println!("   - {file} ({status})");
+                                                println!("   - {} ({})", file.path, file.kind);
                                             }
 
-                                            match git_service.build_diff_content() {
-                                                Ok(diff) => {
-                                                    println!(
-                                                        "✅ Diff content generated ({} chars)",
-                                                        diff.len()
-                                                    );
-                                                    if diff.len() < 500 {
-                                                        println!("Diff preview:\n{diff}");
-                                                    }
-                                                }
-                                                Err(e) => {
-                                                    println!("⚠️  Could not build diff: {e}")
-                                                }
+                                            let diff =
+                                                DiffRenderer::default().render(&staged_files);
+                                            println!(
+                                                "✅ Diff content generated ({} chars)",
+                                                diff.len()
+                                            );
+                                            if diff.len() < 500 {
+                                                println!("Diff preview:\n{diff}");
                                             }
                                         }
                                         Err(e) => {
@@ -0,0 +1,410 @@
+use crate::config::settings::Config;
+use crate::error::{ConvComError, Result};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Name of the repository-level configuration file
+pub const REPO_CONFIG_FILE: &str = ".convcom.toml";
+
+/// Name of the global configuration file in the config directory
+pub const GLOBAL_CONFIG_FILE: &str = "config.toml";
+
+/// Where an effective configuration value was set
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    /// The user's `config.toml`
+    Global(PathBuf),
+    /// The repository's `.convcom.toml`
+    Repository(PathBuf),
+    Env(&'static str),
+    Cli(&'static str),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::Global(path) | Origin::Repository(path) => write!(f, "{}", path.display()),
+            Origin::Env(var) => write!(f, "env {var}"),
+            Origin::Cli(flag) => write!(f, "{flag}"),
+        }
+    }
+}
+
+/// What a setting holds, for checking files and parsing environment variables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    String,
+    Integer,
+    Float,
+    /// Comma separated in the environment
+    List,
+    /// Separated by `,` or `->` in the environment
+    Chain,
+}
+
+/// A configurable value, by its dotted key in the TOML files
+struct Setting {
+    key: &'static str,
+    kind: Kind,
+    env: Option<&'static str>,
+    /// Never printed in full
+    secret: bool,
+    /// Keys, key commands and endpoints, which a cloned repository must not be able to change
+    global_only: bool,
+}
+
+const fn setting(key: &'static str, kind: Kind, env: Option<&'static str>) -> Setting {
+    Setting {
+        key,
+        kind,
+        env,
+        secret: false,
+        global_only: false,
+    }
+}
+
+const fn provider(key: &'static str, kind: Kind, env: &'static str) -> Setting {
+    Setting {
+        global_only: true,
+        ..setting(key, kind, Some(env))
+    }
+}
+
+const fn secret(key: &'static str, env: &'static str) -> Setting {
+    Setting {
+        secret: true,
+        ..provider(key, Kind::String, env)
+    }
+}
+
+/// Every setting, in the order `convcom config show` prints them
+const SETTINGS: &[Setting] = &[
+    setting("model", Kind::String, Some("CONVCOM_MODEL")),
+    setting("fallback", Kind::Chain, Some("CONVCOM_FALLBACK")),
+    setting("temperature", Kind::Float, Some("CONVCOM_TEMPERATURE")),
+    setting("max_tokens", Kind::Integer, Some("CONVCOM_MAX_TOKENS")),
+    setting("max_attempts", Kind::Integer, Some("CONVCOM_MAX_ATTEMPTS")),
+    setting("types", Kind::List, Some("CONVCOM_TYPES")),
+    setting("scopes", Kind::List, Some("CONVCOM_SCOPES")),
+    setting("max_header_length", Kind::Integer, None),
+    setting("exclude", Kind::List, None),
+    secret("groq.api_key", "GROQ_API_KEY"),
+    provider("groq.api_key_cmd", Kind::String, "GROQ_API_KEY_CMD"),
+    secret("anthropic.api_key", "ANTHROPIC_API_KEY"),
+    provider(
+        "anthropic.api_key_cmd",
+        Kind::String,
+        "ANTHROPIC_API_KEY_CMD",
+    ),
+    provider("openai.base_url", Kind::String, "OPENAI_BASE_URL"),
+    secret("openai.api_key", "OPENAI_API_KEY"),
+    provider("openai.api_key_cmd", Kind::String, "OPENAI_API_KEY_CMD"),
+    provider("openai.auth_header", Kind::String, "OPENAI_AUTH_HEADER"),
+    provider("openai.model", Kind::String, "OPENAI_MODEL"),
+    provider("openai.timeout", Kind::Integer, "OPENAI_TIMEOUT"),
+    provider("ollama.host", Kind::String, "OLLAMA_HOST"),
+    provider("ollama.model", Kind::String, "OLLAMA_MODEL"),
+    provider("ollama.keep_alive", Kind::String, "OLLAMA_KEEP_ALIVE"),
+    provider("ollama.num_ctx", Kind::Integer, "OLLAMA_NUM_CTX"),
+];
+
+fn find_setting(key: &str) -> Option<&'static Setting> {
+    SETTINGS.iter().find(|setting| setting.key == key)
+}
+
+/// Configuration values by dotted key, each with the layer that set it last
+///
+/// Layers are applied from defaults up: the global file, the repository
+/// file, environment variables and command line flags.
+#[derive(Debug, Clone, Default)]
+pub struct Layers {
+    values: BTreeMap<&'static str, (Value, Origin)>,
+}
+
+impl Layers {
+    /// Layers holding only the given defaults
+    pub fn with_defaults(defaults: impl IntoIterator<Item = (&'static str, Value)>) -> Self {
+        let mut layers = Self::default();
+        for (key, value) in defaults {
+            layers.values.insert(key, (value, Origin::Default));
+        }
+        layers
+    }
+
+    /// Apply a TOML file, if it exists
+    ///
+    /// Sections such as `[ollama]` set the keys under them. Unknown keys are
+    /// errors, and so are provider settings in a repository file.
+    pub fn merge_file(&mut self, origin: Origin) -> Result<()> {
+        let path = match &origin {
+            Origin::Global(path) | Origin::Repository(path) => path,
+            _ => return Ok(()),
+        };
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let content = std::fs::read_to_string(path)?;
+        let table: Table = toml::from_str(&content).map_err(|e| {
+            ConvComError::ConfigError(format!("Failed to load {}: {e}", path.display()))
+        })?;
+        self.merge_table("", table, &origin)
+    }
+
+    fn merge_table(&mut self, prefix: &str, table: Table, origin: &Origin) -> Result<()> {
+        for (name, value) in table {
+            let key = format!("{prefix}{name}");
+            match value {
+                Value::Table(table) => self.merge_table(&format!("{key}."), table, origin)?,
+                value => self.set(&key, value, origin.clone())?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the environment variables of every setting that has one
+    pub fn merge_env(&mut self) -> Result<()> {
+        for setting in SETTINGS {
+            let Some(var) = setting.env else { continue };
+            let Some(raw) = std::env::var(var).ok().filter(|raw| !raw.is_empty()) else {
+                continue;
+            };
+            let value = parse(setting, &raw).ok_or_else(|| {
+                ConvComError::ConfigError(format!("{var}: {}", expected(setting)))
+            })?;
+            self.set(setting.key, value, Origin::Env(var))?;
+        }
+        Ok(())
+    }
+
+    /// Apply a command line flag, parsed like an environment variable
+    pub fn merge_arg(&mut self, key: &str, flag: &'static str, raw: &str) -> Result<()> {
+        let setting = find_setting(key)
+            .ok_or_else(|| ConvComError::ConfigError(format!("Unknown setting '{key}'")))?;
+        let value = parse(setting, raw)
+            .ok_or_else(|| ConvComError::ConfigError(format!("{flag}: {}", expected(setting))))?;
+        self.set(key, value, Origin::Cli(flag))
+    }
+
+    /// Set a value after checking that the key exists and the value fits it
+    fn set(&mut self, key: &str, value: Value, origin: Origin) -> Result<()> {
+        let setting = find_setting(key).ok_or_else(|| {
+            ConvComError::ConfigError(format!("Unknown setting '{key}' in {origin}"))
+        })?;
+        if setting.global_only && matches!(origin, Origin::Repository(_)) {
+            return Err(ConvComError::ConfigError(format!(
+                "'{key}' can only be set in the global {GLOBAL_CONFIG_FILE}, not in {origin}"
+            )));
+        }
+
+        let value = match (setting.kind, value) {
+            (Kind::Float, Value::Integer(number)) => Value::Float(number as f64),
+            (Kind::String, value @ Value::String(_))
+            | (Kind::Integer, value @ Value::Integer(_))
+            | (Kind::Float, value @ Value::Float(_)) => value,
+            (Kind::List | Kind::Chain, Value::Array(items)) if items.iter().all(Value::is_str) => {
+                Value::Array(items)
+            }
+            _ => {
+                return Err(ConvComError::ConfigError(format!(
+                    "'{key}' in {origin}: {}",
+                    expected(setting)
+                )));
+            }
+        };
+        self.values.insert(setting.key, (value, origin));
+        Ok(())
+    }
+
+    /// The effective value of a setting, if any layer set it
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let Some((value, origin)) = self.values.get(key) else {
+            return Ok(None);
+        };
+        value
+            .clone()
+            .try_into()
+            .map(Some)
+            .map_err(|e| ConvComError::ConfigError(format!("'{key}' from {origin}: {e}")))
+    }
+
+    /// The layer that set a value last
+    pub fn origin(&self, key: &str) -> Option<&Origin> {
+        self.values.get(key).map(|(_, origin)| origin)
+    }
+
+    /// Whether a layer above the defaults set the value
+    pub fn is_set(&self, key: &str) -> bool {
+        self.values
+            .get(key)
+            .is_some_and(|(_, origin)| *origin != Origin::Default)
+    }
+
+    /// Every effective value as TOML, with where it came from; secrets are masked
+    pub fn entries(&self) -> Vec<(&'static str, String, &Origin)> {
+        SETTINGS
+            .iter()
+            .filter_map(|setting| {
+                let (value, origin) = self.values.get(setting.key)?;
+                let shown = if setting.secret {
+                    "\"********\"".to_string()
+                } else {
+                    value.to_string()
+                };
+                Some((setting.key, shown, origin))
+            })
+            .collect()
+    }
+}
+
+/// Parse a value given as text, as environment variables and flags are
+fn parse(setting: &Setting, raw: &str) -> Option<Value> {
+    let list = |items: Vec<String>| Value::Array(items.into_iter().map(Value::from).collect());
+
+    match setting.kind {
+        Kind::String => Some(Value::String(raw.to_string())),
+        Kind::Integer => raw.trim().parse().ok().map(Value::Integer),
+        Kind::Float => raw.trim().parse().ok().map(Value::Float),
+        Kind::List => Some(list(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect(),
+        )),
+        Kind::Chain => Some(list(Config::parse_fallback(raw))),
+    }
+}
+
+/// What a setting takes, for error messages
+fn expected(setting: &Setting) -> &'static str {
+    match setting.kind {
+        Kind::String => "expected a string",
+        Kind::Integer => "expected a whole number",
+        Kind::Float => "expected a number",
+        Kind::List | Kind::Chain => "expected a list of strings",
+    }
+}
+
+/// The global config file in `dir` and the repository file in `root`
+pub fn file_origins(dir: &Path, root: Option<&Path>) -> Vec<Origin> {
+    let mut origins = vec![Origin::Global(dir.join(GLOBAL_CONFIG_FILE))];
+    if let Some(root) = root {
+        origins.push(Origin::Repository(root.join(REPO_CONFIG_FILE)));
+    }
+    origins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layers_from(files: &[(&str, &str)]) -> (tempfile::TempDir, Result<Layers>) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut layers = Layers::with_defaults([
+            ("model", Value::String("llama".to_string())),
+            ("temperature", Value::Float(0.5)),
+        ]);
+        let result = files
+            .iter()
+            .try_for_each(|(name, content)| {
+                let path = dir.path().join(name);
+                std::fs::write(&path, content).unwrap();
+                let origin = match *name {
+                    REPO_CONFIG_FILE => Origin::Repository(path),
+                    _ => Origin::Global(path),
+                };
+                layers.merge_file(origin)
+            })
+            .map(|_| layers);
+        (dir, result)
+    }
+
+    #[test]
+    fn test_later_layers_win() {
+        let (dir, layers) = layers_from(&[
+            (
+                GLOBAL_CONFIG_FILE,
+                "model = \"sonnet\"\ntemperature = 1\ntypes = [\"feat\", \"fix\"]\n\n[ollama]\nnum_ctx = 16384\n",
+            ),
+            (REPO_CONFIG_FILE, "model = \"haiku\"\nscopes = [\"cli\"]\n"),
+        ]);
+        let mut layers = layers.unwrap();
+        layers
+            .merge_arg("temperature", "--temperature", "0.2")
+            .unwrap();
+
+        assert_eq!(layers.get::<String>("model").unwrap().unwrap(), "haiku");
+        assert_eq!(layers.get::<f32>("temperature").unwrap(), Some(0.2));
+        assert_eq!(layers.get::<u32>("ollama.num_ctx").unwrap(), Some(16_384));
+        assert_eq!(
+            layers.get::<Vec<String>>("scopes").unwrap().unwrap(),
+            ["cli"]
+        );
+        assert_eq!(layers.get::<String>("openai.model").unwrap(), None);
+        assert!(layers.is_set("types"));
+
+        let origins: BTreeMap<_, _> = layers
+            .entries()
+            .into_iter()
+            .map(|(key, _, origin)| (key, origin.clone()))
+            .collect();
+        assert_eq!(
+            origins["model"],
+            Origin::Repository(dir.path().join(REPO_CONFIG_FILE))
+        );
+        assert_eq!(
+            origins["types"],
+            Origin::Global(dir.path().join(GLOBAL_CONFIG_FILE))
+        );
+        assert_eq!(origins["temperature"], Origin::Cli("--temperature"));
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let (_dir, layers) = layers_from(&[(GLOBAL_CONFIG_FILE, "modle = \"haiku\"\n")]);
+        assert!(
+            layers
+                .unwrap_err()
+                .to_string()
+                .contains("Unknown setting 'modle'")
+        );
+
+        let (_dir, layers) = layers_from(&[(GLOBAL_CONFIG_FILE, "max_tokens = \"many\"\n")]);
+        assert!(layers.unwrap_err().to_string().contains("whole number"));
+
+        // A cloned repository must not redirect requests carrying the user's keys
+        let (_dir, layers) = layers_from(&[(
+            REPO_CONFIG_FILE,
+            "[openai]\nbase_url = \"https://example.com\"\n",
+        )]);
+        let error = layers.unwrap_err().to_string();
+        assert!(error.contains("can only be set in the global"), "{error}");
+    }
+
+    #[test]
+    fn test_parse_text_values() {
+        let chain = find_setting("fallback").unwrap();
+        assert_eq!(
+            parse(chain, "groq:a -> anthropic:b, ollama:c"),
+            Some(Value::Array(vec![
+                Value::String("groq:a".to_string()),
+                Value::String("anthropic:b".to_string()),
+                Value::String("ollama:c".to_string()),
+            ]))
+        );
+        assert_eq!(parse(find_setting("max_tokens").unwrap(), "lots"), None);
+
+        let mut layers = Layers::default();
+        layers
+            .merge_arg("groq.api_key", "--groq-key", "gsk_secret")
+            .unwrap();
+        let (_, shown, _) = layers.entries().remove(0);
+        assert!(!shown.contains("gsk_secret"));
+    }
+}
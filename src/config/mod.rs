@@ -1 +1,3 @@
+pub mod layers;
+pub mod secrets;
 pub mod settings;
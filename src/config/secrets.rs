@@ -0,0 +1,377 @@
+use crate::config::layers::{Layers, Origin};
+use crate::error::{ConvComError, Result};
+use crate::models::providers::AiProvider;
+use serde::Deserialize;
+use std::fmt;
+use std::process::{Command, Stdio};
+
+/// Service name the keys are stored under in the OS keyring
+pub const KEYRING_SERVICE: &str = "convcom";
+
+/// Providers that take an API key
+pub const KEY_PROVIDERS: &[AiProvider] =
+    &[AiProvider::Groq, AiProvider::Anthropic, AiProvider::OpenAi];
+
+/// An API key, masked in debug output so it never ends up in logs
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    /// The key itself, for the request header
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for ApiKey {
+    fn from(key: String) -> Self {
+        Self(key)
+    }
+}
+
+impl From<&str> for ApiKey {
+    fn from(key: &str) -> Self {
+        Self(key.to_string())
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ApiKey(********)")
+    }
+}
+
+/// Where an API key was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    /// `<provider>.api_key` in a config file or its environment variable
+    Setting(Origin),
+    /// The output of `<provider>.api_key_cmd`
+    Command,
+    /// `git config convcom.<provider>.apiKey`
+    GitConfig,
+    Keyring,
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeySource::Setting(origin) => write!(f, "{origin}"),
+            KeySource::Command => write!(f, "api_key_cmd"),
+            KeySource::GitConfig => write!(f, "git config"),
+            KeySource::Keyring => write!(f, "keyring"),
+        }
+    }
+}
+
+/// Secure storage for API keys, by provider
+pub trait KeyStore {
+    fn get(&self, provider: AiProvider) -> Result<Option<ApiKey>>;
+    fn set(&self, provider: AiProvider, key: &ApiKey) -> Result<()>;
+    /// Remove the key and tell whether there was one
+    fn delete(&self, provider: AiProvider) -> Result<bool>;
+}
+
+/// The OS keyring: Secret Service on Linux, the Keychain on macOS and the
+/// Credential Manager on Windows
+pub struct SystemKeyring;
+
+impl SystemKeyring {
+    fn entry(provider: AiProvider) -> Result<keyring::Entry> {
+        keyring::Entry::new(KEYRING_SERVICE, &provider.to_string()).map_err(keyring_error)
+    }
+}
+
+impl KeyStore for SystemKeyring {
+    fn get(&self, provider: AiProvider) -> Result<Option<ApiKey>> {
+        match Self::entry(provider)?.get_password() {
+            Ok(key) => Ok(Some(ApiKey(key))),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(keyring_error(e)),
+        }
+    }
+
+    fn set(&self, provider: AiProvider, key: &ApiKey) -> Result<()> {
+        Self::entry(provider)?
+            .set_password(key.expose())
+            .map_err(keyring_error)
+    }
+
+    fn delete(&self, provider: AiProvider) -> Result<bool> {
+        match Self::entry(provider)?.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(e) => Err(keyring_error(e)),
+        }
+    }
+}
+
+fn keyring_error(error: keyring::Error) -> ConvComError {
+    ConvComError::ConfigError(format!("Keyring: {error}"))
+}
+
+/// The git config of the current repository, or the global one outside of a repository
+pub fn git_config() -> Option<git2::Config> {
+    match git2::Repository::discover(".") {
+        Ok(repo) => repo.config().ok(),
+        Err(_) => git2::Config::open_default().ok(),
+    }
+}
+
+/// Looks up API keys in the configured sources
+///
+/// The sources are tried in order: the `<provider>.api_key` setting,
+/// `<provider>.api_key_cmd`, git config and the keyring. A keyring that
+/// cannot be reached is only an error when no other source has a key.
+pub struct KeyResolver<'a> {
+    layers: &'a Layers,
+    git_config: Option<git2::Config>,
+    store: Option<&'a dyn KeyStore>,
+}
+
+impl<'a> KeyResolver<'a> {
+    /// Resolve keys from the settings only
+    pub fn new(layers: &'a Layers) -> Self {
+        Self {
+            layers,
+            git_config: None,
+            store: None,
+        }
+    }
+
+    /// Also look up `convcom.<provider>.apiKey` in git config
+    pub fn with_git_config(mut self, git_config: Option<git2::Config>) -> Self {
+        self.git_config = git_config;
+        self
+    }
+
+    /// Also look up keys in `store`
+    pub fn with_store(mut self, store: &'a dyn KeyStore) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// The provider's key, if any source has one
+    pub fn key(&self, provider: AiProvider) -> Result<Option<ApiKey>> {
+        Ok(self.resolve(provider)?.map(|(key, _)| key))
+    }
+
+    /// The provider's key with the source it came from
+    pub fn resolve(&self, provider: AiProvider) -> Result<Option<(ApiKey, KeySource)>> {
+        let setting = format!("{provider}.api_key");
+        if let Some(key) = self.layers.get::<ApiKey>(&setting)?
+            && let Some(origin) = self.layers.origin(&setting)
+        {
+            return Ok(Some((key, KeySource::Setting(origin.clone()))));
+        }
+
+        let command_setting = format!("{provider}.api_key_cmd");
+        if let Some(command) = self.layers.get::<String>(&command_setting)? {
+            let key = run_key_command(&command_setting, &command)?;
+            return Ok(Some((key, KeySource::Command)));
+        }
+
+        if let Some(git_config) = &self.git_config
+            && let Ok(key) = git_config.get_string(&format!("convcom.{provider}.apiKey"))
+            && !key.trim().is_empty()
+        {
+            return Ok(Some((ApiKey(key.trim().to_string()), KeySource::GitConfig)));
+        }
+
+        // Nothing else has a key, so a keyring that cannot be read is reported
+        if let Some(store) = self.store
+            && let Some(key) = store.get(provider)?
+        {
+            return Ok(Some((key, KeySource::Keyring)));
+        }
+
+        Ok(None)
+    }
+}
+
+/// Run a password manager command and take the first line it prints as the key
+///
+/// The command reads from the terminal and reports to it directly, so it
+/// can ask for a passphrase; only its output is captured.
+fn run_key_command(setting: &str, command: &str) -> Result<ApiKey> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let output = shell
+        .arg(command)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| ConvComError::ConfigError(format!("Could not run {setting}: {e}")))?;
+
+    // The output is never included in errors, as it may hold part of a key
+    if !output.status.success() {
+        return Err(ConvComError::ConfigError(format!(
+            "{setting} failed with {}",
+            output.status
+        )));
+    }
+    String::from_utf8(output.stdout)
+        .ok()
+        .and_then(|stdout| stdout.lines().next().map(|line| line.trim().to_string()))
+        .filter(|key| !key.is_empty())
+        .map(ApiKey)
+        .ok_or_else(|| {
+            ConvComError::ConfigError(format!("{setting} printed no key on its first line"))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+    use toml::Value;
+
+    /// An in-memory keyring standing in for the Secret Service
+    #[derive(Default)]
+    struct MemoryKeyring {
+        keys: Mutex<HashMap<AiProvider, String>>,
+        /// Fail every call, like a machine without a keyring daemon
+        unavailable: bool,
+    }
+
+    impl KeyStore for MemoryKeyring {
+        fn get(&self, provider: AiProvider) -> Result<Option<ApiKey>> {
+            if self.unavailable {
+                return Err(ConvComError::ConfigError("Keyring: no daemon".to_string()));
+            }
+            Ok(self
+                .keys
+                .lock()
+                .unwrap()
+                .get(&provider)
+                .cloned()
+                .map(ApiKey))
+        }
+
+        fn set(&self, provider: AiProvider, key: &ApiKey) -> Result<()> {
+            if self.unavailable {
+                return Err(ConvComError::ConfigError("Keyring: no daemon".to_string()));
+            }
+            self.keys
+                .lock()
+                .unwrap()
+                .insert(provider, key.expose().to_string());
+            Ok(())
+        }
+
+        fn delete(&self, provider: AiProvider) -> Result<bool> {
+            Ok(self.keys.lock().unwrap().remove(&provider).is_some())
+        }
+    }
+
+    fn settings(values: &[(&'static str, &str)]) -> Layers {
+        Layers::with_defaults(
+            values
+                .iter()
+                .map(|(key, value)| (*key, Value::String(value.to_string()))),
+        )
+    }
+
+    #[test]
+    fn test_key_sources_in_order() {
+        let keyring = MemoryKeyring::default();
+        keyring
+            .set(AiProvider::Groq, &"from-keyring".into())
+            .unwrap();
+        keyring
+            .set(AiProvider::Anthropic, &"from-keyring".into())
+            .unwrap();
+        keyring
+            .set(AiProvider::OpenAi, &"from-keyring".into())
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut git_config = git2::Config::open(&dir.path().join("gitconfig")).unwrap();
+        git_config
+            .set_str("convcom.anthropic.apiKey", "from-git")
+            .unwrap();
+        git_config
+            .set_str("convcom.openai.apiKey", "from-git")
+            .unwrap();
+
+        let layers = settings(&[
+            ("groq.api_key", "from-setting"),
+            ("anthropic.api_key_cmd", "printf 'from-cmd\\nrest\\n'"),
+        ]);
+        let resolver = KeyResolver::new(&layers)
+            .with_git_config(Some(git_config))
+            .with_store(&keyring);
+
+        let resolved = |provider| {
+            let (key, source) = resolver.resolve(provider).unwrap().unwrap();
+            (key.expose().to_string(), source)
+        };
+        assert_eq!(
+            resolved(AiProvider::Groq),
+            (
+                "from-setting".to_string(),
+                KeySource::Setting(Origin::Default)
+            )
+        );
+        assert_eq!(
+            resolved(AiProvider::Anthropic),
+            ("from-cmd".to_string(), KeySource::Command)
+        );
+        assert_eq!(
+            resolved(AiProvider::OpenAi),
+            ("from-git".to_string(), KeySource::GitConfig)
+        );
+
+        keyring.delete(AiProvider::Groq).unwrap();
+        let layers = settings(&[]);
+        let resolver = KeyResolver::new(&layers).with_store(&keyring);
+        assert_eq!(
+            resolver.resolve(AiProvider::OpenAi).unwrap().unwrap().1,
+            KeySource::Keyring
+        );
+        assert_eq!(resolver.key(AiProvider::Groq).unwrap(), None);
+    }
+
+    #[test]
+    fn test_unavailable_keyring_is_reported() {
+        let keyring = MemoryKeyring {
+            unavailable: true,
+            ..MemoryKeyring::default()
+        };
+        let layers = settings(&[("groq.api_key", "from-setting")]);
+        let resolver = KeyResolver::new(&layers).with_store(&keyring);
+
+        // Only needed when no other source has a key
+        assert!(resolver.key(AiProvider::Groq).unwrap().is_some());
+        let error = resolver.key(AiProvider::Anthropic).unwrap_err().to_string();
+        assert!(error.contains("Keyring: no daemon"), "{error}");
+        assert!(keyring.set(AiProvider::Groq, &"key".into()).is_err());
+    }
+
+    #[test]
+    fn test_keys_stay_out_of_errors_and_debug_output() {
+        let layers = settings(&[
+            ("groq.api_key_cmd", "echo sk-leaked; exit 3"),
+            ("anthropic.api_key_cmd", "true"),
+        ]);
+        let resolver = KeyResolver::new(&layers);
+
+        let failed = resolver.key(AiProvider::Groq).unwrap_err().to_string();
+        assert!(failed.contains("groq.api_key_cmd failed"));
+        assert!(!failed.contains("sk-leaked"));
+        let empty = resolver.key(AiProvider::Anthropic).unwrap_err().to_string();
+        assert!(empty.contains("printed no key"));
+
+        let key = ApiKey::from("sk-secret");
+        assert_eq!(format!("{:?}", Some(&key)), "Some(ApiKey(********))");
+    }
+}
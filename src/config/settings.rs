@@ -1,18 +1,227 @@
+use crate::config::layers::{Layers, file_origins};
+use crate::config::secrets::{ApiKey, KEY_PROVIDERS, KeyResolver, SystemKeyring, git_config};
 use crate::error::{ConvComError, Result};
+use crate::models::ai::MAX_OUTPUT_TOKENS;
+use crate::models::providers::{AiProvider, ModelName};
+use crate::models::registry::ModelEntry;
+use crate::services::lint::{DEFAULT_MAX_HEADER_LENGTH, DEFAULT_TYPES, LintRules};
+use crate::services::retry::RetryPolicy;
+use git2::Repository;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml::Value;
+
+/// Base URL of the OpenAI API, used when no other endpoint is configured
+pub const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// Model requested from an OpenAI-compatible endpoint when none is configured
+pub const OPENAI_DEFAULT_MODEL: &str = "gpt-4o-mini";
+
+/// Seconds an OpenAI-compatible endpoint may take when none is configured,
+/// as long as Ollama gets, since local servers may load the model first
+pub const OPENAI_DEFAULT_TIMEOUT_SECS: u64 = 300;
+
+/// Address of a local Ollama daemon, used when OLLAMA_HOST is not set
+pub const OLLAMA_DEFAULT_HOST: &str = "http://localhost:11434";
+
+/// Model requested from Ollama when none is configured
+pub const OLLAMA_DEFAULT_MODEL: &str = "qwen2.5-coder";
+
+/// Context window requested from Ollama when none is configured
+pub const OLLAMA_DEFAULT_NUM_CTX: u32 = 8_192;
+
+/// Sampling temperature used when none is configured
+pub const DEFAULT_TEMPERATURE: f32 = 0.5;
 
 /// Application configuration
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub groq_api_key: Option<String>,
-    pub anthropic_api_key: Option<String>,
+    pub groq_api_key: Option<ApiKey>,
+    pub anthropic_api_key: Option<ApiKey>,
+    pub openai: Option<OpenAiSettings>,
+    /// Ollama needs no key, so it is always available
+    pub ollama: OllamaSettings,
+    /// Models described in `models.json`, by the name to use them with
+    pub models: BTreeMap<String, ModelEntry>,
+    /// Model used when none is given on the command line
+    pub model: String,
+    /// Models to try in order when the selected one is unavailable
+    pub fallback: Vec<String>,
+    /// Sampling temperature sent with every request
+    pub temperature: f32,
+    /// Most tokens to request for a message
+    pub max_tokens: u32,
+    /// How requests are retried after rate limits and server errors
+    pub retry: RetryPolicy,
+    /// Rules generated and linted messages must follow
+    pub lint: LintRules,
+    /// Gitignore-style patterns of files to summarise instead of show
+    pub exclude: Vec<String>,
+}
+
+/// Connection settings for an OpenAI-compatible chat completions endpoint
+///
+/// Covers OpenAI itself and local servers such as vLLM, llama.cpp or LM Studio.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenAiSettings {
+    /// URL the `/chat/completions` path is appended to
+    pub base_url: String,
+    /// Key sent in `auth_header`; local servers often need none
+    pub api_key: Option<ApiKey>,
+    /// Header carrying the key, with a `Bearer` prefix for `Authorization`
+    pub auth_header: String,
+    /// Model name sent to the endpoint
+    pub model: String,
+    /// Limit for a whole answer, or between two pieces of a streamed one
+    pub timeout: Duration,
+}
+
+impl OpenAiSettings {
+    /// Settings for the OpenAI API with the given key
+    pub fn openai(api_key: ApiKey) -> Self {
+        Self {
+            base_url: OPENAI_BASE_URL.to_string(),
+            api_key: Some(api_key),
+            auth_header: "Authorization".to_string(),
+            model: OPENAI_DEFAULT_MODEL.to_string(),
+            timeout: Duration::from_secs(OPENAI_DEFAULT_TIMEOUT_SECS),
+        }
+    }
+
+    /// Read the `openai.*` settings, or None if neither a base URL nor a key is set
+    fn from_layers(layers: &Layers, api_key: Option<ApiKey>) -> Result<Option<Self>> {
+        if !layers.is_set("openai.base_url") && api_key.is_none() {
+            return Ok(None);
+        }
+        let timeout: u64 = required(layers, "openai.timeout")?;
+        if timeout == 0 {
+            return Err(ConvComError::ConfigError(
+                "openai.timeout must be at least 1".to_string(),
+            ));
+        }
+
+        Ok(Some(Self {
+            base_url: required(layers, "openai.base_url")?,
+            api_key,
+            auth_header: required(layers, "openai.auth_header")?,
+            model: required(layers, "openai.model")?,
+            timeout: Duration::from_secs(timeout),
+        }))
+    }
+}
+
+/// Connection settings for a local Ollama daemon
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OllamaSettings {
+    /// Base URL of the daemon
+    pub host: String,
+    /// Model name sent to the daemon
+    pub model: String,
+    /// How long the daemon keeps the model loaded, like `5m` or `-1`
+    pub keep_alive: Option<String>,
+    /// Context window size the model is loaded with
+    pub num_ctx: u32,
+}
+
+impl Default for OllamaSettings {
+    fn default() -> Self {
+        Self {
+            host: OLLAMA_DEFAULT_HOST.to_string(),
+            model: OLLAMA_DEFAULT_MODEL.to_string(),
+            keep_alive: None,
+            num_ctx: OLLAMA_DEFAULT_NUM_CTX,
+        }
+    }
+}
+
+impl OllamaSettings {
+    /// Read the `ollama.*` settings
+    ///
+    /// The host may leave out the scheme, as the Ollama CLI allows.
+    fn from_layers(layers: &Layers) -> Result<Self> {
+        let host: String = required(layers, "ollama.host")?;
+        let host = if host.contains("://") {
+            host
+        } else {
+            format!("http://{host}")
+        };
+
+        Ok(Self {
+            host,
+            model: required(layers, "ollama.model")?,
+            keep_alive: layers.get("ollama.keep_alive")?,
+            num_ctx: required(layers, "ollama.num_ctx")?,
+        })
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::from_layers(&Self::default_layers()).expect("the defaults are valid")
+    }
 }
 
 impl Config {
-    /// Load configuration from environment and config files
+    /// Load configuration from environment and config files, with every API key
+    #[allow(dead_code)]
     pub fn load() -> Result<Self> {
-        // Try to load from the config file first
+        let (mut config, layers) = Self::load_with(|_| Ok(()))?;
+        for &provider in KEY_PROVIDERS {
+            config.resolve_key(&layers, provider)?;
+        }
+        Ok(config)
+    }
+
+    /// Load the configuration with extra values, such as command line flags, on top
+    ///
+    /// Only API keys in the settings are read; `resolve_key` looks up the
+    /// others for the providers that are used. Returns the layers too, to
+    /// tell where each value came from.
+    pub fn load_with(apply: impl FnOnce(&mut Layers) -> Result<()>) -> Result<(Self, Layers)> {
+        let layers = Self::load_layers(apply)?;
+        let config = Self {
+            models: Self::load_models(&Self::get_config_dir()?.join("models.json"))?,
+            ..Self::from_layers(&layers)?
+        };
+        Ok((config, layers))
+    }
+
+    /// Look up a provider's API key if the settings hold none
+    ///
+    /// The key is looked up with its `api_key_cmd`, in git config and in the
+    /// OS keyring, so a password manager only runs for providers in use.
+    pub fn resolve_key(&mut self, layers: &Layers, provider: AiProvider) -> Result<()> {
+        let key = || {
+            KeyResolver::new(layers)
+                .with_git_config(git_config())
+                .with_store(&SystemKeyring)
+                .key(provider)
+        };
+        match provider {
+            AiProvider::Groq if self.groq_api_key.is_none() => self.groq_api_key = key()?,
+            AiProvider::Anthropic if self.anthropic_api_key.is_none() => {
+                self.anthropic_api_key = key()?
+            }
+            AiProvider::OpenAi
+                if self
+                    .openai
+                    .as_ref()
+                    .is_none_or(|settings| settings.api_key.is_none()) =>
+            {
+                self.openai = OpenAiSettings::from_layers(layers, key()?)?
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Apply the config files, the environment and `apply` over the defaults
+    ///
+    /// Enough for commands that need no API keys, so no key command runs.
+    pub fn load_layers(apply: impl FnOnce(&mut Layers) -> Result<()>) -> Result<Layers> {
         let config_dir = Self::get_config_dir()?;
         let env_file = config_dir.join(".env.commits");
 
@@ -22,29 +231,124 @@ impl Config {
             })?;
         }
 
-        // Get API keys from environment (both are optional)
-        let groq_api_key = env::var("GROQ_API_KEY").ok().filter(|key| !key.is_empty());
-
-        let anthropic_api_key = env::var("ANTHROPIC_API_KEY")
+        let root = Repository::discover(".")
             .ok()
-            .filter(|key| !key.is_empty());
+            .and_then(|repo| repo.workdir().map(Path::to_path_buf));
+        let mut layers = Self::default_layers();
+        for origin in file_origins(&config_dir, root.as_deref()) {
+            layers.merge_file(origin)?;
+        }
+        layers.merge_env()?;
+        apply(&mut layers)?;
+        Ok(layers)
+    }
 
-        // At least one API key must be provided
-        if groq_api_key.is_none() && anthropic_api_key.is_none() {
-            return Err(ConvComError::ConfigError(
-                "At least one API key must be provided. Set GROQ_API_KEY and/or ANTHROPIC_API_KEY."
-                    .to_string(),
-            ));
+    /// The built-in value of every setting that has one
+    fn default_layers() -> Layers {
+        let strings =
+            |items: &[&str]| Value::Array(items.iter().map(|item| Value::from(*item)).collect());
+
+        Layers::with_defaults([
+            ("model", Value::from(ModelName::default().as_str())),
+            ("fallback", strings(&[])),
+            ("temperature", Value::from(f64::from(DEFAULT_TEMPERATURE))),
+            ("max_tokens", Value::from(MAX_OUTPUT_TOKENS)),
+            (
+                "max_attempts",
+                Value::from(RetryPolicy::default().max_attempts),
+            ),
+            ("types", strings(DEFAULT_TYPES)),
+            ("scopes", strings(&[])),
+            (
+                "max_header_length",
+                Value::from(DEFAULT_MAX_HEADER_LENGTH as i64),
+            ),
+            ("exclude", strings(&[])),
+            ("openai.base_url", Value::from(OPENAI_BASE_URL)),
+            ("openai.auth_header", Value::from("Authorization")),
+            ("openai.model", Value::from(OPENAI_DEFAULT_MODEL)),
+            (
+                "openai.timeout",
+                Value::from(OPENAI_DEFAULT_TIMEOUT_SECS as i64),
+            ),
+            ("ollama.host", Value::from(OLLAMA_DEFAULT_HOST)),
+            ("ollama.model", Value::from(OLLAMA_DEFAULT_MODEL)),
+            ("ollama.num_ctx", Value::from(OLLAMA_DEFAULT_NUM_CTX)),
+        ])
+    }
+
+    /// Build the configuration from the effective values, with only the keys set in them
+    pub fn from_layers(layers: &Layers) -> Result<Self> {
+        let temperature: f32 = required(layers, "temperature")?;
+        if !(0.0..=2.0).contains(&temperature) {
+            return Err(ConvComError::ConfigError(format!(
+                "temperature must be between 0 and 2, not {temperature}"
+            )));
+        }
+        let positive = |key: &str| -> Result<u32> {
+            let value: u32 = required(layers, key)?;
+            if value == 0 {
+                return Err(ConvComError::ConfigError(format!(
+                    "{key} must be at least 1"
+                )));
+            }
+            Ok(value)
+        };
+
+        Ok(Self {
+            groq_api_key: layers.get("groq.api_key")?,
+            anthropic_api_key: layers.get("anthropic.api_key")?,
+            openai: OpenAiSettings::from_layers(layers, layers.get("openai.api_key")?)?,
+            ollama: OllamaSettings::from_layers(layers)?,
+            models: BTreeMap::new(),
+            model: required(layers, "model")?,
+            fallback: required(layers, "fallback")?,
+            temperature,
+            max_tokens: positive("max_tokens")?,
+            retry: RetryPolicy {
+                max_attempts: positive("max_attempts")?,
+                ..RetryPolicy::default()
+            },
+            lint: LintRules {
+                types: required(layers, "types")?,
+                scopes: required(layers, "scopes")?,
+                max_header_length: required(layers, "max_header_length")?,
+            },
+            exclude: required(layers, "exclude")?,
+        })
+    }
+
+    /// Split a fallback chain like `groq:a -> anthropic:b, ollama:c` into model names
+    pub fn parse_fallback(chain: &str) -> Vec<String> {
+        chain
+            .split(',')
+            .flat_map(|part| part.split("->"))
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Read the user's model registry, if there is one
+    fn load_models(path: &Path) -> Result<BTreeMap<String, ModelEntry>> {
+        if !path.exists() {
+            return Ok(BTreeMap::new());
         }
 
-        Ok(Config {
-            groq_api_key,
-            anthropic_api_key,
+        let content = std::fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| {
+            ConvComError::ConfigError(format!("Failed to load {}: {e}", path.display()))
         })
     }
 
-    /// Get the configuration directory path
-    fn get_config_dir() -> Result<PathBuf> {
+    /// Get the configuration directory path, under `XDG_CONFIG_HOME` when it is set
+    pub fn get_config_dir() -> Result<PathBuf> {
+        if let Some(config_home) = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            && config_home.is_absolute()
+        {
+            return Ok(config_home.join("conv_commit_ai"));
+        }
+
         let home_dir = dirs::home_dir().ok_or_else(|| {
             ConvComError::ConfigError("Could not determine home directory".to_string())
         })?;
@@ -52,3 +356,70 @@ impl Config {
         Ok(home_dir.join(".config").join("conv_commit_ai"))
     }
 }
+
+/// A value that has a default, so some layer always sets it
+fn required<T: DeserializeOwned>(layers: &Layers, key: &str) -> Result<T> {
+    layers
+        .get(key)?
+        .ok_or_else(|| ConvComError::ConfigError(format!("No value for '{key}'")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fallback() {
+        assert_eq!(
+            Config::parse_fallback(
+                "groq:llama-3.3-70b-versatile -> anthropic:claude-3-5-haiku-20241022,ollama:qwen3"
+            ),
+            [
+                "groq:llama-3.3-70b-versatile",
+                "anthropic:claude-3-5-haiku-20241022",
+                "ollama:qwen3"
+            ]
+        );
+        assert!(Config::parse_fallback(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_keys_are_resolved_per_provider() {
+        let mut layers = Config::default_layers();
+        layers
+            .merge_arg("groq.api_key", "--groq-key", "gsk_key")
+            .unwrap();
+        layers
+            .merge_arg("anthropic.api_key_cmd", "--anthropic-key-cmd", "exit 1")
+            .unwrap();
+        let mut config = Config::from_layers(&layers).unwrap();
+
+        // The broken command of another provider does not get in the way
+        config.resolve_key(&layers, AiProvider::Groq).unwrap();
+        config.resolve_key(&layers, AiProvider::Ollama).unwrap();
+        assert!(config.groq_api_key.is_some());
+        assert!(config.resolve_key(&layers, AiProvider::Anthropic).is_err());
+    }
+
+    #[test]
+    fn test_openai_timeout() {
+        let mut layers = Config::default_layers();
+        let openai = OpenAiSettings::from_layers(&layers, Some("key".into()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(openai.timeout, Duration::from_secs(300));
+
+        layers
+            .merge_arg("openai.timeout", "--openai-timeout", "900")
+            .unwrap();
+        let openai = OpenAiSettings::from_layers(&layers, Some("key".into()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(openai.timeout, Duration::from_secs(900));
+
+        layers
+            .merge_arg("openai.timeout", "--openai-timeout", "0")
+            .unwrap();
+        assert!(OpenAiSettings::from_layers(&layers, Some("key".into())).is_err());
+    }
+}
@@ -20,6 +20,10 @@ pub enum ConvComError {
     #[error("API request failed: {0}")]
     ApiRequestError(String),
 
+    /// The request timed out or the server could not be reached
+    #[error("Network error: {0}")]
+    NetworkError(String),
+
     /// API returned an error response
     #[error("API error {status_code}: {message}")]
     ApiError { status_code: u16, message: String },
@@ -48,11 +52,84 @@ pub enum ConvComError {
     #[error("No staged files found")]
     NoStagedFilesError,
 
+    /// A revision range or commit contains no changes
+    #[error("No changes found {0}")]
+    NoChangesError(String),
+
+    /// A patch could not be parsed
+    #[error("Invalid patch: {0}")]
+    PatchError(String),
+
+    /// A commit message does not follow the Conventional Commits format
+    #[error("Invalid commit message: {0}")]
+    CommitMessageError(String),
+
+    /// A repository hook exited with a non-zero status
+    #[error("The {0} hook rejected the commit")]
+    HookRejected(String),
+
     /// Not in a git repository
     #[error("Not in a git repository")]
     NotGitRepoError,
 }
 
+impl ConvComError {
+    /// Stable identifier of the error kind for machine-readable output
+    pub fn code(&self) -> &'static str {
+        match self {
+            ConvComError::ConfigError(_) => "config_error",
+            ConvComError::GitError(_) => "git_error",
+            ConvComError::HttpClientError(_) => "http_client_error",
+            ConvComError::ApiRequestError(_) => "api_request_error",
+            ConvComError::NetworkError(_) => "network_error",
+            ConvComError::ApiError { .. } => "api_error",
+            ConvComError::ResponseParseError(_) => "response_parse_error",
+            ConvComError::EmptyResponseError => "empty_response",
+            ConvComError::EnvVarError(_) => "env_var_error",
+            ConvComError::IoError(_) => "io_error",
+            ConvComError::TemplateError(_) => "template_error",
+            ConvComError::NoStagedFilesError => "no_staged_files",
+            ConvComError::NoChangesError(_) => "no_changes",
+            ConvComError::PatchError(_) => "invalid_patch",
+            ConvComError::CommitMessageError(_) => "invalid_commit_message",
+            ConvComError::HookRejected(_) => "hook_rejected",
+            ConvComError::NotGitRepoError => "not_git_repo",
+        }
+    }
+
+    /// Whether another attempt, possibly with another model, may succeed
+    ///
+    /// True for rate limits, timeouts, overloaded or unavailable servers and
+    /// unreachable endpoints; never for rejected keys, bad requests or
+    /// internal server errors that would fail the same way again.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ConvComError::ApiError {
+                status_code,
+                message,
+            } => {
+                matches!(status_code, 408 | 429 | 502 | 503 | 504 | 529)
+                    || message.contains("overloaded_error")
+            }
+            ConvComError::NetworkError(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ConvComError {
+    /// Timeouts, failed connections and connections dropped while sending
+    /// are network errors; anything else, such as an invalid URL or header,
+    /// is a request error
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() || error.is_connect() || error.is_request() {
+            ConvComError::NetworkError(error.to_string())
+        } else {
+            ConvComError::ApiRequestError(error.to_string())
+        }
+    }
+}
+
 impl From<std::io::Error> for ConvComError {
     fn from(error: std::io::Error) -> Self {
         ConvComError::IoError(error.to_string())
@@ -3,9 +3,15 @@ pub mod error;
 pub mod models;
 pub mod prompt;
 pub mod services;
+pub mod ui;
 
-pub use config::settings::Config;
+pub use config::settings::{Config, OpenAiSettings};
 pub use error::{ConvComError, Result};
+pub use models::commit::{ConventionalCommit, Footer, FooterSeparator};
+pub use models::diff::{DiffStyle, FileChange};
 pub use models::providers::{AiProvider, ModelName};
-pub use prompt::builder::PromptBuilder;
-pub use services::{ai_service::AiService, git_service::GitService};
+pub use prompt::{builder::PromptBuilder, diff_renderer::DiffRenderer};
+pub use services::{
+    ai_service::AiService,
+    git_service::{DiffSource, GitService},
+};
@@ -1,130 +1,1043 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 mod config;
 mod error;
 mod models;
 mod prompt;
 mod services;
+mod ui;
 
+use config::layers::Layers;
+use config::secrets::{ApiKey, KEY_PROVIDERS, KeyResolver, KeyStore, SystemKeyring, git_config};
 use config::settings::Config;
-use models::providers::ModelName;
+use error::ConvComError;
+use models::ai::TokenUsage;
+use models::commit::ConventionalCommit;
+use models::diff::{DiffStyle, FileChange};
+use models::providers::{AiProvider, Model};
+use models::registry::ModelRegistry;
 use prompt::builder::PromptBuilder;
-use services::{ai_service::AiService, git_service::GitService};
+use prompt::diff_renderer::{DiffRenderer, Truncation};
+use services::{
+    ai_service::AiService,
+    exclusions::ExclusionRules,
+    git_service::{DEFAULT_CONTEXT_LINES, DiffSource, GitService},
+    hooks::{self, HookInstall},
+    lint::{LintRules, extract_message, lint, read_message, validate},
+    patch::load_patch,
+    providers::OllamaProvider,
+};
+use std::io::{BufRead, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use ui::{
+    editor::resolve_editor,
+    json::{MessageReport, error_report},
+    preview::LivePreview,
+    review::{Candidate, Review, ReviewStep},
+};
 
 #[derive(Parser)]
 #[command(name = "convcom")]
 #[command(about = "Generate conventional commit messages using AI")]
 #[command(version = "0.1.0")]
 struct Cli {
-    /// The AI model to use
-    #[arg(long, value_enum, default_value_t = ModelName::default())]
-    model: ModelName,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// The AI model to use: a name listed by `convcom models` or provider:model
+    #[arg(long, global = true)]
+    model: Option<String>,
+
+    /// Sampling temperature between 0 and 2
+    #[arg(long, global = true)]
+    temperature: Option<f32>,
+
+    /// Most tokens to request for the message
+    #[arg(long, global = true)]
+    max_tokens: Option<u32>,
 
     /// Focus message to guide AI attention
     #[arg(long, short)]
     focus: Option<String>,
+
+    /// How the changes are rendered into the prompt
+    #[arg(long, value_enum, default_value_t = DiffStyle::default())]
+    diff_format: DiffStyle,
+
+    /// Number of unchanged context lines around each change (unified format)
+    #[arg(long, default_value_t = DEFAULT_CONTEXT_LINES)]
+    context: u32,
+
+    /// Describe the changes from this revision to --to instead of the staged changes
+    #[arg(long, value_name = "REV")]
+    from: Option<String>,
+
+    /// End of the range started by --from (defaults to HEAD)
+    #[arg(long, value_name = "REV", requires = "from")]
+    to: Option<String>,
+
+    /// Describe an existing commit against its parent, e.g. to reword it
+    #[arg(long, value_name = "REV", conflicts_with = "from")]
+    commit: Option<String>,
+
+    /// Describe all tracked working tree changes against HEAD, staged or not
+    #[arg(long, conflicts_with_all = ["from", "commit", "patch"])]
+    all: bool,
+
+    /// Describe only the working tree changes that are not staged yet
+    #[arg(long, conflicts_with_all = ["from", "commit", "patch", "all"])]
+    unstaged: bool,
+
+    /// Commit the staged changes with the generated message, running hooks and signing as configured
+    #[arg(long, conflicts_with_all = ["from", "commit", "patch", "all", "unstaged"])]
+    create_commit: bool,
+
+    /// Print the message without the interactive review, even in a terminal
+    #[arg(long)]
+    no_interactive: bool,
+
+    /// Print the message as text, or as JSON with metadata for tools (implies --no-interactive)
+    #[arg(long, value_enum, default_value_t = OutputFormat::default())]
+    format: OutputFormat,
+
+    /// How often to ask the model to fix a message that breaks the Conventional Commits rules
+    #[arg(long, default_value_t = DEFAULT_RETRIES)]
+    retries: u32,
+
+    /// Describe a unified or git-format patch read from a file ('-' for stdin); no repository needed
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["from", "commit"])]
+    patch: Option<String>,
+}
+
+/// How the generated message is printed
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// The plain commit message
+    #[default]
+    Text,
+    /// The parsed message and metadata as a JSON object
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Manage git hooks that generate or check messages for `git commit`
+    Hook {
+        #[command(subcommand)]
+        action: HookAction,
+    },
+    /// Check a commit message against the Conventional Commits rules
+    Lint {
+        /// File with the message, e.g. .git/COMMIT_EDITMSG ('-' for stdin)
+        #[arg(default_value = "-")]
+        file: String,
+
+        #[command(flatten)]
+        rules: LintArgs,
+    },
+    /// List the known models, including those installed in the local Ollama daemon
+    Models,
+    /// Inspect the configuration from config.toml, .convcom.toml, the environment and flags
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Manage API keys in the OS keyring
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuthAction {
+    /// Store a provider's API key in the keyring, read from the terminal or stdin
+    Login {
+        #[arg(value_enum)]
+        provider: AiProvider,
+    },
+    /// Remove a provider's API key from the keyring
+    Logout {
+        #[arg(value_enum)]
+        provider: AiProvider,
+    },
+    /// Show where each provider's API key comes from, without showing the keys
+    Status,
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Print every effective setting; API keys are masked
+    Show {
+        /// Show which file, variable or flag set each value
+        #[arg(long)]
+        origin: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum HookAction {
+    /// Install a hook, keeping any existing hook of the same name
+    Install {
+        #[arg(value_enum, default_value_t = HookKind::default())]
+        hook: HookKind,
+    },
+    /// Remove a hook and restore the hook it replaced
+    Uninstall {
+        #[arg(value_enum, default_value_t = HookKind::default())]
+        hook: HookKind,
+    },
+    /// Fill in the commit message file (run by the installed hook)
+    #[command(hide = true)]
+    PrepareCommitMsg {
+        file: PathBuf,
+        source: Option<String>,
+        commit: Option<String>,
+    },
+    /// Check the commit message file (run by the installed hook)
+    #[command(hide = true)]
+    CommitMsg { file: String },
+}
+
+/// Hooks convcom can install
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum HookKind {
+    /// Fill in a generated message before the editor opens
+    #[default]
+    PrepareCommitMsg,
+    /// Reject messages that break the Conventional Commits rules
+    CommitMsg,
+}
+
+impl HookKind {
+    /// File name of the hook
+    fn name(self) -> &'static str {
+        match self {
+            HookKind::PrepareCommitMsg => hooks::PREPARE_COMMIT_MSG,
+            HookKind::CommitMsg => hooks::COMMIT_MSG,
+        }
+    }
+}
+
+#[derive(Args)]
+struct LintArgs {
+    /// Allowed types, comma separated (defaults to the common Conventional Commits types)
+    #[arg(long, value_delimiter = ',')]
+    types: Vec<String>,
+
+    /// Allowed scopes, comma separated (any scope when not given)
+    #[arg(long, value_delimiter = ',')]
+    scopes: Vec<String>,
+
+    /// Maximum length of the header line (defaults to the `max_header_length` setting)
+    #[arg(long)]
+    max_header_length: Option<usize>,
+}
+
+impl LintArgs {
+    /// The configured lint rules with the flags on top
+    fn rules(&self, configured: LintRules) -> LintRules {
+        let mut rules = configured;
+        if !self.types.is_empty() {
+            rules.types = self.types.clone();
+        }
+        if !self.scopes.is_empty() {
+            rules.scopes = self.scopes.clone();
+        }
+        if let Some(max_header_length) = self.max_header_length {
+            rules.max_header_length = max_header_length;
+        }
+        rules
+    }
+}
+
+impl Cli {
+    /// Load the layered configuration with the flags that override settings on top
+    ///
+    /// API keys are looked up for the selected model and its fallbacks only.
+    /// A fallback whose key cannot be read is left out with a warning.
+    fn load_config(&self) -> error::Result<(Config, Layers)> {
+        let (mut config, layers) = Config::load_with(|layers| self.apply_flags(layers))?;
+        let registry = ModelRegistry::new(&config);
+        if let Ok(model) = registry.resolve(&config.model) {
+            config.resolve_key(&layers, model.provider)?;
+        }
+        for name in config.fallback.clone() {
+            if let Ok(model) = registry.resolve(&name)
+                && let Err(e) = config.resolve_key(&layers, model.provider)
+            {
+                eprintln!("Warning: no key for fallback model '{name}': {e}");
+            }
+        }
+        Ok((config, layers))
+    }
+
+    /// Load the configuration without looking up API keys, for commands that need none
+    fn load_settings(&self) -> error::Result<(Config, Layers)> {
+        let layers = Config::load_layers(|layers| self.apply_flags(layers))?;
+        Ok((Config::from_layers(&layers)?, layers))
+    }
+
+    fn apply_flags(&self, layers: &mut Layers) -> error::Result<()> {
+        if let Some(model) = &self.model {
+            layers.merge_arg("model", "--model", model)?;
+        }
+        if let Some(temperature) = self.temperature {
+            layers.merge_arg("temperature", "--temperature", &temperature.to_string())?;
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            layers.merge_arg("max_tokens", "--max-tokens", &max_tokens.to_string())?;
+        }
+        Ok(())
+    }
+
+    /// Which changes to describe, based on the revision flags
+    fn diff_source(&self) -> DiffSource {
+        match (&self.commit, &self.from) {
+            (Some(rev), _) => DiffSource::Commit(rev.clone()),
+            (None, Some(from)) => DiffSource::Range {
+                from: from.clone(),
+                to: self.to.clone().unwrap_or_else(|| "HEAD".to_string()),
+            },
+            (None, None) if self.all => DiffSource::All,
+            (None, None) if self.unstaged => DiffSource::Unstaged,
+            (None, None) => DiffSource::Staged,
+        }
+    }
+}
+
+/// Default number of correction requests for an invalid message
+const DEFAULT_RETRIES: u32 = 2;
+
+/// A generated message and what it took to get it
+struct Generation {
+    commit: ConventionalCommit,
+    /// The model that wrote the message
+    model: Model,
+    /// Models that could not answer, with their errors
+    fallbacks: Vec<(Model, ConvComError)>,
+    /// Tokens summed over all attempts, if the provider reported them
+    usage: Option<TokenUsage>,
+    attempts: u32,
+    latency: Duration,
+    truncations: Vec<Truncation>,
+}
+
+/// Render the changes to fit every model's context window and generate a valid message
+///
+/// The models are tried in order until one answers. A message that breaks
+/// the Conventional Commits rules is sent back to the model that wrote it
+/// with the violations, up to `retries` times. With `live`, each answer is
+/// shown on a terminal while it is written.
+#[allow(clippy::too_many_arguments)]
+async fn generate(
+    ai_service: &AiService,
+    prompt_builder: &PromptBuilder,
+    changes: &[FileChange],
+    diff_format: DiffStyle,
+    models: &[Model],
+    focus: Option<&str>,
+    retries: u32,
+    live: bool,
+) -> Result<Generation> {
+    let started = Instant::now();
+    let token_budget = models
+        .iter()
+        .map(|model| prompt_builder.diff_token_budget(model, focus))
+        .min()
+        .unwrap_or_default();
+    let renderer = DiffRenderer::new(diff_format).with_token_budget(token_budget);
+    let (diff_content, truncations) = renderer.render_with_truncations(changes);
+    let prompt = prompt_builder.build_prompt(&diff_content, focus)?;
+    let rules = prompt_builder.rules();
+
+    let mut request = prompt.clone();
+    let mut usage: Option<TokenUsage> = None;
+    let mut fallbacks = Vec::new();
+    let mut chain = models;
+    let mut attempt = 0;
+    let mut preview = if live { LivePreview::stderr() } else { None };
+    loop {
+        let answer = match preview.as_mut() {
+            Some(preview) => {
+                let answer = ai_service
+                    .stream_commit_message(request, chain, preview)
+                    .await;
+                preview.clear();
+                answer?
+            }
+            None => ai_service.generate_commit_message(request, chain).await?,
+        };
+        for (model, error) in &answer.fallbacks {
+            eprintln!("{model} could not answer: {error}");
+        }
+        if !answer.fallbacks.is_empty() {
+            eprintln!("Using {} instead.", answer.model);
+        }
+        fallbacks.extend(answer.fallbacks);
+
+        // Corrections go to the model that wrote the rejected answer
+        let model = answer.model;
+        let completion = answer.completion;
+        let answered = chain.iter().position(|candidate| *candidate == model);
+        chain = &chain[answered.unwrap_or_default()..];
+        if let Some(completion_usage) = completion.usage {
+            *usage.get_or_insert_default() += completion_usage;
+        }
+
+        // Preambles and code fences are removed here instead of asking for a correction
+        let message = extract_message(&completion.content);
+        let violations: Vec<String> = match validate(&message, rules) {
+            Ok(commit) => {
+                return Ok(Generation {
+                    commit,
+                    model,
+                    fallbacks,
+                    usage,
+                    attempts: attempt + 1,
+                    latency: started.elapsed(),
+                    truncations,
+                });
+            }
+            Err(diagnostics) => diagnostics.iter().map(ToString::to_string).collect(),
+        };
+
+        if attempt == retries {
+            return Err(ConvComError::CommitMessageError(format!(
+                "{model} gave no valid message in {} attempts; the last one broke these rules:\n{}",
+                attempt + 1,
+                violations.join("\n")
+            ))
+            .into());
+        }
+        attempt += 1;
+        eprintln!(
+            "The message from {model} breaks {} rule(s), asking for a correction ({attempt} of {retries})...",
+            violations.len()
+        );
+        request = prompt_builder.build_correction_prompt(&prompt, &message, &violations);
+    }
+}
+
+/// Put the selected model first, followed by the fallbacks
+fn model_chain(model: &Model, fallbacks: &[Model]) -> Vec<Model> {
+    std::iter::once(model)
+        .chain(fallbacks.iter().filter(|fallback| *fallback != model))
+        .cloned()
+        .collect()
+}
+
+/// Resolve the configured fallback models, skipping those that cannot be used
+fn resolve_fallbacks(
+    registry: &ModelRegistry,
+    ai_service: &AiService,
+    names: &[String],
+) -> Vec<Model> {
+    names
+        .iter()
+        .filter_map(|name| match registry.resolve(name) {
+            Ok(model) if ai_service.has_provider(model.provider) => Some(model),
+            Ok(model) => {
+                eprintln!(
+                    "Skipping fallback model '{name}': the {} provider has no API key configured.",
+                    model.provider
+                );
+                None
+            }
+            Err(e) => {
+                eprintln!("Skipping fallback model '{name}': {e}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Report a fatal error in the selected output format and exit
+///
+/// `describe` prints the error for people; JSON output gets an error object
+/// with a stable code instead.
+fn fail<E: Into<anyhow::Error>>(
+    format: OutputFormat,
+    error: E,
+    describe: impl FnOnce(&anyhow::Error),
+) -> ! {
+    let error = error.into();
+    match format {
+        OutputFormat::Text => describe(&error),
+        OutputFormat::Json => println!("{}", error_report(&error)),
+    }
+    std::process::exit(1);
+}
+
+/// Install or remove a hook and describe what was done
+fn manage_hook(action: &HookAction) -> error::Result<String> {
+    let dir = GitService::new()?.hooks_dir()?;
+
+    match action {
+        HookAction::Install { hook } => {
+            let name = hook.name();
+            let program = std::env::current_exe()?;
+            let may_reject = matches!(hook, HookKind::CommitMsg);
+            let script = hooks::hook_script(name, &program, may_reject);
+            Ok(match hooks::install_hook(&dir, name, &script)? {
+                HookInstall::Installed => format!("Installed the {name} hook in {}", dir.display()),
+                HookInstall::Updated => format!("Updated the {name} hook in {}", dir.display()),
+                HookInstall::Chained => format!(
+                    "Installed the {name} hook in {}; the existing hook was moved to {} and still runs first",
+                    dir.display(),
+                    hooks::chained_hook_path(&dir, name).display()
+                ),
+            })
+        }
+        HookAction::Uninstall { hook } => {
+            let name = hook.name();
+            Ok(if hooks::uninstall_hook(&dir, name)? {
+                format!("Removed the {name} hook and restored the one it replaced")
+            } else {
+                format!("Removed the {name} hook")
+            })
+        }
+        HookAction::PrepareCommitMsg { .. } | HookAction::CommitMsg { .. } => {
+            unreachable!("hooks are run by git")
+        }
+    }
+}
+
+/// Store, remove or locate API keys and describe what was done
+fn manage_auth(cli: &Cli, action: &AuthAction) -> error::Result<String> {
+    let keyring = SystemKeyring;
+    let key_provider = |provider: &AiProvider| {
+        if KEY_PROVIDERS.contains(provider) {
+            Ok(*provider)
+        } else {
+            Err(ConvComError::ConfigError(format!(
+                "The {provider} provider needs no API key"
+            )))
+        }
+    };
+
+    match action {
+        AuthAction::Login { provider } => {
+            let provider = key_provider(provider)?;
+            let key = if std::io::stdin().is_terminal() {
+                rpassword::prompt_password(format!("{provider} API key: "))?
+            } else {
+                let mut line = String::new();
+                std::io::stdin().lock().read_line(&mut line)?;
+                line
+            };
+            let key = key.trim();
+            if key.is_empty() {
+                return Err(ConvComError::ConfigError("No API key given".to_string()));
+            }
+            keyring.set(provider, &ApiKey::from(key))?;
+            Ok(format!("Stored the {provider} API key in the keyring"))
+        }
+        AuthAction::Logout { provider } => {
+            let provider = key_provider(provider)?;
+            Ok(if keyring.delete(provider)? {
+                format!("Removed the {provider} API key from the keyring")
+            } else {
+                format!("The keyring holds no {provider} API key")
+            })
+        }
+        AuthAction::Status => {
+            let (_, layers) = cli.load_settings()?;
+            let keys = KeyResolver::new(&layers)
+                .with_git_config(git_config())
+                .with_store(&keyring);
+            let lines: Vec<String> = KEY_PROVIDERS
+                .iter()
+                .map(|&provider| match keys.resolve(provider) {
+                    Ok(Some((_, source))) => format!("{provider}: {source}"),
+                    Ok(None) => format!("{provider}: not set"),
+                    // Also says why the keyring could not be searched
+                    Err(e) => format!("{provider}: {e}"),
+                })
+                .collect();
+            Ok(lines.join("\n"))
+        }
+    }
+}
+
+/// Print lint diagnostics for a message and tell whether it passed
+fn report_lint(source: &str, message: &str, rules: &LintRules) -> bool {
+    let diagnostics = lint(message, rules);
+    let name = if source == "-" { "<stdin>" } else { source };
+    for diagnostic in &diagnostics {
+        eprintln!("{name}:{diagnostic}");
+    }
+    diagnostics.is_empty()
+}
+
+/// Generate a message for `git commit` and put it above the template in `file`
+async fn prepare_commit_msg(cli: &Cli, file: &Path) -> Result<()> {
+    let (config, _) = cli.load_config()?;
+    let ai_service = AiService::from_config(&config)?;
+    let registry = ModelRegistry::new(&config);
+    let model = registry.resolve(&config.model)?;
+    if !ai_service.has_provider(model.provider) {
+        anyhow::bail!(
+            "model '{model}' requires the {} provider, which has no API key configured",
+            model.provider
+        );
+    }
+    let fallbacks = resolve_fallbacks(&registry, &ai_service, &config.fallback);
+    let prompt_builder = PromptBuilder::new()?.with_rules(config.lint);
+
+    // `git commit -a` and `git commit <paths>` commit a temporary index
+    let mut git_service = GitService::new()?
+        .with_context_lines(cli.context)
+        .with_exclusions(&config.exclude)?;
+    if let Some(index_file) = std::env::var_os("GIT_INDEX_FILE") {
+        git_service = git_service.with_index_file(index_file.into());
+    }
+    let changes = git_service.get_changes()?;
+
+    let generation = generate(
+        &ai_service,
+        &prompt_builder,
+        &changes,
+        cli.diff_format,
+        &model_chain(&model, &fallbacks),
+        cli.focus.as_deref(),
+        cli.retries,
+        false,
+    )
+    .await?;
+    let template = std::fs::read_to_string(file).unwrap_or_default();
+    std::fs::write(file, format!("{}\n{template}", generation.commit))?;
+
+    Ok(())
+}
+
+/// Print the models known by name, then those installed in the Ollama daemon
+async fn list_models(cli: &Cli) -> error::Result<()> {
+    let (config, _) = cli.load_settings()?;
+    let registry = ModelRegistry::new(&config);
+    let print = |name: &str, model: &Model| {
+        println!(
+            "{name:<46} {}:{} ({} tokens)",
+            model.provider, model.id, model.context_window
+        );
+    };
+
+    for (name, model) in registry.models() {
+        print(name, model);
+    }
+    match OllamaProvider::new(config.ollama)?.list_models().await {
+        Ok(installed) => {
+            for name in installed {
+                let spec = format!("ollama:{name}");
+                print(&spec, &registry.resolve(&spec)?);
+            }
+        }
+        Err(e) => eprintln!("Could not list Ollama models: {e}"),
+    }
+    Ok(())
+}
+
+/// Print each effective setting as TOML, optionally with where it was set
+fn show_config(layers: &Layers, origin: bool) {
+    for (key, value, source) in layers.entries() {
+        if origin {
+            println!("{key} = {value}  # {source}");
+        } else {
+            println!("{key} = {value}");
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    match &cli.command {
+        Some(Command::Hook {
+            action: HookAction::PrepareCommitMsg { file, source, .. },
+        }) => {
+            // Never block the commit: without a message git shows its usual template
+            if hooks::should_prepare_message(source.as_deref())
+                && let Err(e) = prepare_commit_msg(&cli, file).await
+            {
+                eprintln!("convcom: no commit message generated: {e}");
+            }
+            Ok(())
+        }
+        Some(Command::Hook {
+            action: HookAction::CommitMsg { file },
+        }) => {
+            // A broken config must not make every commit fail
+            let rules = match cli.load_settings() {
+                Ok((config, _)) => config.lint,
+                Err(e) => {
+                    eprintln!("convcom: {e}; checking with the default rules");
+                    LintRules::default()
+                }
+            };
+            let passed = match read_message(file) {
+                Ok(message) => report_lint(file, &message, &rules),
+                Err(e) => {
+                    eprintln!("convcom: {e}");
+                    false
+                }
+            };
+            if !passed {
+                eprintln!(
+                    "convcom: commit rejected; edit the message with 'git commit -e -F {file}'"
+                );
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Hook { action }) => match manage_hook(action) {
+            Ok(summary) => {
+                println!("{summary}");
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("Hook error: {e}");
+                std::process::exit(1);
+            }
+        },
+        Some(Command::Lint { file, rules }) => {
+            let rules = match cli.load_settings() {
+                Ok((config, _)) => rules.rules(config.lint),
+                Err(e) => {
+                    eprintln!("{e}");
+                    std::process::exit(1);
+                }
+            };
+            let message = match read_message(file) {
+                Ok(message) => message,
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    std::process::exit(1);
+                }
+            };
+            if !report_lint(file, &message, &rules) {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Models) => {
+            if let Err(e) = list_models(&cli).await {
+                eprintln!("Error listing models: {e}");
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Auth { action }) => match manage_auth(&cli, action) {
+            Ok(summary) => {
+                println!("{summary}");
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("Auth error: {e}");
+                std::process::exit(1);
+            }
+        },
+        Some(Command::Config {
+            action: ConfigAction::Show { origin },
+        }) => match cli.load_settings() {
+            Ok((_, layers)) => {
+                show_config(&layers, *origin);
+                Ok(())
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        },
+        None => run(cli).await,
+    }
+}
+
+/// Generate a message for the selected changes, review it and optionally commit
+async fn run(cli: Cli) -> Result<()> {
+    let format = cli.format;
+
     // Load configuration
-    let config = match Config::load() {
-        Ok(config) => config,
-        Err(e) => {
+    let (mut config, layers) = match cli.load_config() {
+        Ok(loaded) => loaded,
+        Err(e) => fail(format, e, |e| {
             eprintln!("Configuration error: {e}");
             eprintln!();
             eprintln!("Please set your GROQ_API_KEY:");
             eprintln!("1. Get a free API key from: https://console.groq.com");
             eprintln!("2. Set it in your environment: export GROQ_API_KEY=\"your_key_here\"");
-            eprintln!("3. Or create config file: ~/.config/conv_commit_ai/.env.commits");
-            std::process::exit(1);
-        }
+            eprintln!("3. Or set groq.api_key in ~/.config/conv_commit_ai/config.toml");
+            eprintln!("4. Or store it in the OS keyring: convcom auth login groq");
+        }),
     };
 
     // Create AI service with available providers
-    let ai_service = match AiService::new(config.groq_api_key, config.anthropic_api_key) {
+    let mut ai_service = match AiService::from_config(&config) {
         Ok(service) => service,
-        Err(e) => {
+        Err(e) => fail(format, e, |e| {
             eprintln!("AI service initialization error: {e}");
             eprintln!();
             eprintln!("Please configure at least one AI provider:");
             eprintln!("1. Groq: Set GROQ_API_KEY (get free key from console.groq.com)");
             eprintln!("2. Anthropic: Set ANTHROPIC_API_KEY (get key from console.anthropic.com)");
-            eprintln!("3. Or create config file: ~/.config/conv_commit_ai/.env.commits");
-            std::process::exit(1);
-        }
+            eprintln!("3. OpenAI-compatible: Set OPENAI_BASE_URL and/or OPENAI_API_KEY");
+            eprintln!("4. Ollama: Run a local daemon and use --model ollama");
+            eprintln!("5. Or set the keys in ~/.config/conv_commit_ai/config.toml");
+            eprintln!("6. Or store them in the OS keyring: convcom auth login <provider>");
+        }),
+    };
+
+    // Resolve the model name through the presets and the user's registry
+    let registry = ModelRegistry::new(&config);
+    let model = match registry.resolve(&config.model) {
+        Ok(model) => model,
+        Err(e) => fail(format, e, |e| eprintln!("{e}")),
     };
 
     // Validate that the selected model is available
-    if !ai_service.has_provider(cli.model.provider()) {
-        eprintln!(
-            "Error: Model '{}' requires {} provider, but no API key is configured.",
-            cli.model,
-            cli.model.provider()
-        );
-        eprintln!();
-        eprintln!(
-            "Available providers: {:?}",
-            ai_service.available_providers()
-        );
-        eprintln!("Configure the required API key or choose a different model.");
-        std::process::exit(1);
+    if !ai_service.has_provider(model.provider) {
+        let error = ConvComError::ConfigError(format!(
+            "Model '{model}' requires {} provider, but no API key is configured.",
+            model.provider
+        ));
+        fail(format, error, |_| {
+            eprintln!(
+                "Error: Model '{model}' requires {} provider, but no API key is configured.",
+                model.provider
+            );
+            eprintln!();
+            eprintln!(
+                "Available providers: {:?}",
+                ai_service.available_providers()
+            );
+            eprintln!("Configure the required API key or choose a different model.");
+        });
     }
+    let fallbacks = resolve_fallbacks(&registry, &ai_service, &config.fallback);
 
-    // Create Git service and get diff content
-    let git_service = match GitService::new() {
-        Ok(service) => service,
-        Err(e) => {
-            eprintln!("Git error: {e}");
-            eprintln!("Make sure you're in a git repository with staged changes.");
-            std::process::exit(1);
-        }
+    // Create prompt builder
+    let prompt_builder = match PromptBuilder::new() {
+        Ok(builder) => builder.with_rules(config.lint.clone()),
+        Err(e) => fail(format, e, |e| eprintln!("Template error: {e}")),
     };
 
-    let diff_content = match git_service.build_diff_content() {
-        Ok(content) => {
-            if content.trim().is_empty() {
-                eprintln!("No staged changes found.");
-                eprintln!("Use 'git add <files>' to stage changes for commit.");
-                std::process::exit(1);
+    // Collect the changes to describe, from a patch or from the repository
+    let source = cli.diff_source();
+    let (changes, git_service) = match &cli.patch {
+        Some(source) => {
+            let changes = ExclusionRules::load_with_patterns(Path::new("."), &config.exclude)
+                .and_then(|exclusions| load_patch(source, &exclusions));
+            match changes {
+                Ok(changes) => (changes, None),
+                Err(e) => fail(format, e, |e| eprintln!("Error reading patch: {e}")),
             }
-            content
         }
-        Err(e) => {
-            eprintln!("Error getting git diff: {e}");
-            std::process::exit(1);
+        None => {
+            let git_service = match GitService::new()
+                .and_then(|service| service.with_exclusions(&config.exclude))
+            {
+                Ok(service) => {
+                    let service = service
+                        .with_context_lines(cli.context)
+                        .with_source(source.clone());
+                    // Diff and commit the index git would use
+                    match std::env::var_os("GIT_INDEX_FILE") {
+                        Some(index_file) => service.with_index_file(index_file.into()),
+                        None => service,
+                    }
+                }
+                Err(e) => fail(format, e, |e| {
+                    eprintln!("Git error: {e}");
+                    eprintln!("Make sure you're in a git repository with staged changes.");
+                }),
+            };
+
+            // Warn when part of a file's changes would be left out of the commit
+            if source.is_uncommitted()
+                && let Ok(paths) = git_service.partially_staged_files()
+                && !paths.is_empty()
+            {
+                eprintln!(
+                    "Warning: these files have both staged and unstaged changes, \
+                     so the message may not match what gets committed:"
+                );
+                for path in &paths {
+                    eprintln!("  {path}");
+                }
+            }
+
+            match git_service.get_changes() {
+                Ok(changes) => (changes, Some(git_service)),
+                Err(ConvComError::NoStagedFilesError) => {
+                    fail(format, ConvComError::NoStagedFilesError, |_| {
+                        eprintln!("No staged changes found.");
+                        eprintln!("Use 'git add <files>' to stage changes for commit.");
+                    })
+                }
+                Err(e) => fail(format, e, |e| eprintln!("Error getting git diff: {e}")),
+            }
         }
     };
 
-    // Create prompt builder
-    let prompt_builder = match PromptBuilder::new() {
-        Ok(builder) => builder,
-        Err(e) => {
-            eprintln!("Template error: {e}");
-            std::process::exit(1);
-        }
+    // Generate commit message (silently for clean output)
+    let generation = match generate(
+        &ai_service,
+        &prompt_builder,
+        &changes,
+        cli.diff_format,
+        &model_chain(&model, &fallbacks),
+        cli.focus.as_deref(),
+        cli.retries,
+        format == OutputFormat::Text,
+    )
+    .await
+    {
+        Ok(generation) => generation,
+        Err(e) => fail(format, e, |e| {
+            eprintln!("Error generating commit message: {e}")
+        }),
     };
 
-    // Build the complete prompt using the template system
-    let prompt = match prompt_builder.build_prompt(&diff_content, cli.focus.as_deref()) {
-        Ok(prompt) => prompt,
-        Err(e) => {
-            eprintln!("Error building prompt: {e}");
-            std::process::exit(1);
+    if format == OutputFormat::Json {
+        let mut report = MessageReport {
+            commit: &generation.commit,
+            model: &generation.model,
+            fallbacks: &generation.fallbacks,
+            usage: generation.usage,
+            latency: generation.latency,
+            attempts: generation.attempts,
+            files: &changes,
+            truncations: &generation.truncations,
+            created_commit: None,
+        };
+        if cli.create_commit
+            && let Some(git_service) = &git_service
+        {
+            match git_service.create_commit(&generation.commit.to_string()) {
+                Ok(oid) => report.created_commit = Some(oid.to_string()),
+                Err(e) => fail(format, e, |_| {}),
+            }
         }
-    };
+        println!("{}", report.to_json());
+        return Ok(());
+    }
 
-    // Generate commit message (silently for clean output)
-    match ai_service.generate_commit_message(prompt, cli.model).await {
-        Ok(commit_message) => {
-            println!("{commit_message}");
+    // Review interactively only when a person is at the terminal
+    let interactive =
+        !cli.no_interactive && std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    let first_message = generation.commit.to_string();
+    let commit_message = if interactive {
+        let repo_config = git_service
+            .as_ref()
+            .and_then(|service| service.config().ok());
+        let edit_path = git_service.as_ref().map_or_else(
+            || std::env::temp_dir().join("convcom-COMMIT_EDITMSG"),
+            GitService::commit_message_path,
+        );
+        let mut review = Review::new(resolve_editor(repo_config.as_ref()), edit_path);
+        review.push(Candidate {
+            message: first_message,
+            origin: generation.model.to_string(),
+        });
+        let mut model = model;
+
+        loop {
+            let step = review.next_step(&mut std::io::stdin().lock(), &mut std::io::stderr());
+            match step {
+                Ok(ReviewStep::Accept(message)) => break message,
+                Ok(ReviewStep::Abort) => {
+                    eprintln!("Aborted.");
+                    std::process::exit(1);
+                }
+                Ok(ReviewStep::Regenerate {
+                    model: new_model,
+                    hint,
+                }) => {
+                    if let Some(spec) = new_model {
+                        let new_model = match registry.resolve(&spec) {
+                            Ok(new_model) => new_model,
+                            Err(e) => {
+                                eprintln!("{e}");
+                                continue;
+                            }
+                        };
+                        // Keys were only looked up for the models in use so far
+                        if !ai_service.has_provider(new_model.provider) {
+                            let added = config
+                                .resolve_key(&layers, new_model.provider)
+                                .and_then(|()| AiService::from_config(&config));
+                            match added {
+                                Ok(service) => ai_service = service,
+                                Err(e) => {
+                                    eprintln!("{e}");
+                                    continue;
+                                }
+                            }
+                        }
+                        if !ai_service.has_provider(new_model.provider) {
+                            eprintln!(
+                                "Model '{new_model}' requires the {} provider, which has no API key configured.",
+                                new_model.provider
+                            );
+                            continue;
+                        }
+                        model = new_model;
+                    }
+
+                    let focus = match (cli.focus.as_deref(), hint.as_deref()) {
+                        (Some(focus), Some(hint)) => Some(format!("{focus}\n{hint}")),
+                        (focus, hint) => focus.or(hint).map(str::to_string),
+                    };
+                    eprintln!("Generating a new message with {model}...");
+                    match generate(
+                        &ai_service,
+                        &prompt_builder,
+                        &changes,
+                        cli.diff_format,
+                        &model_chain(&model, &fallbacks),
+                        focus.as_deref(),
+                        cli.retries,
+                        true,
+                    )
+                    .await
+                    {
+                        Ok(generation) => review.push(Candidate {
+                            message: generation.commit.to_string(),
+                            origin: generation.model.to_string(),
+                        }),
+                        Err(e) => eprintln!("Error generating commit message: {e}"),
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error reading your choice: {e}");
+                    std::process::exit(1);
+                }
+            }
         }
-        Err(e) => {
-            eprintln!("Error generating commit message: {e}");
-            std::process::exit(1);
+    } else {
+        first_message
+    };
+
+    // Accepting in the review commits staged changes; otherwise only --create-commit does
+    let create_commit =
+        cli.create_commit || (interactive && cli.patch.is_none() && source == DiffSource::Staged);
+    if !(interactive && create_commit) {
+        println!("{commit_message}");
+    }
+
+    if create_commit && let Some(git_service) = &git_service {
+        match git_service.create_commit(&commit_message) {
+            Ok(oid) => eprintln!("Created commit {oid}"),
+            Err(e) => {
+                eprintln!("Error creating commit: {e}");
+                eprintln!(
+                    "The message was saved to {}; retry with 'git commit -e -F <file>'.",
+                    git_service.commit_message_path().display()
+                );
+                std::process::exit(1);
+            }
         }
     }
 
@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
 
+/// Maximum number of tokens requested for a generated commit message
+pub const MAX_OUTPUT_TOKENS: u32 = 1024;
+
 /// Chat message for the API request
 #[derive(Debug, Serialize)]
 pub struct ChatMessage {
@@ -30,6 +33,18 @@ pub struct ChatCompletionRequest {
     pub messages: Vec<ChatMessage>,
     pub temperature: f32,
     pub max_tokens: u32,
+    /// Send the answer as server-sent events
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+}
+
+/// Options for a streamed chat completion
+#[derive(Debug, Serialize)]
+pub struct StreamOptions {
+    /// Ask for token counts in the last event
+    pub include_usage: bool,
 }
 
 /// Groq API chat completion response choice
@@ -44,8 +59,102 @@ pub struct ChatCompletionMessage {
     pub content: String,
 }
 
+/// Token counts reported by the chat completion API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+impl std::ops::AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+    }
+}
+
 /// Groq API chat completion response
 #[derive(Debug, Deserialize)]
 pub struct ChatCompletionResponse {
     pub choices: Vec<ChatCompletionChoice>,
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+}
+
+/// One server-sent event of a streamed chat completion
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionChunk {
+    #[serde(default)]
+    pub choices: Vec<ChatCompletionChunkChoice>,
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+    /// Groq reports the token counts here instead of in `usage`
+    #[serde(default)]
+    pub x_groq: Option<GroqChunkMetadata>,
+    /// Set instead of choices when generation fails midway
+    #[serde(default)]
+    pub error: Option<serde_json::Value>,
+}
+
+/// Choice in a streamed chat completion event
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionChunkChoice {
+    pub delta: ChatCompletionDelta,
+}
+
+/// New message content in a streamed chat completion event
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionDelta {
+    #[serde(default)]
+    pub content: Option<String>,
+}
+
+/// Groq's extra fields in a streamed chat completion event
+#[derive(Debug, Deserialize)]
+pub struct GroqChunkMetadata {
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+}
+
+/// Text generated by a provider, with the tokens it used when reported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    pub content: String,
+    pub usage: Option<TokenUsage>,
+}
+
+/// One line of a streamed Ollama `/api/chat` response
+#[derive(Debug, Deserialize)]
+pub struct OllamaChatChunk {
+    #[serde(default)]
+    pub message: Option<OllamaChatMessage>,
+    #[serde(default)]
+    pub done: bool,
+    /// Tokens in the prompt, sent with the final chunk
+    #[serde(default)]
+    pub prompt_eval_count: Option<u32>,
+    /// Tokens generated, sent with the final chunk
+    #[serde(default)]
+    pub eval_count: Option<u32>,
+    /// Set instead of a message when generation fails
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Message content in an Ollama chat chunk
+#[derive(Debug, Deserialize)]
+pub struct OllamaChatMessage {
+    pub content: String,
+}
+
+/// Ollama `/api/tags` response listing the local models
+#[derive(Debug, Deserialize)]
+pub struct OllamaTags {
+    pub models: Vec<OllamaModel>,
+}
+
+/// A model installed in Ollama
+#[derive(Debug, Deserialize)]
+pub struct OllamaModel {
+    pub name: String,
 }
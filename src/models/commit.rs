@@ -0,0 +1,448 @@
+use crate::error::{ConvComError, Result};
+use std::fmt;
+
+/// The `BREAKING CHANGE` footer token, the only token allowed to contain a space
+pub const BREAKING_CHANGE: &str = "BREAKING CHANGE";
+
+/// The `type(scope)!: description` first line of a conventional commit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header<'a> {
+    pub commit_type: &'a str,
+    pub scope: Option<&'a str>,
+    /// Whether a `!` marks the commit as breaking
+    pub breaking: bool,
+    pub description: &'a str,
+}
+
+impl<'a> Header<'a> {
+    /// Split a header line into its parts, or None if it is not shaped like one
+    ///
+    /// The parts are not validated beyond their shape: the type is any word,
+    /// and the scope and description may be empty.
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let (prefix, description) = match line.split_once(": ") {
+            Some(parts) => parts,
+            None => (line.strip_suffix(':')?, ""),
+        };
+        let (prefix, breaking) = match prefix.strip_suffix('!') {
+            Some(prefix) => (prefix, true),
+            None => (prefix, false),
+        };
+        let (commit_type, scope) = match prefix.split_once('(') {
+            Some((commit_type, scope)) => (commit_type, Some(scope.strip_suffix(')')?)),
+            None => (prefix, None),
+        };
+
+        let is_word = !commit_type.is_empty()
+            && commit_type
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+        if !is_word || scope.is_some_and(|scope| scope.contains(['(', ')'])) {
+            return None;
+        }
+
+        Some(Self {
+            commit_type,
+            scope,
+            breaking,
+            description,
+        })
+    }
+}
+
+/// How a footer token is separated from its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FooterSeparator {
+    /// `Token: value`
+    Colon,
+    /// `Token #value`, used for references like `Refs #123`
+    Hash,
+}
+
+impl FooterSeparator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FooterSeparator::Colon => ": ",
+            FooterSeparator::Hash => " #",
+        }
+    }
+}
+
+/// A git-trailer style footer such as `Reviewed-by: Alice`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Footer {
+    pub token: String,
+    pub separator: FooterSeparator,
+    /// The value, which may span several lines
+    pub value: String,
+}
+
+impl Footer {
+    /// Parse a line starting a footer, accepting only tokens the spec allows
+    ///
+    /// Tokens use `-` instead of whitespace, except for `BREAKING CHANGE`.
+    pub fn parse(line: &str) -> Option<Self> {
+        Self::split(line).filter(|footer| is_footer_token(&footer.token))
+    }
+
+    /// Split a line that looks like a footer, even if its token is invalid
+    ///
+    /// The token may be up to three words, so `Reviewed by: Alice` is found
+    /// while ordinary prose with a colon usually is not.
+    pub fn parse_loose(line: &str) -> Option<Self> {
+        Self::split(line).filter(|footer| {
+            let words = footer.token.split(' ').collect::<Vec<_>>();
+            words.len() <= 3
+                && footer.token.starts_with(|c: char| c.is_alphabetic())
+                && words.iter().all(|word| is_footer_token(word))
+        })
+    }
+
+    fn split(line: &str) -> Option<Self> {
+        [FooterSeparator::Colon, FooterSeparator::Hash]
+            .into_iter()
+            .find_map(|separator| {
+                let (token, value) = line.split_once(separator.as_str())?;
+                Some(Self {
+                    token: token.to_string(),
+                    separator,
+                    value: value.to_string(),
+                })
+            })
+    }
+
+    /// Whether the token is some spelling of `BREAKING CHANGE` or `BREAKING-CHANGE`
+    pub fn is_breaking_change(&self) -> bool {
+        self.token.eq_ignore_ascii_case(BREAKING_CHANGE)
+            || self.token.eq_ignore_ascii_case("BREAKING-CHANGE")
+    }
+}
+
+/// Check a footer token: a word using `-` instead of whitespace, or `BREAKING CHANGE`
+fn is_footer_token(token: &str) -> bool {
+    token == BREAKING_CHANGE
+        || (!token.is_empty() && token.chars().all(|c| c.is_alphanumeric() || c == '-'))
+}
+
+impl fmt::Display for Footer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.token, self.separator.as_str(), self.value)
+    }
+}
+
+/// Index of the paragraph that starts the footers, given each paragraph's first line
+///
+/// Like git trailers, footers are the trailing paragraphs that each open
+/// with a valid `token: value` or `token #value` line; a body paragraph
+/// after them makes them part of the body.
+pub fn footer_start(first_lines: &[&str]) -> Option<usize> {
+    let trailing = first_lines
+        .iter()
+        .rev()
+        .take_while(|line| Footer::parse(line).is_some())
+        .count();
+    (trailing > 0).then(|| first_lines.len() - trailing)
+}
+
+/// A commit message following the Conventional Commits specification
+///
+/// Displaying a parsed message gives back the original text, apart from
+/// trailing whitespace and runs of blank lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConventionalCommit {
+    pub commit_type: String,
+    pub scope: Option<String>,
+    /// Whether the header marks the commit as breaking with `!`
+    pub breaking: bool,
+    pub description: String,
+    /// Body paragraphs, without the blank lines between them
+    pub body: Vec<String>,
+    pub footers: Vec<Footer>,
+}
+
+impl ConventionalCommit {
+    /// Parse a commit message
+    ///
+    /// Footers are taken from the trailing paragraphs that open with a
+    /// `token: value` or `token #value` line, like git trailers. Within them,
+    /// a line that does not start a new footer continues the value of the
+    /// previous one.
+    pub fn parse(message: &str) -> Result<Self> {
+        let invalid = |reason: &str| ConvComError::CommitMessageError(reason.to_string());
+        let mut lines = message.trim_end().lines();
+
+        let header_line = lines.next().unwrap_or_default();
+        let header = Header::parse(header_line)
+            .ok_or_else(|| invalid("the header must look like 'type(scope): description'"))?;
+        if header.description.trim().is_empty() {
+            return Err(invalid("the description is empty"));
+        }
+        if header.scope.is_some_and(|scope| scope.trim().is_empty()) {
+            return Err(invalid("the scope is empty"));
+        }
+        if lines.next().is_some_and(|line| !line.trim().is_empty()) {
+            return Err(invalid(
+                "the body must be separated from the header by a blank line",
+            ));
+        }
+
+        // Paragraphs as the index of their first line and their lines
+        let lines: Vec<&str> = lines.collect();
+        let mut paragraphs: Vec<(usize, Vec<&str>)> = Vec::new();
+        let mut in_paragraph = false;
+        for (index, &line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                in_paragraph = false;
+            } else if in_paragraph {
+                paragraphs
+                    .last_mut()
+                    .expect("paragraph started")
+                    .1
+                    .push(line);
+            } else {
+                paragraphs.push((index, vec![line]));
+                in_paragraph = true;
+            }
+        }
+
+        let first_lines: Vec<&str> = paragraphs.iter().map(|(_, lines)| lines[0]).collect();
+        let footer_paragraph = footer_start(&first_lines);
+        let footer_lines = match footer_paragraph {
+            Some(paragraph) => &lines[paragraphs[paragraph].0..],
+            None => &[],
+        };
+        paragraphs.truncate(footer_paragraph.unwrap_or(paragraphs.len()));
+
+        let mut footers: Vec<Footer> = Vec::new();
+        for &line in footer_lines {
+            match Footer::parse(line) {
+                Some(footer) => footers.push(footer),
+                None => {
+                    let footer = footers.last_mut().expect("first footer line parsed");
+                    footer.value.push('\n');
+                    footer.value.push_str(line);
+                }
+            }
+        }
+        // Blank lines between two footers belong to neither
+        for footer in &mut footers {
+            footer.value.truncate(footer.value.trim_end().len());
+        }
+
+        Ok(Self {
+            commit_type: header.commit_type.to_string(),
+            scope: header.scope.map(str::to_string),
+            breaking: header.breaking,
+            description: header.description.to_string(),
+            body: paragraphs
+                .into_iter()
+                .map(|(_, paragraph)| paragraph.join("\n"))
+                .collect(),
+            footers,
+        })
+    }
+
+    /// The `type(scope)!: description` line
+    pub fn header(&self) -> String {
+        let scope = self
+            .scope
+            .as_ref()
+            .map(|scope| format!("({scope})"))
+            .unwrap_or_default();
+        let breaking = if self.breaking { "!" } else { "" };
+        format!(
+            "{}{scope}{breaking}: {}",
+            self.commit_type, self.description
+        )
+    }
+
+    /// Whether the commit is breaking, by `!` or a `BREAKING CHANGE` footer
+    pub fn is_breaking(&self) -> bool {
+        self.breaking || self.footers.iter().any(Footer::is_breaking_change)
+    }
+}
+
+impl fmt::Display for ConventionalCommit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.header())?;
+        for paragraph in &self.body {
+            write!(f, "\n\n{paragraph}")?;
+        }
+        for (index, footer) in self.footers.iter().enumerate() {
+            let separator = if index == 0 { "\n\n" } else { "\n" };
+            write!(f, "{separator}{footer}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_header() {
+        assert_eq!(
+            Header::parse("feat(api)!: add login"),
+            Some(Header {
+                commit_type: "feat",
+                scope: Some("api"),
+                breaking: true,
+                description: "add login",
+            })
+        );
+        assert_eq!(
+            Header::parse("fix:").map(|header| header.description),
+            Some("")
+        );
+
+        assert_eq!(Header::parse("Add login"), None);
+        assert_eq!(Header::parse("feat(api: add login"), None);
+        assert_eq!(Header::parse("feat:add login"), None);
+        assert_eq!(Header::parse("new feature: add login"), None);
+    }
+
+    #[test]
+    fn test_parse_footer_line() {
+        assert_eq!(
+            Footer::parse("Refs #123"),
+            Some(Footer {
+                token: "Refs".to_string(),
+                separator: FooterSeparator::Hash,
+                value: "123".to_string(),
+            })
+        );
+        assert!(Footer::parse("BREAKING CHANGE: config moved").is_some());
+        assert!(Footer::parse("Reviewed-by: Alice").is_some());
+
+        assert!(Footer::parse("Reviewed by: Alice").is_none());
+        assert_eq!(
+            Footer::parse_loose("Reviewed by: Alice").map(|footer| footer.token),
+            Some("Reviewed by".to_string())
+        );
+        assert!(Footer::parse_loose("* Add the parser: it reads headers").is_none());
+        assert!(Footer::parse_loose("This change is needed because: reasons").is_none());
+
+        assert!(
+            Footer::parse_loose("breaking change: x")
+                .unwrap()
+                .is_breaking_change()
+        );
+        assert!(
+            Footer::parse("breaking-change: x")
+                .unwrap()
+                .is_breaking_change()
+        );
+    }
+
+    #[test]
+    fn test_parse_conventional_commit() {
+        let message = "feat(parser)!: accept quoted paths\n\n\
+                       Paths are unquoted like git does.\n\
+                       Octal escapes are decoded.\n\n\
+                       * Add the unquoting helper.\n\n\
+                       BREAKING CHANGE: paths are compared\n\
+                       byte by byte\n\
+                       Refs #12\n";
+        let commit = ConventionalCommit::parse(message).unwrap();
+
+        assert_eq!(commit.commit_type, "feat");
+        assert_eq!(commit.scope.as_deref(), Some("parser"));
+        assert!(commit.breaking);
+        assert_eq!(commit.description, "accept quoted paths");
+        assert_eq!(
+            commit.body,
+            vec![
+                "Paths are unquoted like git does.\nOctal escapes are decoded.",
+                "* Add the unquoting helper."
+            ]
+        );
+        assert_eq!(
+            commit.footers,
+            vec![
+                Footer {
+                    token: BREAKING_CHANGE.to_string(),
+                    separator: FooterSeparator::Colon,
+                    value: "paths are compared\nbyte by byte".to_string(),
+                },
+                Footer {
+                    token: "Refs".to_string(),
+                    separator: FooterSeparator::Hash,
+                    value: "12".to_string(),
+                },
+            ]
+        );
+
+        assert_eq!(commit.to_string(), message.trim_end());
+        assert_eq!(
+            ConventionalCommit::parse(&commit.to_string()).unwrap(),
+            commit
+        );
+    }
+
+    #[test]
+    fn test_footers_are_trailing_paragraphs() {
+        let message = "fix(config): read the new location\n\n\
+                       Files are looked up in one place.\n\n\
+                       BREAKING CHANGE: the config moved.\n\
+                       Old files are read once and copied.\n\n\
+                       Refs #31\n\
+                       Reviewed-by: Alice";
+        let commit = ConventionalCommit::parse(message).unwrap();
+
+        assert_eq!(commit.body, vec!["Files are looked up in one place."]);
+        assert_eq!(
+            commit.footers[0].value,
+            "the config moved.\nOld files are read once and copied."
+        );
+        assert_eq!(commit.footers[1].value, "31");
+        assert_eq!(commit.footers[2].token, "Reviewed-by");
+        assert_eq!(
+            commit.to_string(),
+            message.replace("copied.\n\n", "copied.\n")
+        );
+
+        // A footer-like body paragraph followed by more body stays in the body
+        let commit = ConventionalCommit::parse(
+            "fix: a\n\nNote: the cache is cleared\n\nMore explanation here.\n\nRefs: #1",
+        )
+        .unwrap();
+        assert_eq!(
+            commit.body,
+            vec!["Note: the cache is cleared", "More explanation here."]
+        );
+        assert_eq!(commit.footers.len(), 1);
+        assert_eq!(commit.footers[0].token, "Refs");
+        assert_eq!(
+            ConventionalCommit::parse(&commit.to_string()).unwrap(),
+            commit
+        );
+    }
+
+    #[test]
+    fn test_breaking_change_footer() {
+        let commit = ConventionalCommit::parse("fix: a\n\nBREAKING-CHANGE: b").unwrap();
+        assert!(!commit.breaking);
+        assert!(commit.is_breaking());
+        assert!(commit.body.is_empty());
+
+        // Prose with a colon in the last paragraph is still body
+        let commit = ConventionalCommit::parse("fix: a\n\nThe reason is simple: b").unwrap();
+        assert!(!commit.is_breaking());
+        assert_eq!(commit.body, vec!["The reason is simple: b"]);
+        assert!(commit.footers.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_conventional_commit() {
+        for message in ["", "Add a parser", "fix: ", "fix(): a", "fix: a\nbody"] {
+            assert!(
+                matches!(
+                    ConventionalCommit::parse(message),
+                    Err(ConvComError::CommitMessageError(_))
+                ),
+                "{message:?}"
+            );
+        }
+    }
+}
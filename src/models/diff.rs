@@ -0,0 +1,264 @@
+use std::fmt;
+
+/// Kind of change recorded for a staged file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+    Renamed,
+    Copied,
+    TypeChanged,
+}
+
+impl ChangeKind {
+    /// Get the single-letter status used by `git status --short`
+    pub fn as_char(&self) -> char {
+        match self {
+            ChangeKind::Added => 'A',
+            ChangeKind::Modified => 'M',
+            ChangeKind::Deleted => 'D',
+            ChangeKind::Renamed => 'R',
+            ChangeKind::Copied => 'C',
+            ChangeKind::TypeChanged => 'T',
+        }
+    }
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_char())
+    }
+}
+
+/// Why a file's content is left out of the prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExclusionReason {
+    /// Package manager lock file
+    Lockfile,
+    /// Minified asset or source map
+    Minified,
+    /// Marked `linguist-generated` in `.gitattributes`
+    Generated,
+    /// Marked `-diff` in `.gitattributes`
+    DiffDisabled,
+    /// Matched by a pattern in `.convcomignore`
+    Ignored,
+}
+
+impl fmt::Display for ExclusionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExclusionReason::Lockfile => write!(f, "lockfile"),
+            ExclusionReason::Minified => write!(f, "minified"),
+            ExclusionReason::Generated => write!(f, "generated"),
+            ExclusionReason::DiffDisabled => write!(f, "diff disabled"),
+            ExclusionReason::Ignored => write!(f, "ignored"),
+        }
+    }
+}
+
+/// How file changes are rendered into the prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Default)]
+pub enum DiffStyle {
+    /// Only added and removed lines (default)
+    #[value(name = "compact")]
+    #[default]
+    Compact,
+
+    /// Hunks with `@@` headers, enclosing function, context and line numbers
+    #[value(name = "unified")]
+    Unified,
+}
+
+impl fmt::Display for DiffStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffStyle::Compact => write!(f, "compact"),
+            DiffStyle::Unified => write!(f, "unified"),
+        }
+    }
+}
+
+/// Role of a single line inside a diff hunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Context,
+    Added,
+    Removed,
+}
+
+/// A single line of a diff hunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffLine {
+    pub kind: LineKind,
+    /// Line number in the old file (None for added lines)
+    pub old_lineno: Option<u32>,
+    /// Line number in the new file (None for removed lines)
+    pub new_lineno: Option<u32>,
+    /// Line content without the trailing newline
+    pub content: String,
+}
+
+/// A contiguous block of changes within a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffHunk {
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    /// Hunk header line, e.g. `@@ -10,6 +10,7 @@ fn main()`
+    pub header: String,
+    pub lines: Vec<DiffLine>,
+}
+
+/// Size, type and image dimensions of a binary file on both sides of a change
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BinaryInfo {
+    /// Size in bytes before the change (None if the file did not exist)
+    pub old_size: Option<u64>,
+    /// Size in bytes after the change (None if the file no longer exists)
+    pub new_size: Option<u64>,
+    /// MIME type guessed from the content's magic bytes
+    pub mime_type: Option<&'static str>,
+    /// Width and height before the change, for recognised images
+    pub old_dimensions: Option<(u32, u32)>,
+    /// Width and height after the change, for recognised images
+    pub new_dimensions: Option<(u32, u32)>,
+}
+
+/// A single file change between two snapshots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    pub kind: ChangeKind,
+    /// Path after the change (the deleted path for deletions)
+    pub path: String,
+    /// Source path for renames and copies
+    pub old_path: Option<String>,
+    /// Similarity index in percent for renames and copies
+    pub similarity: Option<u16>,
+    /// Git file mode before the change (0 if the file did not exist)
+    pub old_mode: u32,
+    /// Git file mode after the change (0 if the file no longer exists)
+    pub new_mode: u32,
+    /// Content hunks in file order
+    pub hunks: Vec<DiffHunk>,
+    /// Set when the content should be summarised instead of shown
+    pub excluded: Option<ExclusionReason>,
+    /// Added and removed lines that were counted but not kept in `hunks`
+    pub omitted_lines: usize,
+    /// Set when git considers the content binary
+    pub binary: Option<BinaryInfo>,
+}
+
+impl FileChange {
+    /// Create a change for a path with no source path, modes or hunks
+    pub fn new(kind: ChangeKind, path: impl Into<String>) -> Self {
+        Self {
+            kind,
+            path: path.into(),
+            old_path: None,
+            similarity: None,
+            old_mode: 0,
+            new_mode: 0,
+            hunks: Vec::new(),
+            excluded: None,
+            omitted_lines: 0,
+            binary: None,
+        }
+    }
+
+    /// Iterate over all added and removed lines across hunks
+    pub fn changed_lines(&self) -> impl Iterator<Item = &DiffLine> {
+        self.hunks
+            .iter()
+            .flat_map(|hunk| hunk.lines.iter())
+            .filter(|line| line.kind != LineKind::Context)
+    }
+
+    /// Number of added and removed lines, including omitted ones
+    pub fn changed_line_count(&self) -> usize {
+        self.omitted_lines + self.changed_lines().count()
+    }
+
+    /// Check whether the file mode changed while the file existed on both sides
+    pub fn mode_changed(&self) -> bool {
+        self.old_mode != 0 && self.new_mode != 0 && self.old_mode != self.new_mode
+    }
+}
+
+/// Describe a git file mode in human readable form
+pub fn describe_mode(mode: u32) -> &'static str {
+    match mode {
+        0o100644 | 0o100664 => "regular file",
+        0o100755 => "executable file",
+        0o120000 => "symlink",
+        0o160000 => "submodule",
+        0o040000 => "directory",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change_kind_chars() {
+        assert_eq!(ChangeKind::Added.as_char(), 'A');
+        assert_eq!(ChangeKind::Renamed.as_char(), 'R');
+        assert_eq!(ChangeKind::Copied.as_char(), 'C');
+        assert_eq!(ChangeKind::TypeChanged.to_string(), "T");
+    }
+
+    #[test]
+    fn test_mode_changed() {
+        let mut change = FileChange {
+            old_mode: 0o100644,
+            new_mode: 0o100755,
+            ..FileChange::new(ChangeKind::Modified, "run.sh")
+        };
+        assert!(change.mode_changed());
+
+        change.old_mode = 0;
+        assert!(!change.mode_changed());
+    }
+
+    #[test]
+    fn test_changed_lines_skip_context() {
+        let line = |kind, content: &str| DiffLine {
+            kind,
+            old_lineno: None,
+            new_lineno: None,
+            content: content.to_string(),
+        };
+        let change = FileChange {
+            hunks: vec![DiffHunk {
+                old_start: 1,
+                old_lines: 2,
+                new_start: 1,
+                new_lines: 2,
+                header: "@@ -1,2 +1,2 @@".to_string(),
+                lines: vec![
+                    line(LineKind::Context, "keep"),
+                    line(LineKind::Removed, "old"),
+                    line(LineKind::Added, "new"),
+                ],
+            }],
+            ..FileChange::new(ChangeKind::Modified, "file.txt")
+        };
+
+        let contents: Vec<&str> = change
+            .changed_lines()
+            .map(|line| line.content.as_str())
+            .collect();
+        assert_eq!(contents, vec!["old", "new"]);
+    }
+
+    #[test]
+    fn test_describe_mode() {
+        assert_eq!(describe_mode(0o100755), "executable file");
+        assert_eq!(describe_mode(0o120000), "symlink");
+        assert_eq!(describe_mode(0o100644), "regular file");
+    }
+}
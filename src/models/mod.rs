@@ -1,2 +1,5 @@
 pub mod ai;
+pub mod commit;
+pub mod diff;
 pub mod providers;
+pub mod registry;
@@ -1,7 +1,10 @@
+use crate::models::ai::MAX_OUTPUT_TOKENS;
+use serde::Deserialize;
 use std::fmt;
 
 /// AI Provider selection
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum AiProvider {
     /// Groq (default)
     #[value(name = "groq")]
@@ -11,6 +14,14 @@ pub enum AiProvider {
     /// Anthropic Claude
     #[value(name = "anthropic")]
     Anthropic,
+
+    /// Any OpenAI-compatible chat completions endpoint
+    #[value(name = "openai")]
+    OpenAi,
+
+    /// A local Ollama daemon
+    #[value(name = "ollama")]
+    Ollama,
 }
 
 impl fmt::Display for AiProvider {
@@ -18,11 +29,15 @@ impl fmt::Display for AiProvider {
         match self {
             AiProvider::Groq => write!(f, "groq"),
             AiProvider::Anthropic => write!(f, "anthropic"),
+            AiProvider::OpenAi => write!(f, "openai"),
+            AiProvider::Ollama => write!(f, "ollama"),
         }
     }
 }
 
-/// Available AI models across all providers
+/// Built-in model presets across the hosted providers
+///
+/// Any other model can be used through the `ModelRegistry`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Default)]
 pub enum ModelName {
     // Groq Models
@@ -154,6 +169,67 @@ impl ModelName {
         }
     }
 
+    /// Get the context window size of the model in tokens
+    pub fn context_window(&self) -> u32 {
+        match self {
+            ModelName::Allam27B => 4_096,
+            ModelName::Gemma29BIT | ModelName::Llama370B8192 | ModelName::Llama38B8192 => 8_192,
+            ModelName::MistralSaba24B => 32_768,
+            ModelName::MetaLlamaPromptGuard222M | ModelName::MetaLlamaPromptGuard286M => 512,
+            ModelName::CompoundBeta
+            | ModelName::CompoundBetaMini
+            | ModelName::DeepSeekR1DistillLlama70B
+            | ModelName::Llama318BInstant
+            | ModelName::Llama3370BVersatile
+            | ModelName::MetaLlama4Maverick17B128E
+            | ModelName::MetaLlama4Scout17B16E
+            | ModelName::MetaLlamaGuard412B
+            | ModelName::QwenQWQ32B
+            | ModelName::Qwen332B => 131_072,
+
+            ModelName::Claude4Sonnet
+            | ModelName::Claude35Sonnet
+            | ModelName::Claude35Haiku
+            | ModelName::Claude3Opus
+            | ModelName::Claude3Sonnet
+            | ModelName::Claude3Haiku => 200_000,
+        }
+    }
+
+    /// Get the maximum number of tokens the model can generate
+    pub fn max_output_tokens(&self) -> u32 {
+        match self {
+            ModelName::MetaLlamaPromptGuard222M | ModelName::MetaLlamaPromptGuard286M => 512,
+            ModelName::Allam27B => 4_096,
+            ModelName::Claude3Opus | ModelName::Claude3Sonnet | ModelName::Claude3Haiku => 4_096,
+            ModelName::Claude35Sonnet | ModelName::Claude35Haiku => 8_192,
+            ModelName::Claude4Sonnet => 64_000,
+            ModelName::MetaLlama4Maverick17B128E | ModelName::MetaLlama4Scout17B16E => 8_192,
+            ModelName::Gemma29BIT
+            | ModelName::Llama370B8192
+            | ModelName::Llama38B8192
+            | ModelName::MetaLlamaGuard412B => 8_192,
+            ModelName::CompoundBeta
+            | ModelName::CompoundBetaMini
+            | ModelName::MistralSaba24B
+            | ModelName::Llama3370BVersatile => 32_768,
+            ModelName::DeepSeekR1DistillLlama70B
+            | ModelName::Llama318BInstant
+            | ModelName::QwenQWQ32B
+            | ModelName::Qwen332B => 131_072,
+        }
+    }
+
+    /// Whether the model answers chat prompts, rather than only classifying them
+    pub fn is_chat(&self) -> bool {
+        !matches!(
+            self,
+            ModelName::MetaLlamaGuard412B
+                | ModelName::MetaLlamaPromptGuard222M
+                | ModelName::MetaLlamaPromptGuard286M
+        )
+    }
+
     /// Get the provider this model belongs to
     pub fn provider(&self) -> AiProvider {
         match self {
@@ -193,6 +269,48 @@ impl fmt::Display for ModelName {
     }
 }
 
+/// A model on a provider, with the limits convcom needs to prompt it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Model {
+    pub provider: AiProvider,
+    /// Model name sent to the provider's API
+    pub id: String,
+    /// Context window size in tokens
+    pub context_window: u32,
+    /// Maximum number of tokens the model can generate
+    pub max_output_tokens: u32,
+    /// Most tokens to request for a commit message, from the `max_tokens` setting
+    pub output_limit: u32,
+    /// Whether the model answers chat prompts
+    pub chat: bool,
+}
+
+impl Model {
+    /// Number of tokens to request for a commit message
+    pub fn output_tokens(&self) -> u32 {
+        self.max_output_tokens.min(self.output_limit)
+    }
+}
+
+impl From<ModelName> for Model {
+    fn from(preset: ModelName) -> Self {
+        Self {
+            provider: preset.provider(),
+            id: preset.as_str().to_string(),
+            context_window: preset.context_window(),
+            max_output_tokens: preset.max_output_tokens(),
+            output_limit: MAX_OUTPUT_TOKENS,
+            chat: preset.is_chat(),
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -204,10 +322,33 @@ mod tests {
         assert_eq!(ModelName::Claude3Haiku.provider(), AiProvider::Anthropic);
     }
 
+    #[test]
+    fn test_context_window() {
+        assert_eq!(ModelName::Llama3370BVersatile.context_window(), 131_072);
+        assert_eq!(ModelName::Llama38B8192.context_window(), 8_192);
+        assert_eq!(ModelName::Claude35Haiku.context_window(), 200_000);
+    }
+
+    #[test]
+    fn test_model_from_preset() {
+        let model = Model::from(ModelName::Claude35Haiku);
+        assert_eq!(model.provider, AiProvider::Anthropic);
+        assert_eq!(model.id, "claude-3-5-haiku-20241022");
+        assert_eq!(model.output_tokens(), MAX_OUTPUT_TOKENS);
+        assert!(model.chat);
+        assert!(!Model::from(ModelName::MetaLlamaPromptGuard222M).chat);
+        assert_eq!(
+            Model::from(ModelName::MetaLlamaPromptGuard222M).output_tokens(),
+            512
+        );
+    }
+
     #[test]
     fn test_provider_display() {
         assert_eq!(AiProvider::Groq.to_string(), "groq");
         assert_eq!(AiProvider::Anthropic.to_string(), "anthropic");
+        assert_eq!(AiProvider::OpenAi.to_string(), "openai");
+        assert_eq!(AiProvider::Ollama.to_string(), "ollama");
     }
 
     #[test]
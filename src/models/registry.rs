@@ -0,0 +1,266 @@
+use crate::config::settings::{Config, OPENAI_DEFAULT_MODEL};
+use crate::error::{ConvComError, Result};
+use crate::models::ai::MAX_OUTPUT_TOKENS;
+use crate::models::providers::{AiProvider, Model, ModelName};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Short names for popular presets
+const ALIASES: &[(&str, ModelName)] = &[
+    ("llama", ModelName::Llama3370BVersatile),
+    ("sonnet", ModelName::Claude4Sonnet),
+    ("haiku", ModelName::Claude35Haiku),
+];
+
+/// Context window assumed for unknown models of hosted providers
+const UNKNOWN_CONTEXT_WINDOW: u32 = 8_192;
+
+/// A model the user describes in `models.json`, keyed by the name to use it with
+///
+/// Only the provider is required; the id defaults to the name and the
+/// limits to the provider's defaults.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ModelEntry {
+    pub provider: AiProvider,
+    pub id: Option<String>,
+    pub context_window: Option<u32>,
+    pub max_output_tokens: Option<u32>,
+    pub chat: Option<bool>,
+}
+
+/// Every model known by name: presets, their aliases and the user's models
+pub struct ModelRegistry {
+    models: BTreeMap<String, Model>,
+    /// Context window for Ollama models that are not in the registry
+    ollama_num_ctx: u32,
+    /// Most tokens to request from any model
+    max_tokens: u32,
+}
+
+impl ModelRegistry {
+    /// Build the registry from the presets and the configuration
+    ///
+    /// `ollama` and `openai-compatible` name the models configured for those
+    /// providers. User entries replace built-in ones of the same name.
+    pub fn new(config: &Config) -> Self {
+        let mut registry = Self {
+            models: BTreeMap::new(),
+            ollama_num_ctx: config.ollama.num_ctx,
+            max_tokens: config.max_tokens,
+        };
+
+        for preset in ModelName::value_variants() {
+            registry
+                .models
+                .insert(preset.as_str().to_string(), Model::from(*preset));
+        }
+        for (alias, preset) in ALIASES {
+            registry
+                .models
+                .insert(alias.to_string(), Model::from(*preset));
+        }
+
+        let openai_model = config
+            .openai
+            .as_ref()
+            .map_or(OPENAI_DEFAULT_MODEL, |settings| settings.model.as_str());
+        let openai_model = registry.unknown(AiProvider::OpenAi, openai_model);
+        registry
+            .models
+            .insert("openai-compatible".to_string(), openai_model);
+        let ollama_model = registry.unknown(AiProvider::Ollama, &config.ollama.model);
+        registry.models.insert("ollama".to_string(), ollama_model);
+
+        for (name, entry) in &config.models {
+            let defaults = registry.lookup(entry.provider, entry.id.as_deref().unwrap_or(name));
+            let model = Model {
+                context_window: entry.context_window.unwrap_or(defaults.context_window),
+                max_output_tokens: entry
+                    .max_output_tokens
+                    .unwrap_or(defaults.max_output_tokens),
+                chat: entry.chat.unwrap_or(defaults.chat),
+                ..defaults
+            };
+            registry.models.insert(name.clone(), model);
+        }
+
+        registry
+    }
+
+    /// Find the model for a name from the registry or a `provider:model` spec
+    ///
+    /// A spec naming a registered model keeps its limits; any other model
+    /// gets the provider's defaults. Model ids may contain `:` themselves.
+    /// Models without a known output limit may generate up to `max_tokens`.
+    pub fn resolve(&self, spec: &str) -> Result<Model> {
+        let model = match self.models.get(spec) {
+            Some(model) => model.clone(),
+            None => {
+                let (provider, id) = spec
+                    .split_once(':')
+                    .and_then(|(provider, id)| {
+                        let provider = AiProvider::from_str(provider, true).ok()?;
+                        Some((provider, id)).filter(|(_, id)| !id.is_empty())
+                    })
+                    .ok_or_else(|| {
+                        ConvComError::ConfigError(format!(
+                            "Unknown model '{spec}'. Use provider:model (e.g. groq:{}) \
+                             or a name listed by 'convcom models'.",
+                            ModelName::default()
+                        ))
+                    })?;
+
+                self.lookup(provider, id)
+            }
+        };
+
+        if !model.chat {
+            return Err(ConvComError::ConfigError(format!(
+                "Model '{spec}' is not a chat model and cannot write commit messages"
+            )));
+        }
+        Ok(Model {
+            output_limit: self.max_tokens,
+            ..model
+        })
+    }
+
+    /// The registered chat models by name
+    pub fn models(&self) -> impl Iterator<Item = (&str, &Model)> {
+        self.models
+            .iter()
+            .filter(|(_, model)| model.chat)
+            .map(|(name, model)| (name.as_str(), model))
+    }
+
+    /// The registered model with this id, or one with the provider's defaults
+    fn lookup(&self, provider: AiProvider, id: &str) -> Model {
+        self.models
+            .values()
+            .find(|model| model.provider == provider && model.id == id)
+            .cloned()
+            .unwrap_or_else(|| self.unknown(provider, id))
+    }
+
+    /// A model the registry knows nothing about, with the provider's defaults
+    fn unknown(&self, provider: AiProvider, id: &str) -> Model {
+        let context_window = match provider {
+            AiProvider::Anthropic => 200_000,
+            AiProvider::Ollama => self.ollama_num_ctx,
+            AiProvider::Groq | AiProvider::OpenAi => UNKNOWN_CONTEXT_WINDOW,
+        };
+
+        Model {
+            provider,
+            id: id.to_string(),
+            context_window,
+            max_output_tokens: self.max_tokens.max(MAX_OUTPUT_TOKENS),
+            output_limit: MAX_OUTPUT_TOKENS,
+            chat: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::OllamaSettings;
+
+    fn config(models: &str) -> Config {
+        Config {
+            ollama: OllamaSettings {
+                model: "qwen2.5-coder:7b".to_string(),
+                num_ctx: 32_768,
+                ..OllamaSettings::default()
+            },
+            models: serde_json::from_str(models).unwrap(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_presets_and_aliases() {
+        let registry = ModelRegistry::new(&config("{}"));
+
+        let preset = registry.resolve("llama-3.3-70b-versatile").unwrap();
+        assert_eq!(preset, Model::from(ModelName::Llama3370BVersatile));
+        assert_eq!(
+            registry.resolve("haiku").unwrap(),
+            Model::from(ModelName::Claude35Haiku)
+        );
+
+        let ollama = registry.resolve("ollama").unwrap();
+        assert_eq!(ollama.provider, AiProvider::Ollama);
+        assert_eq!(ollama.id, "qwen2.5-coder:7b");
+        assert_eq!(ollama.context_window, 32_768);
+        assert_eq!(
+            registry.resolve("openai-compatible").unwrap().id,
+            "gpt-4o-mini"
+        );
+    }
+
+    #[test]
+    fn test_resolve_provider_specs() {
+        let registry = ModelRegistry::new(&config("{}"));
+
+        let known = registry.resolve("groq:llama-3.1-8b-instant").unwrap();
+        assert_eq!(known, Model::from(ModelName::Llama318BInstant));
+
+        let new_model = registry.resolve("Anthropic:claude-opus-4-1").unwrap();
+        assert_eq!(new_model.provider, AiProvider::Anthropic);
+        assert_eq!(new_model.id, "claude-opus-4-1");
+        assert_eq!(new_model.context_window, 200_000);
+
+        let local = registry.resolve("ollama:llama3.2:3b").unwrap();
+        assert_eq!(local.id, "llama3.2:3b");
+        assert_eq!(local.context_window, 32_768);
+
+        for spec in ["no-such-model", "mistral:large", "groq:"] {
+            let error = registry.resolve(spec).unwrap_err().to_string();
+            assert!(error.contains("Unknown model"), "{spec}: {error}");
+        }
+        let error = registry.resolve("groq:meta-llama/llama-prompt-guard-2-22m");
+        assert!(error.unwrap_err().to_string().contains("not a chat model"));
+    }
+
+    #[test]
+    fn test_user_models() {
+        let registry = ModelRegistry::new(&config(
+            r#"{
+                "fast": { "provider": "groq", "id": "llama-3.1-8b-instant" },
+                "gpt-4.1": { "provider": "openai", "context_window": 1047576, "max_output_tokens": 32768 },
+                "embed": { "provider": "ollama", "id": "nomic-embed-text", "chat": false }
+            }"#,
+        ));
+
+        assert_eq!(
+            registry.resolve("fast").unwrap(),
+            Model::from(ModelName::Llama318BInstant)
+        );
+
+        let gpt = registry.resolve("gpt-4.1").unwrap();
+        assert_eq!(gpt.provider, AiProvider::OpenAi);
+        assert_eq!(gpt.id, "gpt-4.1");
+        assert_eq!(gpt.context_window, 1_047_576);
+        assert_eq!(gpt.output_tokens(), MAX_OUTPUT_TOKENS);
+        assert_eq!(registry.resolve("openai:gpt-4.1").unwrap(), gpt);
+
+        assert!(registry.resolve("embed").is_err());
+        assert!(registry.models().all(|(name, _)| name != "embed"));
+    }
+
+    #[test]
+    fn test_max_tokens() {
+        let registry = ModelRegistry::new(&Config {
+            max_tokens: 16_384,
+            ..config("{}")
+        });
+
+        assert_eq!(registry.resolve("haiku").unwrap().output_tokens(), 8_192);
+        assert_eq!(
+            registry.resolve("ollama:qwen3").unwrap().output_tokens(),
+            16_384
+        );
+    }
+}
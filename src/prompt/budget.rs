@@ -0,0 +1,179 @@
+use std::path::Path;
+
+/// Conservative characters-per-token ratio for source code
+const CHARS_PER_TOKEN: usize = 3;
+
+/// Lock files produced by package managers
+pub const LOCK_FILES: &[&str] = &[
+    "Cargo.lock",
+    "package-lock.json",
+    "npm-shrinkwrap.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "bun.lockb",
+    "poetry.lock",
+    "Pipfile.lock",
+    "uv.lock",
+    "Gemfile.lock",
+    "composer.lock",
+    "go.sum",
+    "flake.lock",
+    "mix.lock",
+    "pubspec.lock",
+    "Podfile.lock",
+    "packages.lock.json",
+];
+
+/// Estimate the number of tokens a piece of text will use
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Order in which files receive a share of the token budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FilePriority {
+    /// Application and library code, docs and configuration
+    Source,
+    /// Test code and fixtures
+    Test,
+    /// Lock files, minified assets and other generated output
+    Generated,
+}
+
+impl FilePriority {
+    /// Classify a repository path by its name and location
+    pub fn for_path(path: &str) -> Self {
+        let file_name = Path::new(path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(path);
+        let directories: Vec<&str> = path.split('/').rev().skip(1).collect();
+
+        let generated = LOCK_FILES.contains(&file_name)
+            || [".min.js", ".min.css", ".map", ".pb.go", "_pb2.py"]
+                .iter()
+                .any(|suffix| file_name.ends_with(suffix))
+            || file_name.contains(".generated.")
+            || file_name.contains("_generated.")
+            || directories
+                .iter()
+                .any(|dir| matches!(*dir, "generated" | "vendor" | "node_modules" | "dist"));
+        if generated {
+            return FilePriority::Generated;
+        }
+
+        let test = directories
+            .iter()
+            .any(|dir| matches!(*dir, "test" | "tests" | "__tests__" | "spec" | "testdata"))
+            || file_name.starts_with("test_")
+            || [
+                "_test.", ".test.", "_spec.", ".spec.", "_tests.", "Test.", "Tests.",
+            ]
+            .iter()
+            .any(|marker| file_name.contains(marker));
+        if test {
+            return FilePriority::Test;
+        }
+
+        FilePriority::Source
+    }
+}
+
+/// Split a token budget across files, highest priority first
+///
+/// Each entry is a file's priority and the tokens it needs to be shown in
+/// full. Within a priority tier the remaining budget is shared evenly, and
+/// whatever small files do not use is passed on to larger ones. Lower tiers
+/// only receive what the higher tiers left over.
+pub fn allocate(needs: &[(FilePriority, usize)], budget: usize) -> Vec<usize> {
+    let mut grants = vec![0; needs.len()];
+    let mut remaining = budget;
+
+    for priority in [
+        FilePriority::Source,
+        FilePriority::Test,
+        FilePriority::Generated,
+    ] {
+        let mut tier: Vec<usize> = (0..needs.len())
+            .filter(|&index| needs[index].0 == priority)
+            .collect();
+        tier.sort_by_key(|&index| needs[index].1);
+
+        let mut files_left = tier.len();
+        for index in tier {
+            let grant = needs[index].1.min(remaining / files_left);
+            grants[index] = grant;
+            remaining -= grant;
+            files_left -= 1;
+        }
+    }
+
+    grants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("abc"), 1);
+        assert_eq!(estimate_tokens("abcd"), 2);
+    }
+
+    #[test]
+    fn test_file_priority() {
+        assert_eq!(FilePriority::for_path("src/main.rs"), FilePriority::Source);
+        assert_eq!(FilePriority::for_path("README.md"), FilePriority::Source);
+        assert_eq!(FilePriority::for_path("tests/cli.rs"), FilePriority::Test);
+        assert_eq!(
+            FilePriority::for_path("app/user_test.go"),
+            FilePriority::Test
+        );
+        assert_eq!(
+            FilePriority::for_path("web/button.spec.ts"),
+            FilePriority::Test
+        );
+        assert_eq!(FilePriority::for_path("test_parser.py"), FilePriority::Test);
+        assert_eq!(
+            FilePriority::for_path("Cargo.lock"),
+            FilePriority::Generated
+        );
+        assert_eq!(
+            FilePriority::for_path("web/package-lock.json"),
+            FilePriority::Generated
+        );
+        assert_eq!(
+            FilePriority::for_path("static/app.min.js"),
+            FilePriority::Generated
+        );
+    }
+
+    #[test]
+    fn test_allocate_everything_fits() {
+        let needs = [(FilePriority::Source, 10), (FilePriority::Test, 20)];
+        assert_eq!(allocate(&needs, 100), vec![10, 20]);
+    }
+
+    #[test]
+    fn test_allocate_by_priority() {
+        let needs = [
+            (FilePriority::Generated, 50),
+            (FilePriority::Test, 50),
+            (FilePriority::Source, 60),
+        ];
+        assert_eq!(allocate(&needs, 100), vec![0, 40, 60]);
+    }
+
+    #[test]
+    fn test_allocate_shares_leftovers_within_tier() {
+        let needs = [
+            (FilePriority::Source, 500),
+            (FilePriority::Source, 10),
+            (FilePriority::Source, 500),
+        ];
+        // The small file is shown in full, the large ones split the rest
+        assert_eq!(allocate(&needs, 110), vec![50, 10, 50]);
+    }
+}
@@ -1,16 +1,38 @@
 use crate::error::{ConvComError, Result};
+use crate::models::ai::MAX_OUTPUT_TOKENS;
+use crate::models::providers::Model;
+use crate::prompt::budget::estimate_tokens;
+use crate::services::lint::LintRules;
 use std::collections::HashMap;
 
+/// Room kept for a correction request: the rejected answer plus the violations
+const CORRECTION_RESERVE_TOKENS: usize = MAX_OUTPUT_TOKENS as usize + 256;
+
 /// Professional prompt building system for conventional commit generation using templates
 pub struct PromptBuilder {
     template: String,
+    rules: LintRules,
 }
 
 impl PromptBuilder {
     /// Initialize the prompt builder with the commit template
     pub fn new() -> Result<Self> {
         let template = Self::load_template()?;
-        Ok(Self { template })
+        Ok(Self {
+            template,
+            rules: LintRules::default(),
+        })
+    }
+
+    /// Ask for messages following the configured types, scopes and header length
+    pub fn with_rules(mut self, rules: LintRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// The rules generated messages are checked against
+    pub fn rules(&self) -> &LintRules {
+        &self.rules
     }
 
     /// Load the commit template from embedded resource or file
@@ -50,13 +72,87 @@ impl PromptBuilder {
         Ok(result)
     }
 
+    /// Ask the model to fix a message that broke the Conventional Commits rules
+    ///
+    /// The original prompt is repeated with the rejected answer and the
+    /// list of violations appended.
+    pub fn build_correction_prompt(
+        &self,
+        prompt: &str,
+        rejected: &str,
+        violations: &[String],
+    ) -> String {
+        let violations = violations
+            .iter()
+            .map(|violation| format!("- {violation}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        format!(
+            "{prompt}\n\n### YOUR PREVIOUS ANSWER WAS REJECTED\n\
+             <<REJECTED_ANSWER_START>>\n{}\n<<REJECTED_ANSWER_END>>\n\n\
+             It breaks these rules (line numbers refer to the rejected answer):\n{violations}\n\n\
+             Fix every problem. Output ONLY the corrected commit message: no preamble, \
+             no explanation and no code fences.\n\n### Corrected commit message:\n",
+            rejected.trim_end()
+        )
+    }
+
+    /// Get the number of tokens left for the diff in the model's context window
+    ///
+    /// Accounts for the template, the focus message, the tokens reserved
+    /// for the generated commit message and room for a correction request.
+    pub fn diff_token_budget(&self, model: &Model, focus_message: Option<&str>) -> usize {
+        let overhead = estimate_tokens(&self.template)
+            + estimate_tokens(&self.build_focus_section(focus_message))
+            + estimate_tokens(&self.build_focus_reminder(focus_message))
+            + model.output_tokens() as usize
+            + CORRECTION_RESERVE_TOKENS;
+
+        (model.context_window as usize).saturating_sub(overhead)
+    }
+
     /// Build the focus section for the prompt header
+    ///
+    /// Rules that differ from the defaults are listed here too, as the
+    /// template only describes the standard types.
     fn build_focus_section(&self, focus_message: Option<&str>) -> String {
+        let rules = self.build_rules_section();
         match focus_message {
             Some(message) => format!(
-                "\n🚨 CRITICAL USER REQUIREMENT 🚨\n{message}\n🚨 THIS MUST BE APPLIED TO YOUR OUTPUT 🚨\n\n"
+                "{rules}\n🚨 CRITICAL USER REQUIREMENT 🚨\n{message}\n🚨 THIS MUST BE APPLIED TO YOUR OUTPUT 🚨\n\n"
             ),
-            None => String::new(),
+            None => rules,
+        }
+    }
+
+    /// Describe the project's commit rules where they differ from the defaults
+    fn build_rules_section(&self) -> String {
+        let defaults = LintRules::default();
+        let mut lines = Vec::new();
+        if self.rules.types != defaults.types && !self.rules.types.is_empty() {
+            lines.push(format!(
+                "- The type MUST be one of: {}",
+                self.rules.types.join(", ")
+            ));
+        }
+        if !self.rules.scopes.is_empty() {
+            lines.push(format!(
+                "- A scope, if any, MUST be one of: {}",
+                self.rules.scopes.join(", ")
+            ));
+        }
+        if self.rules.max_header_length != defaults.max_header_length {
+            lines.push(format!(
+                "- The header MUST be at most {} characters",
+                self.rules.max_header_length
+            ));
+        }
+
+        if lines.is_empty() {
+            String::new()
+        } else {
+            format!("\nPROJECT COMMIT RULES:\n{}\n\n", lines.join("\n"))
         }
     }
 
@@ -74,6 +170,7 @@ impl PromptBuilder {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::providers::ModelName;
 
     #[test]
     fn test_prompt_builder_creation() {
@@ -125,6 +222,27 @@ mod tests {
         assert!(result.contains("🚨 CRITICAL USER REQUIREMENT 🚨"));
     }
 
+    #[test]
+    fn test_rules_section() {
+        let builder = PromptBuilder::new().unwrap().with_rules(LintRules {
+            types: vec!["feat".to_string(), "fix".to_string()],
+            scopes: vec!["api".to_string()],
+            ..LintRules::default()
+        });
+
+        let section = builder.build_focus_section(Some("be brief"));
+        assert!(section.contains("type MUST be one of: feat, fix"));
+        assert!(section.contains("scope, if any, MUST be one of: api"));
+        assert!(!section.contains("characters"));
+        assert!(section.contains("be brief"));
+        assert!(
+            builder.diff_token_budget(&ModelName::Llama38B8192.into(), None)
+                < PromptBuilder::new()
+                    .unwrap()
+                    .diff_token_budget(&ModelName::Llama38B8192.into(), None)
+        );
+    }
+
     #[test]
     fn test_focus_reminder_generation() {
         let builder = PromptBuilder::new().unwrap();
@@ -140,6 +258,45 @@ mod tests {
         assert!(result.contains("🚨 REMINDER: APPLY THIS REQUIREMENT"));
     }
 
+    #[test]
+    fn test_diff_token_budget() {
+        let builder = PromptBuilder::new().unwrap();
+
+        let large = builder.diff_token_budget(&ModelName::Llama3370BVersatile.into(), None);
+        let small = builder.diff_token_budget(&ModelName::Llama38B8192.into(), None);
+        let focused = builder.diff_token_budget(&ModelName::Llama38B8192.into(), Some("be brief"));
+
+        assert!(small > 0 && small < 8_192 - MAX_OUTPUT_TOKENS as usize);
+        assert!(large > small);
+        assert!(focused < small);
+        assert_eq!(
+            builder.diff_token_budget(&ModelName::MetaLlamaPromptGuard222M.into(), None),
+            0
+        );
+    }
+
+    #[test]
+    fn test_build_correction_prompt() {
+        let builder = PromptBuilder::new().unwrap();
+        let prompt = builder.build_prompt("MODIFIED: a.rs", None).unwrap();
+
+        let correction = builder.build_correction_prompt(
+            &prompt,
+            "Here is your commit message:\n\nFeat: add a\n",
+            &[
+                "1: the header must look like 'type(scope): description' [header-format]"
+                    .to_string(),
+            ],
+        );
+
+        assert!(correction.starts_with(&prompt));
+        assert!(correction.contains(
+            "<<REJECTED_ANSWER_START>>\nHere is your commit message:\n\nFeat: add a\n<<REJECTED_ANSWER_END>>"
+        ));
+        assert!(correction.contains("- 1: the header must look like"));
+        assert!(correction.ends_with("### Corrected commit message:\n"));
+    }
+
     #[test]
     fn test_template_contains_required_elements() {
         let builder = PromptBuilder::new().unwrap();
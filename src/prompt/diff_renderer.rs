@@ -0,0 +1,619 @@
+use crate::models::diff::{
+    BinaryInfo, ChangeKind, DiffStyle, ExclusionReason, FileChange, LineKind, describe_mode,
+};
+use crate::prompt::budget::{FilePriority, allocate, estimate_tokens};
+use crate::services::binary::format_size;
+use serde::Serialize;
+
+/// A block of content lines, optionally introduced by a hunk header
+struct Block {
+    header: Option<String>,
+    lines: Vec<String>,
+}
+
+impl Block {
+    fn tokens(&self) -> usize {
+        self.header
+            .iter()
+            .chain(&self.lines)
+            .map(|line| line_tokens(line))
+            .sum()
+    }
+}
+
+/// Content of one file left out of the prompt to stay within the token budget
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Truncation {
+    pub path: String,
+    pub omitted_lines: usize,
+    pub omitted_hunks: usize,
+}
+
+/// A file rendered into fixed header lines, truncatable blocks and trailing lines
+struct RenderedFile {
+    path: String,
+    priority: FilePriority,
+    header: Vec<String>,
+    blocks: Vec<Block>,
+    trailer: Vec<String>,
+}
+
+impl RenderedFile {
+    fn fixed_tokens(&self) -> usize {
+        self.header
+            .iter()
+            .chain(&self.trailer)
+            .map(|line| line_tokens(line))
+            .sum()
+    }
+
+    fn block_tokens(&self) -> usize {
+        self.blocks.iter().map(Block::tokens).sum()
+    }
+
+    /// Flatten into output lines, keeping blocks only while they fit the allowance
+    fn into_lines(self, allowance: Option<usize>) -> (Vec<String>, Option<Truncation>) {
+        let mut output = self.header;
+        let mut remaining = allowance.unwrap_or(usize::MAX);
+        let mut omitted_lines = 0;
+        let mut omitted_hunks = 0;
+
+        for block in self.blocks {
+            if omitted_lines > 0 {
+                omitted_lines += block.lines.len();
+                omitted_hunks += 1;
+                continue;
+            }
+
+            let tokens = block.tokens();
+            if tokens <= remaining {
+                remaining -= tokens;
+                output.extend(block.header);
+                output.extend(block.lines);
+                continue;
+            }
+
+            // Keep as much of the first block that does not fit as possible
+            let header_tokens = block.header.as_deref().map_or(0, line_tokens);
+            let mut kept = Vec::new();
+            if header_tokens < remaining {
+                remaining -= header_tokens;
+                for line in &block.lines {
+                    let tokens = line_tokens(line);
+                    if tokens > remaining {
+                        break;
+                    }
+                    remaining -= tokens;
+                    kept.push(line.clone());
+                }
+            }
+
+            omitted_lines += block.lines.len() - kept.len();
+            omitted_hunks += 1;
+            if !kept.is_empty() {
+                output.extend(block.header);
+                output.extend(kept);
+            }
+        }
+
+        let truncation = (omitted_lines > 0).then(|| {
+            output.push(omission_marker(omitted_lines, omitted_hunks));
+            Truncation {
+                path: self.path,
+                omitted_lines,
+                omitted_hunks,
+            }
+        });
+
+        output.extend(self.trailer);
+        (output, truncation)
+    }
+}
+
+/// Tokens used by a single output line including its newline
+fn line_tokens(line: &str) -> usize {
+    estimate_tokens(line) + 1
+}
+
+/// Describe content left out of the prompt, e.g. `... 340 more lines in 6 hunks omitted`
+fn omission_marker(lines: usize, hunks: usize) -> String {
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+
+    format!(
+        "... {lines} more line{} in {hunks} hunk{} omitted",
+        plural(lines),
+        plural(hunks)
+    )
+}
+
+/// Renders collected file changes into the diff section of the prompt
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DiffRenderer {
+    style: DiffStyle,
+    token_budget: Option<usize>,
+}
+
+impl DiffRenderer {
+    /// Create a renderer for the given diff style without a size limit
+    pub fn new(style: DiffStyle) -> Self {
+        Self {
+            style,
+            token_budget: None,
+        }
+    }
+
+    /// Limit the rendered diff to roughly the given number of tokens
+    pub fn with_token_budget(mut self, tokens: usize) -> Self {
+        self.token_budget = Some(tokens);
+        self
+    }
+
+    /// Render all file changes into a single diff section
+    ///
+    /// With a token budget, every file keeps its header line and the budget
+    /// left after headers is split across file contents by priority. Content
+    /// that does not fit is replaced with a note saying how much was omitted.
+    #[allow(dead_code)]
+    pub fn render(&self, files: &[FileChange]) -> String {
+        self.render_with_truncations(files).0
+    }
+
+    /// Render like `render`, also listing the files whose content was cut short
+    pub fn render_with_truncations(&self, files: &[FileChange]) -> (String, Vec<Truncation>) {
+        let rendered: Vec<RenderedFile> = files.iter().map(|file| self.render_file(file)).collect();
+
+        let allowances: Vec<Option<usize>> = match self.token_budget {
+            Some(budget) => {
+                let fixed: usize = rendered.iter().map(RenderedFile::fixed_tokens).sum();
+                let needs: Vec<(FilePriority, usize)> = rendered
+                    .iter()
+                    .map(|file| (file.priority, file.block_tokens()))
+                    .collect();
+
+                allocate(&needs, budget.saturating_sub(fixed))
+                    .into_iter()
+                    .map(Some)
+                    .collect()
+            }
+            None => vec![None; rendered.len()],
+        };
+
+        let mut lines = Vec::new();
+        let mut truncations = Vec::new();
+        for (file, allowance) in rendered.into_iter().zip(allowances) {
+            let (file_lines, truncation) = file.into_lines(allowance);
+            lines.extend(file_lines);
+            truncations.extend(truncation);
+        }
+
+        (lines.join("\n"), truncations)
+    }
+
+    /// Section label used for a change kind
+    fn kind_label(kind: ChangeKind) -> &'static str {
+        match kind {
+            ChangeKind::Added => "NEW FILE",
+            ChangeKind::Modified => "MODIFIED",
+            ChangeKind::Deleted => "DELETED",
+            ChangeKind::Renamed => "RENAMED",
+            ChangeKind::Copied => "COPIED",
+            ChangeKind::TypeChanged => "TYPE CHANGED",
+        }
+    }
+
+    /// One-line entry with details in parentheses, e.g. `MODIFIED (lockfile, 812 lines): Cargo.lock`
+    fn summary_line(file: &FileChange, details: &str) -> String {
+        let paths = match (&file.kind, &file.old_path) {
+            (ChangeKind::Renamed | ChangeKind::Copied, Some(old_path)) => {
+                format!("{old_path} -> {}", file.path)
+            }
+            _ => file.path.clone(),
+        };
+
+        format!("{} ({details}): {paths}", Self::kind_label(file.kind))
+    }
+
+    /// Summary for a file whose content is left out
+    fn excluded_line(file: &FileChange, reason: ExclusionReason) -> String {
+        let count = file.changed_line_count();
+        let plural = if count == 1 { "" } else { "s" };
+
+        Self::summary_line(file, &format!("{reason}, {count} line{plural}"))
+    }
+
+    /// Summary for a binary file, e.g.
+    /// `MODIFIED (binary, image/png, 1.2 KiB -> 3.4 KiB, 16x16 -> 32x32): logo.png`
+    fn binary_line(file: &FileChange, info: &BinaryInfo) -> String {
+        /// Show both sides of a change, or one value if only one side exists or nothing changed
+        fn before_after(old: Option<String>, new: Option<String>) -> Option<String> {
+            match (old, new) {
+                (Some(old), Some(new)) if old != new => Some(format!("{old} -> {new}")),
+                (old, new) => new.or(old),
+            }
+        }
+        let size = |bytes: Option<u64>| bytes.map(format_size);
+        let dimensions = |size: Option<(u32, u32)>| size.map(|(w, h)| format!("{w}x{h}"));
+
+        let details: Vec<String> = [
+            Some("binary".to_string()),
+            info.mime_type.map(str::to_string),
+            before_after(size(info.old_size), size(info.new_size)),
+            before_after(
+                dimensions(info.old_dimensions),
+                dimensions(info.new_dimensions),
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+
+        Self::summary_line(file, &details.join(", "))
+    }
+
+    /// Render the changes of a single file based on its change kind
+    fn render_file(&self, file: &FileChange) -> RenderedFile {
+        let mut header = Vec::new();
+        let mut blocks = Vec::new();
+        let mut trailer = Vec::new();
+        let file_path = file.path.as_str();
+
+        if let Some(reason) = file.excluded {
+            header.push(Self::excluded_line(file, reason));
+            return RenderedFile {
+                path: file.path.clone(),
+                priority: FilePriority::for_path(file_path),
+                header,
+                blocks,
+                trailer,
+            };
+        }
+
+        if let Some(info) = &file.binary {
+            if file.mode_changed() {
+                header.push(Self::mode_line(file));
+            }
+            header.push(Self::binary_line(file, info));
+            return RenderedFile {
+                path: file.path.clone(),
+                priority: FilePriority::for_path(file_path),
+                header,
+                blocks,
+                trailer,
+            };
+        }
+
+        match file.kind {
+            ChangeKind::Added => {
+                header.push(format!("NEW FILE: {file_path}"));
+                match self.style {
+                    DiffStyle::Compact => {
+                        header.push("COMPLETE CONTENT:".to_string());
+                        blocks.push(Block {
+                            header: None,
+                            lines: file
+                                .changed_lines()
+                                .map(|line| line.content.clone())
+                                .collect(),
+                        });
+                    }
+                    DiffStyle::Unified => blocks = self.content_blocks(file),
+                }
+                trailer.push(String::new());
+            }
+            ChangeKind::Deleted => {
+                header.push(format!("DELETED: {file_path}"));
+            }
+            ChangeKind::Modified => {
+                if file.mode_changed() {
+                    header.push(Self::mode_line(file));
+                }
+
+                blocks = self.content_blocks(file);
+                if !blocks.is_empty() {
+                    header.push(format!("MODIFIED: {file_path}"));
+                    trailer.push(String::new());
+                }
+            }
+            ChangeKind::Renamed | ChangeKind::Copied => {
+                let label = Self::kind_label(file.kind);
+                let old_path = file.old_path.as_deref().unwrap_or("?");
+                let similarity = file
+                    .similarity
+                    .map(|percent| format!(" ({percent}%)"))
+                    .unwrap_or_default();
+
+                header.push(format!("{label}: {old_path} -> {file_path}{similarity}"));
+                if file.mode_changed() {
+                    header.push(Self::mode_line(file));
+                }
+                blocks = self.content_blocks(file);
+                trailer.push(String::new());
+            }
+            ChangeKind::TypeChanged => {
+                header.push(format!(
+                    "TYPE CHANGED: {file_path} ({} -> {})",
+                    describe_mode(file.old_mode),
+                    describe_mode(file.new_mode)
+                ));
+                blocks = self.content_blocks(file);
+                trailer.push(String::new());
+            }
+        }
+
+        RenderedFile {
+            path: file.path.clone(),
+            priority: FilePriority::for_path(file_path),
+            header,
+            blocks,
+            trailer,
+        }
+    }
+
+    /// Format a permission change line
+    fn mode_line(file: &FileChange) -> String {
+        format!(
+            "MODE CHANGED: {} ({:o} -> {:o})",
+            file.path, file.old_mode, file.new_mode
+        )
+    }
+
+    /// Format the content delta of a file in the configured style, one block per hunk
+    fn content_blocks(&self, file: &FileChange) -> Vec<Block> {
+        match self.style {
+            DiffStyle::Compact => Self::compact_blocks(file),
+            DiffStyle::Unified => Self::unified_blocks(file),
+        }
+    }
+
+    /// Only added and removed lines, each prefixed with its sign
+    fn compact_blocks(file: &FileChange) -> Vec<Block> {
+        file.hunks
+            .iter()
+            .map(|hunk| Block {
+                header: None,
+                lines: hunk
+                    .lines
+                    .iter()
+                    .filter_map(|line| match line.kind {
+                        LineKind::Added => Some(format!("+ {}", line.content)),
+                        LineKind::Removed => Some(format!("- {}", line.content)),
+                        LineKind::Context => None,
+                    })
+                    .collect(),
+            })
+            .filter(|block| !block.lines.is_empty())
+            .collect()
+    }
+
+    /// Hunk headers followed by every line with its old and new line numbers
+    fn unified_blocks(file: &FileChange) -> Vec<Block> {
+        let number = |lineno: Option<u32>| lineno.map(|n| n.to_string()).unwrap_or_default();
+
+        file.hunks
+            .iter()
+            .map(|hunk| Block {
+                header: Some(hunk.header.clone()),
+                lines: hunk
+                    .lines
+                    .iter()
+                    .map(|line| {
+                        let sign = match line.kind {
+                            LineKind::Added => '+',
+                            LineKind::Removed => '-',
+                            LineKind::Context => ' ',
+                        };
+                        format!(
+                            "{sign} {:>4} {:>4} | {}",
+                            number(line.old_lineno),
+                            number(line.new_lineno),
+                            line.content
+                        )
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::diff::{DiffHunk, DiffLine};
+
+    fn line(kind: LineKind, old: Option<u32>, new: Option<u32>, content: &str) -> DiffLine {
+        DiffLine {
+            kind,
+            old_lineno: old,
+            new_lineno: new,
+            content: content.to_string(),
+        }
+    }
+
+    fn modified_file() -> FileChange {
+        FileChange {
+            hunks: vec![DiffHunk {
+                old_start: 10,
+                old_lines: 2,
+                new_start: 10,
+                new_lines: 2,
+                header: "@@ -10,2 +10,2 @@ fn main()".to_string(),
+                lines: vec![
+                    line(LineKind::Context, Some(10), Some(10), "    let x = 1;"),
+                    line(LineKind::Removed, Some(11), None, "    let y = 2;"),
+                    line(LineKind::Added, None, Some(11), "    let y = 3;"),
+                ],
+            }],
+            ..FileChange::new(ChangeKind::Modified, "src/main.rs")
+        }
+    }
+
+    #[test]
+    fn test_compact_rendering() {
+        let output = DiffRenderer::new(DiffStyle::Compact).render(&[modified_file()]);
+
+        assert_eq!(
+            output,
+            "MODIFIED: src/main.rs\n-     let y = 2;\n+     let y = 3;\n"
+        );
+    }
+
+    #[test]
+    fn test_unified_rendering() {
+        let output = DiffRenderer::new(DiffStyle::Unified).render(&[modified_file()]);
+
+        assert!(output.contains("MODIFIED: src/main.rs\n@@ -10,2 +10,2 @@ fn main()\n"));
+        assert!(output.contains("    10   10 |     let x = 1;"));
+        assert!(output.contains("-   11      |     let y = 2;"));
+        assert!(output.contains("+        11 |     let y = 3;"));
+    }
+
+    #[test]
+    fn test_new_file_rendering() {
+        let file = FileChange {
+            hunks: vec![DiffHunk {
+                old_start: 0,
+                old_lines: 0,
+                new_start: 1,
+                new_lines: 2,
+                header: "@@ -0,0 +1,2 @@".to_string(),
+                lines: vec![
+                    line(LineKind::Added, None, Some(1), "first"),
+                    line(LineKind::Added, None, Some(2), "second"),
+                ],
+            }],
+            ..FileChange::new(ChangeKind::Added, "notes.txt")
+        };
+
+        let compact = DiffRenderer::default().render(std::slice::from_ref(&file));
+        assert!(compact.contains("NEW FILE: notes.txt\nCOMPLETE CONTENT:\nfirst\nsecond"));
+
+        let unified = DiffRenderer::new(DiffStyle::Unified).render(&[file]);
+        assert!(unified.contains("NEW FILE: notes.txt\n@@ -0,0 +1,2 @@\n+         1 | first"));
+    }
+
+    fn large_file(path: &str, hunks: u32, lines_per_hunk: u32) -> FileChange {
+        FileChange {
+            hunks: (0..hunks)
+                .map(|h| DiffHunk {
+                    old_start: h * 100,
+                    old_lines: 0,
+                    new_start: h * 100,
+                    new_lines: lines_per_hunk,
+                    header: format!("@@ -{0},0 +{0},{lines_per_hunk} @@", h * 100),
+                    lines: (0..lines_per_hunk)
+                        .map(|n| line(LineKind::Added, None, Some(h * 100 + n), "some added code"))
+                        .collect(),
+                })
+                .collect(),
+            ..FileChange::new(ChangeKind::Modified, path)
+        }
+    }
+
+    #[test]
+    fn test_no_budget_renders_everything() {
+        let output = DiffRenderer::default().render(&[large_file("src/lib.rs", 10, 50)]);
+
+        assert_eq!(output.matches("+ some added code").count(), 500);
+        assert!(!output.contains("omitted"));
+    }
+
+    #[test]
+    fn test_budget_truncates_with_marker() {
+        let output = DiffRenderer::default()
+            .with_token_budget(200)
+            .render(&[large_file("src/lib.rs", 4, 20)]);
+
+        let kept = output.matches("+ some added code").count();
+        assert!(kept > 0 && kept < 80);
+        let marker = format!("... {} more lines in ", 80 - kept);
+        assert!(output.contains(&marker), "{output}");
+        assert!(output.ends_with("hunks omitted\n"));
+    }
+
+    #[test]
+    fn test_budget_prefers_source_over_tests_and_lock_files() {
+        let files = [
+            large_file("Cargo.lock", 1, 40),
+            large_file("tests/api.rs", 1, 40),
+            large_file("src/api.rs", 1, 40),
+        ];
+        let (output, truncations) = DiffRenderer::default()
+            .with_token_budget(400)
+            .render_with_truncations(&files);
+
+        // Every file keeps its header even when its content is dropped
+        assert!(output.contains("MODIFIED: Cargo.lock\n... 40 more lines in 1 hunk omitted"));
+        assert!(output.contains("MODIFIED: tests/api.rs\n+ "));
+        let source = output.split("MODIFIED: src/api.rs\n").nth(1).unwrap();
+        assert!(source.starts_with("+ some added code"));
+        assert!(!source.contains("omitted"));
+
+        assert_eq!(truncations.len(), 2);
+        assert_eq!(
+            truncations[0],
+            Truncation {
+                path: "Cargo.lock".to_string(),
+                omitted_lines: 40,
+                omitted_hunks: 1,
+            }
+        );
+        assert_eq!(truncations[1].path, "tests/api.rs");
+    }
+
+    #[test]
+    fn test_excluded_file_rendering() {
+        let mut lock = large_file("Cargo.lock", 2, 406);
+        lock.excluded = Some(ExclusionReason::Lockfile);
+        let mut moved = large_file("web/app.min.js", 1, 1);
+        moved.kind = ChangeKind::Renamed;
+        moved.old_path = Some("app.min.js".to_string());
+        moved.excluded = Some(ExclusionReason::Minified);
+
+        let output = DiffRenderer::new(DiffStyle::Unified).render(&[lock, moved]);
+
+        assert_eq!(
+            output,
+            "MODIFIED (lockfile, 812 lines): Cargo.lock\n\
+             RENAMED (minified, 1 line): app.min.js -> web/app.min.js"
+        );
+    }
+
+    #[test]
+    fn test_binary_file_rendering() {
+        let logo = FileChange {
+            binary: Some(BinaryInfo {
+                old_size: Some(1229),
+                new_size: Some(3482),
+                mime_type: Some("image/png"),
+                old_dimensions: Some((16, 16)),
+                new_dimensions: Some((32, 32)),
+            }),
+            ..FileChange::new(ChangeKind::Modified, "logo.png")
+        };
+        let archive = FileChange {
+            binary: Some(BinaryInfo {
+                new_size: Some(812),
+                mime_type: Some("application/zip"),
+                ..BinaryInfo::default()
+            }),
+            ..FileChange::new(ChangeKind::Added, "fixtures.zip")
+        };
+
+        let output = DiffRenderer::default().render(&[logo, archive]);
+
+        assert_eq!(
+            output,
+            "MODIFIED (binary, image/png, 1.2 KiB -> 3.4 KiB, 16x16 -> 32x32): logo.png\n\
+             NEW FILE (binary, application/zip, 812 B): fixtures.zip"
+        );
+    }
+
+    #[test]
+    fn test_omission_marker() {
+        assert_eq!(omission_marker(1, 1), "... 1 more line in 1 hunk omitted");
+        assert_eq!(
+            omission_marker(340, 6),
+            "... 340 more lines in 6 hunks omitted"
+        );
+    }
+}
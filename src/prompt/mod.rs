@@ -1 +1,3 @@
+pub mod budget;
 pub mod builder;
+pub mod diff_renderer;
@@ -1,8 +1,22 @@
+use crate::config::settings::Config;
 use crate::error::{ConvComError, Result};
-use crate::models::providers::{AiProvider, ModelName};
-use crate::services::providers::{AiProviderTrait, create_provider};
+use crate::models::ai::Completion;
+use crate::models::providers::{AiProvider, Model, ModelName};
+use crate::services::providers::{
+    AiProviderTrait, AnthropicProvider, GroqProvider, OllamaProvider, OpenAiCompatibleProvider,
+};
+use crate::services::stream::TokenSink;
 use anyhow::Result as AnyhowResult;
 
+/// A completion with the model that wrote it
+#[derive(Debug)]
+pub struct Answer {
+    pub model: Model,
+    pub completion: Completion,
+    /// Models tried before, with the error that made each hand over
+    pub fallbacks: Vec<(Model, ConvComError)>,
+}
+
 /// AI service for generating commit messages using multiple providers
 pub struct AiService {
     providers: std::collections::HashMap<AiProvider, Box<dyn AiProviderTrait>>,
@@ -11,26 +25,48 @@ pub struct AiService {
 impl AiService {
     /// Create a new AI service instance with provider support
     pub fn new(groq_api_key: Option<String>, anthropic_api_key: Option<String>) -> Result<Self> {
-        let mut providers = std::collections::HashMap::new();
+        Self::from_config(&Config {
+            groq_api_key: groq_api_key.map(Into::into),
+            anthropic_api_key: anthropic_api_key.map(Into::into),
+            ..Config::default()
+        })
+    }
+
+    /// Create a new AI service instance with every provider set up in the configuration
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let mut providers: std::collections::HashMap<AiProvider, Box<dyn AiProviderTrait>> =
+            std::collections::HashMap::new();
 
         // Add Groq provider if API key is provided
-        if let Some(key) = groq_api_key {
-            let provider = create_provider(AiProvider::Groq, key)?;
-            providers.insert(AiProvider::Groq, provider);
+        if let Some(key) = &config.groq_api_key {
+            let provider = GroqProvider::new(key.clone())?
+                .with_temperature(config.temperature)
+                .with_retry_policy(config.retry);
+            providers.insert(AiProvider::Groq, Box::new(provider));
         }
 
         // Add Anthropic provider if API key is provided
-        if let Some(key) = anthropic_api_key {
-            let provider = create_provider(AiProvider::Anthropic, key)?;
-            providers.insert(AiProvider::Anthropic, provider);
+        if let Some(key) = &config.anthropic_api_key {
+            let provider = AnthropicProvider::new(key.clone())?
+                .with_temperature(config.temperature)
+                .with_retry_policy(config.retry);
+            providers.insert(AiProvider::Anthropic, Box::new(provider));
         }
 
-        if providers.is_empty() {
-            return Err(ConvComError::ConfigError(
-                "At least one AI provider API key must be provided".to_string(),
-            ));
+        // Add the OpenAI-compatible provider if an endpoint is configured
+        if let Some(settings) = &config.openai {
+            let provider = OpenAiCompatibleProvider::new(settings.clone())?
+                .with_temperature(config.temperature)
+                .with_retry_policy(config.retry);
+            providers.insert(AiProvider::OpenAi, Box::new(provider));
         }
 
+        // Ollama needs no key; requests fail later if no daemon is running
+        let provider = OllamaProvider::new(config.ollama.clone())?
+            .with_temperature(config.temperature)
+            .with_retry_policy(config.retry);
+        providers.insert(AiProvider::Ollama, Box::new(provider));
+
         Ok(Self { providers })
     }
 
@@ -40,22 +76,91 @@ impl AiService {
         Self::new(Some(api_key), None)
     }
 
-    /// Generate a commit message using the specified model
+    /// Generate a commit message with the first model in `models` that answers
+    ///
+    /// A model that is rate limited, overloaded, timing out or unreachable
+    /// hands over to the next one. Any other error, such as a rejected API
+    /// key, is returned without trying the rest.
     pub async fn generate_commit_message(
         &self,
         prompt: String,
-        model: ModelName,
-    ) -> AnyhowResult<String> {
-        let provider_type = model.provider();
+        models: &[Model],
+    ) -> AnyhowResult<Answer> {
+        self.answer(prompt, models, None).await
+    }
+
+    /// Like `generate_commit_message`, passing the message to `sink` as it is written
+    ///
+    /// The sink is restarted when a model hands over to the next one.
+    pub async fn stream_commit_message(
+        &self,
+        prompt: String,
+        models: &[Model],
+        sink: &mut dyn TokenSink,
+    ) -> AnyhowResult<Answer> {
+        self.answer(prompt, models, Some(sink)).await
+    }
+
+    async fn answer(
+        &self,
+        prompt: String,
+        models: &[Model],
+        mut sink: Option<&mut dyn TokenSink>,
+    ) -> AnyhowResult<Answer> {
+        let mut fallbacks = Vec::new();
+        for (index, model) in models.iter().enumerate() {
+            let result = match sink.as_deref_mut() {
+                Some(sink) => {
+                    if index > 0 {
+                        sink.restart();
+                    }
+                    self.stream(prompt.clone(), model, sink).await
+                }
+                None => self.complete(prompt.clone(), model).await,
+            };
+            match result {
+                Ok(completion) => {
+                    return Ok(Answer {
+                        model: model.clone(),
+                        completion,
+                        fallbacks,
+                    });
+                }
+                Err(e) if e.is_retryable() && index + 1 < models.len() => {
+                    fallbacks.push((model.clone(), e));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Err(ConvComError::ConfigError("No model to generate the message with".to_string()).into())
+    }
+
+    /// Generate a commit message along with the token usage reported by the provider
+    pub async fn complete(&self, prompt: String, model: &Model) -> Result<Completion> {
+        self.provider(model)?.generate_message(prompt, model).await
+    }
+
+    /// Like `complete`, passing the message to `sink` as it is written
+    pub async fn stream(
+        &self,
+        prompt: String,
+        model: &Model,
+        sink: &mut dyn TokenSink,
+    ) -> Result<Completion> {
+        self.provider(model)?
+            .stream_message(prompt, model, sink)
+            .await
+    }
 
+    fn provider(&self, model: &Model) -> Result<&dyn AiProviderTrait> {
+        let provider_type = model.provider;
         let provider = self.providers.get(&provider_type).ok_or_else(|| {
             ConvComError::ConfigError(format!(
                 "Provider {provider_type} is not configured. Please provide API key for this provider."
             ))
         })?;
-
-        let result = provider.generate_message(prompt, model).await?;
-        Ok(result)
+        Ok(provider.as_ref())
     }
 
     /// Get list of available providers
@@ -106,9 +211,178 @@ impl AiService {
                         ModelName::Claude3Haiku,
                     ]);
                 }
+                // These serve whatever models the endpoint has
+                AiProvider::OpenAi | AiProvider::Ollama => {}
             }
         }
 
         models
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::settings::{OllamaSettings, OpenAiSettings};
+    use crate::models::ai::MAX_OUTPUT_TOKENS;
+    use crate::services::retry::RetryPolicy;
+
+    fn model(provider: AiProvider, id: &str) -> Model {
+        Model {
+            provider,
+            id: id.to_string(),
+            context_window: 8_192,
+            max_output_tokens: MAX_OUTPUT_TOKENS,
+            output_limit: MAX_OUTPUT_TOKENS,
+            chat: true,
+        }
+    }
+
+    async fn service_with_openai_status(status: usize) -> (mockito::ServerGuard, AiService) {
+        service_with_openai_response(status, r#"{"error":{"message":"nope"}}"#).await
+    }
+
+    async fn service_with_openai_response(
+        status: usize,
+        body: &str,
+    ) -> (mockito::ServerGuard, AiService) {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_status(status)
+            .with_body(body)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/api/chat")
+            .with_body(r#"{"message":{"content":"fix: retry"},"done":true}"#)
+            .create_async()
+            .await;
+
+        let service = AiService::from_config(&Config {
+            openai: Some(OpenAiSettings {
+                base_url: format!("{}/v1", server.url()),
+                ..OpenAiSettings::openai("key".into())
+            }),
+            ollama: OllamaSettings {
+                host: server.url(),
+                ..OllamaSettings::default()
+            },
+            // Hand over at once so the fallback is what gets tested
+            retry: RetryPolicy::none(),
+            ..Config::default()
+        })
+        .unwrap();
+        (server, service)
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_on_rate_limit() {
+        let (_server, service) = service_with_openai_status(429).await;
+        let chain = [
+            model(AiProvider::OpenAi, "gpt-4o-mini"),
+            model(AiProvider::Ollama, "qwen3"),
+        ];
+
+        let answer = service
+            .generate_commit_message("diff".to_string(), &chain)
+            .await
+            .unwrap();
+
+        assert_eq!(answer.model, chain[1]);
+        assert_eq!(answer.completion.content, "fix: retry");
+        assert_eq!(answer.fallbacks.len(), 1);
+        assert_eq!(answer.fallbacks[0].0, chain[0]);
+        assert!(answer.fallbacks[0].1.to_string().contains("429"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_restarts_on_fallback() {
+        #[derive(Default)]
+        struct Recorder {
+            text: String,
+            restarts: u32,
+        }
+        impl TokenSink for Recorder {
+            fn token(&mut self, text: &str) {
+                self.text.push_str(text);
+            }
+            fn restart(&mut self) {
+                self.text.clear();
+                self.restarts += 1;
+            }
+        }
+
+        let (_server, service) = service_with_openai_status(503).await;
+        let chain = [
+            model(AiProvider::OpenAi, "gpt-4o-mini"),
+            model(AiProvider::Ollama, "qwen3"),
+        ];
+
+        let mut recorder = Recorder::default();
+        let answer = service
+            .stream_commit_message("diff".to_string(), &chain, &mut recorder)
+            .await
+            .unwrap();
+
+        assert_eq!(answer.model, chain[1]);
+        assert_eq!(recorder.text, answer.completion.content);
+        assert_eq!(recorder.restarts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_no_fallback_on_auth_error() {
+        let (_server, service) = service_with_openai_status(401).await;
+        let chain = [
+            model(AiProvider::OpenAi, "gpt-4o-mini"),
+            model(AiProvider::Ollama, "qwen3"),
+        ];
+
+        let error = service
+            .generate_commit_message("diff".to_string(), &chain)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("401"));
+    }
+
+    #[tokio::test]
+    async fn test_no_fallback_on_invalid_request_in_stream() {
+        let (_server, service) = service_with_openai_response(
+            200,
+            "data: {\"error\":{\"message\":\"bad max_tokens\",\"type\":\"invalid_request_error\"}}\n\n",
+        )
+        .await;
+        let chain = [
+            model(AiProvider::OpenAi, "gpt-4o-mini"),
+            model(AiProvider::Ollama, "qwen3"),
+        ];
+
+        let error = service
+            .stream_commit_message("diff".to_string(), &chain, &mut |_: &str| {})
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("invalid_request_error"));
+    }
+
+    #[test]
+    fn test_retryable_errors() {
+        let api_error = |status_code| ConvComError::ApiError {
+            status_code,
+            message: String::new(),
+        };
+        for status in [408, 429, 502, 503, 504, 529] {
+            assert!(api_error(status).is_retryable(), "{status}");
+        }
+        for status in [400, 401, 403, 404, 500, 501] {
+            assert!(!api_error(status).is_retryable(), "{status}");
+        }
+        let overloaded = ConvComError::ApiError {
+            status_code: 500,
+            message: r#"{"type":"overloaded_error"}"#.to_string(),
+        };
+        assert!(overloaded.is_retryable());
+        assert!(ConvComError::NetworkError("timed out".to_string()).is_retryable());
+        assert!(!ConvComError::ApiRequestError("builder error".to_string()).is_retryable());
+        assert!(!ConvComError::EmptyResponseError.is_retryable());
+    }
+}
@@ -0,0 +1,252 @@
+/// Fallback MIME type for unrecognised binary content
+pub const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// Known file signatures as (offset, magic bytes, MIME type)
+///
+/// Magic values short enough to start unrelated files are checked in
+/// `checked_mime` instead.
+const SIGNATURES: &[(usize, &[u8], &str)] = &[
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (0, b"II*\x00", "image/tiff"),
+    (0, b"MM\x00*", "image/tiff"),
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"\x1f\x8b\x08", "application/gzip"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (0, b"\x7fELF", "application/x-elf"),
+    (0, b"\xcf\xfa\xed\xfe", "application/x-mach-binary"),
+    (0, b"\xce\xfa\xed\xfe", "application/x-mach-binary"),
+    (0, b"\x00asm", "application/wasm"),
+    (0, b"SQLite format 3\x00", "application/vnd.sqlite3"),
+    (0, b"wOFF", "font/woff"),
+    (0, b"wOF2", "font/woff2"),
+    (0, b"OTTO", "font/otf"),
+    (0, b"\x00\x01\x00\x00", "font/ttf"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (4, b"ftyp", "video/mp4"),
+    (257, b"ustar", "application/x-tar"),
+];
+
+/// Guess the MIME type of binary content from its leading magic bytes
+pub fn sniff_mime(content: &[u8]) -> &'static str {
+    if content.len() >= 12 && &content[..4] == b"RIFF" {
+        match &content[8..12] {
+            b"WEBP" => return "image/webp",
+            b"WAVE" => return "audio/wav",
+            b"AVI " => return "video/x-msvideo",
+            _ => {}
+        }
+    }
+    if let Some(mime) = checked_mime(content) {
+        return mime;
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|(offset, magic, _)| content.get(*offset..offset + magic.len()) == Some(*magic))
+        .map_or(DEFAULT_MIME_TYPE, |(_, _, mime)| mime)
+}
+
+/// Recognise formats with short magic bytes by the header fields behind them
+fn checked_mime(content: &[u8]) -> Option<&'static str> {
+    let le16 = |at: usize| {
+        Some(u16::from_le_bytes(
+            content.get(at..at + 2)?.try_into().ok()?,
+        ))
+    };
+    let le32 = |at: usize| {
+        Some(u32::from_le_bytes(
+            content.get(at..at + 4)?.try_into().ok()?,
+        ))
+    };
+
+    if content.starts_with(b"BM") {
+        // The size of one of the known DIB headers follows the file header
+        return matches!(le32(14)?, 12 | 40 | 52 | 56 | 64 | 108 | 124).then_some("image/bmp");
+    }
+    if content.starts_with(b"MZ") {
+        // The DOS stub points to the PE header
+        let pe = usize::try_from(le32(0x3c)?).ok()?;
+        return (content.get(pe..pe.checked_add(4)?)? == b"PE\0\0")
+            .then_some("application/vnd.microsoft.portable-executable");
+    }
+    if content.starts_with(b"\x00\x00\x01\x00") {
+        // At least one 16 byte entry, whose image data starts after the directory
+        let count = usize::from(le16(4)?);
+        let first_image = usize::try_from(le32(18)?).ok()?;
+        return (count > 0 && content.get(9) == Some(&0) && first_image >= 6 + 16 * count)
+            .then_some("image/x-icon");
+    }
+    if content.starts_with(b"ID3") {
+        // ID3v2.2 to 2.4, with a tag size of four 7-bit bytes
+        let header = content.get(3..10)?;
+        return (matches!(header[0], 2..=4)
+            && header[1] != 0xff
+            && header[3..].iter().all(|byte| byte & 0x80 == 0))
+        .then_some("audio/mpeg");
+    }
+    None
+}
+
+/// Read the width and height of a PNG, JPEG or GIF image
+pub fn image_dimensions(content: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |at: usize| {
+        Some(u16::from_be_bytes(
+            content.get(at..at + 2)?.try_into().ok()?,
+        ))
+    };
+    let le16 = |at: usize| {
+        Some(u16::from_le_bytes(
+            content.get(at..at + 2)?.try_into().ok()?,
+        ))
+    };
+    let be32 = |at: usize| {
+        Some(u32::from_be_bytes(
+            content.get(at..at + 4)?.try_into().ok()?,
+        ))
+    };
+
+    match sniff_mime(content) {
+        // The IHDR chunk always comes first
+        "image/png" => Some((be32(16)?, be32(20)?)),
+        "image/gif" => Some((u32::from(le16(6)?), u32::from(le16(8)?))),
+        "image/jpeg" => {
+            // Walk the marker segments until a start-of-frame marker
+            let mut position = 2;
+            loop {
+                while *content.get(position)? != 0xff {
+                    position += 1;
+                }
+                while *content.get(position)? == 0xff {
+                    position += 1;
+                }
+                let marker = *content.get(position)?;
+                position += 1;
+
+                match marker {
+                    0xd0..=0xd9 | 0x01 => continue,
+                    0xc0..=0xcf if !matches!(marker, 0xc4 | 0xc8 | 0xcc) => {
+                        let height = be16(position + 3)?;
+                        let width = be16(position + 5)?;
+                        return Some((u32::from(width), u32::from(height)));
+                    }
+                    _ => position += usize::from(be16(position)?),
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Format a byte count for humans, e.g. `812 B` or `12.3 KiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut content = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        content.extend_from_slice(&width.to_be_bytes());
+        content.extend_from_slice(&height.to_be_bytes());
+        content.extend_from_slice(&[8, 6, 0, 0, 0]);
+        content
+    }
+
+    #[test]
+    fn test_sniff_mime() {
+        assert_eq!(sniff_mime(&png(1, 1)), "image/png");
+        assert_eq!(sniff_mime(b"GIF89a\x01\x00\x01\x00"), "image/gif");
+        assert_eq!(sniff_mime(b"%PDF-1.7\n"), "application/pdf");
+        assert_eq!(sniff_mime(b"RIFF\x00\x00\x00\x00WEBPVP8 "), "image/webp");
+        assert_eq!(sniff_mime(b"\x7fELF\x02\x01\x01"), "application/x-elf");
+        assert_eq!(sniff_mime(b"\x00\x01\x02\x03"), DEFAULT_MIME_TYPE);
+        assert_eq!(sniff_mime(b""), DEFAULT_MIME_TYPE);
+    }
+
+    #[test]
+    fn test_short_magic_needs_valid_header() {
+        let mut bmp = b"BM\x46\x00\x00\x00\x00\x00\x00\x00\x36\x00\x00\x00".to_vec();
+        bmp.extend_from_slice(&40u32.to_le_bytes());
+        assert_eq!(sniff_mime(&bmp), "image/bmp");
+        assert_eq!(sniff_mime(b"BMW service records\n"), DEFAULT_MIME_TYPE);
+
+        let mut exe = vec![0; 0x80];
+        exe[..2].copy_from_slice(b"MZ");
+        exe[0x3c] = 0x40;
+        exe[0x40..0x44].copy_from_slice(b"PE\0\0");
+        assert_eq!(
+            sniff_mime(&exe),
+            "application/vnd.microsoft.portable-executable"
+        );
+        exe[0x3c] = 0x60;
+        assert_eq!(sniff_mime(&exe), DEFAULT_MIME_TYPE);
+        assert_eq!(sniff_mime(b"MZ"), DEFAULT_MIME_TYPE);
+
+        let mut ico = b"\x00\x00\x01\x00\x01\x00\x10\x10\x00\x00\x01\x00\x20\x00".to_vec();
+        ico.extend_from_slice(&[0x68, 0x04, 0, 0, 0x16, 0, 0, 0]);
+        assert_eq!(sniff_mime(&ico), "image/x-icon");
+        ico[4] = 0;
+        assert_eq!(sniff_mime(&ico), DEFAULT_MIME_TYPE);
+
+        assert_eq!(sniff_mime(b"ID3\x04\x00\x00\x00\x00\x02\x01"), "audio/mpeg");
+        assert_eq!(sniff_mime(b"ID3: 42, name: x"), DEFAULT_MIME_TYPE);
+    }
+
+    #[test]
+    fn test_png_dimensions() {
+        assert_eq!(image_dimensions(&png(640, 480)), Some((640, 480)));
+        assert_eq!(image_dimensions(&png(640, 480)[..18]), None);
+    }
+
+    #[test]
+    fn test_gif_dimensions() {
+        assert_eq!(
+            image_dimensions(b"GIF89a\x20\x03\x58\x02\x00\x00"),
+            Some((800, 600))
+        );
+    }
+
+    #[test]
+    fn test_jpeg_dimensions() {
+        let mut jpeg = vec![0xff, 0xd8];
+        // APP0 segment that must be skipped
+        jpeg.extend_from_slice(&[0xff, 0xe0, 0x00, 0x06, b'J', b'F', b'I', b'F']);
+        // SOF0 with precision, height 300, width 400
+        jpeg.extend_from_slice(&[0xff, 0xc0, 0x00, 0x11, 0x08, 0x01, 0x2c, 0x01, 0x90]);
+
+        assert_eq!(image_dimensions(&jpeg), Some((400, 300)));
+        assert_eq!(image_dimensions(&jpeg[..12]), None);
+    }
+
+    #[test]
+    fn test_non_image_dimensions() {
+        assert_eq!(image_dimensions(b"%PDF-1.7\n"), None);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(812), "812 B");
+        assert_eq!(format_size(12_595), "12.3 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024), "5.0 MiB");
+    }
+}
@@ -0,0 +1,163 @@
+use crate::error::{ConvComError, Result};
+use crate::models::diff::ExclusionReason;
+use crate::prompt::budget::LOCK_FILES;
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+
+/// Name of the repository-level ignore file
+pub const IGNORE_FILE_NAME: &str = ".convcomignore";
+
+/// File name suffixes of minified assets and source maps
+const MINIFIED_SUFFIXES: &[&str] = &[".min.js", ".min.mjs", ".min.css", ".js.map", ".css.map"];
+
+/// Path based rules deciding which files are summarised instead of shown
+pub struct ExclusionRules {
+    ignore: Gitignore,
+}
+
+impl ExclusionRules {
+    /// Create rules with only the built-in defaults
+    pub fn new() -> Self {
+        Self {
+            ignore: Gitignore::empty(),
+        }
+    }
+
+    /// Load the built-in defaults plus the `.convcomignore` file in `root`, if present
+    pub fn load(root: &Path) -> Result<Self> {
+        Self::load_with_patterns(root, &[])
+    }
+
+    /// Load the rules with extra gitignore patterns from the `exclude` setting
+    ///
+    /// Patterns in `.convcomignore` come later, so they win over the setting.
+    pub fn load_with_patterns(root: &Path, patterns: &[String]) -> Result<Self> {
+        let ignore_file = root.join(IGNORE_FILE_NAME);
+        if patterns.is_empty() && !ignore_file.exists() {
+            return Ok(Self::new());
+        }
+
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in patterns {
+            builder.add_line(None, pattern).map_err(|e| {
+                ConvComError::ConfigError(format!("Invalid exclude pattern '{pattern}': {e}"))
+            })?;
+        }
+        if ignore_file.exists()
+            && let Some(e) = builder.add(&ignore_file)
+        {
+            return Err(ConvComError::ConfigError(format!(
+                "Invalid {IGNORE_FILE_NAME}: {e}"
+            )));
+        }
+        let ignore = builder
+            .build()
+            .map_err(|e| ConvComError::ConfigError(format!("Invalid {IGNORE_FILE_NAME}: {e}")))?;
+
+        Ok(Self { ignore })
+    }
+
+    /// Check a repository-relative path against the ignore file and defaults
+    ///
+    /// A negated pattern such as `!Cargo.lock` in `.convcomignore` keeps a
+    /// file that the defaults would otherwise exclude.
+    pub fn check(&self, path: &str) -> Option<ExclusionReason> {
+        match self.ignore.matched_path_or_any_parents(path, false) {
+            Match::Ignore(_) => return Some(ExclusionReason::Ignored),
+            Match::Whitelist(_) => return None,
+            Match::None => {}
+        }
+
+        let file_name = Path::new(path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(path);
+
+        if LOCK_FILES.contains(&file_name) {
+            Some(ExclusionReason::Lockfile)
+        } else if MINIFIED_SUFFIXES
+            .iter()
+            .any(|suffix| file_name.ends_with(suffix))
+        {
+            Some(ExclusionReason::Minified)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for ExclusionRules {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_rules() {
+        let rules = ExclusionRules::new();
+
+        assert_eq!(rules.check("Cargo.lock"), Some(ExclusionReason::Lockfile));
+        assert_eq!(
+            rules.check("web/package-lock.json"),
+            Some(ExclusionReason::Lockfile)
+        );
+        assert_eq!(
+            rules.check("static/app.min.js"),
+            Some(ExclusionReason::Minified)
+        );
+        assert_eq!(rules.check("src/main.rs"), None);
+        assert_eq!(rules.check("docs/lockfile.md"), None);
+    }
+
+    #[test]
+    fn test_ignore_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(IGNORE_FILE_NAME),
+            "# snapshots are noise\n*.snap\nfixtures/\n!Cargo.lock\n",
+        )
+        .unwrap();
+        let rules = ExclusionRules::load(dir.path()).unwrap();
+
+        assert_eq!(
+            rules.check("tests/snapshots/api.snap"),
+            Some(ExclusionReason::Ignored)
+        );
+        assert_eq!(
+            rules.check("fixtures/data/big.json"),
+            Some(ExclusionReason::Ignored)
+        );
+        assert_eq!(rules.check("Cargo.lock"), None);
+        assert_eq!(rules.check("yarn.lock"), Some(ExclusionReason::Lockfile));
+        assert_eq!(rules.check("src/lib.rs"), None);
+    }
+
+    #[test]
+    fn test_exclude_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(IGNORE_FILE_NAME), "!docs/keep.md\n").unwrap();
+        let patterns = ["docs/*.md".to_string(), "!yarn.lock".to_string()];
+        let rules = ExclusionRules::load_with_patterns(dir.path(), &patterns).unwrap();
+
+        assert_eq!(rules.check("docs/guide.md"), Some(ExclusionReason::Ignored));
+        assert_eq!(rules.check("docs/keep.md"), None);
+        assert_eq!(rules.check("yarn.lock"), None);
+
+        let invalid = ["{broken".to_string()];
+        assert!(ExclusionRules::load_with_patterns(dir.path(), &invalid).is_err());
+    }
+
+    #[test]
+    fn test_missing_ignore_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let rules = ExclusionRules::load(dir.path()).unwrap();
+
+        assert_eq!(rules.check("Cargo.lock"), Some(ExclusionReason::Lockfile));
+        assert_eq!(rules.check("src/lib.rs"), None);
+    }
+}
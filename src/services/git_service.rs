@@ -1,116 +1,372 @@
 use crate::error::{ConvComError, Result};
-use git2::{Repository, Status};
-use std::path::Path;
+use crate::models::diff::{
+    BinaryInfo, ChangeKind, DiffHunk, DiffLine, ExclusionReason, FileChange, LineKind,
+};
+use crate::services::binary::{image_dimensions, sniff_mime};
+use crate::services::exclusions::ExclusionRules;
+use crate::services::{hooks, signing};
+use git2::{
+    AttrCheckFlags, AttrValue, Delta, Diff, DiffDelta, DiffFile, DiffFindOptions, DiffFormat,
+    DiffOptions, ErrorCode, Index, Oid, Repository, Signature, Tree,
+};
+use regex::Regex;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+/// Minimum similarity (in percent) for a file pair to count as a rename or copy
+const SIMILARITY_THRESHOLD: u16 = 50;
+
+/// Default number of unchanged lines kept around each change
+pub const DEFAULT_CONTEXT_LINES: u32 = 3;
+
+/// Bytes read from the start of a binary file to tell its type and dimensions
+const SNIFF_PREFIX_LEN: usize = 64 * 1024;
+
+/// The similarity line in the header of a rename or copy
+static SIMILARITY_INDEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?m)^similarity index (\d+)%").unwrap());
+
+/// The two snapshots a diff compares
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum DiffSource {
+    /// HEAD against the index
+    #[default]
+    Staged,
+    /// The tree of one revision against the tree of another
+    Range { from: String, to: String },
+    /// A commit against its first parent (or an empty tree for a root commit)
+    Commit(String),
+    /// HEAD against tracked files in the working tree, staged or not
+    All,
+    /// The index against tracked files in the working tree
+    Unstaged,
+}
+
+impl DiffSource {
+    /// Check whether the diff involves the index or working tree rather than only commits
+    pub fn is_uncommitted(&self) -> bool {
+        matches!(
+            self,
+            DiffSource::Staged | DiffSource::All | DiffSource::Unstaged
+        )
+    }
+}
+
+impl fmt::Display for DiffSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffSource::Staged => write!(f, "in the index"),
+            DiffSource::Range { from, to } => write!(f, "between {from} and {to}"),
+            DiffSource::Commit(rev) => write!(f, "in commit {rev}"),
+            DiffSource::All => write!(f, "in the working tree"),
+            DiffSource::Unstaged => write!(f, "between the index and the working tree"),
+        }
+    }
+}
 
 /// Git service for handling git diff operations and change extraction
 pub struct GitService {
     repo: Repository,
+    context_lines: u32,
+    exclusions: ExclusionRules,
+    source: DiffSource,
+    index_file: Option<PathBuf>,
 }
 
 impl GitService {
     /// Create a new GitService by discovering the repository
     pub fn new() -> Result<Self> {
         let repo = Repository::discover(".").map_err(|_| ConvComError::NotGitRepoError)?;
+        let exclusions = match repo.workdir() {
+            Some(workdir) => ExclusionRules::load(workdir)?,
+            None => ExclusionRules::new(),
+        };
 
-        Ok(Self { repo })
+        Ok(Self {
+            repo,
+            context_lines: DEFAULT_CONTEXT_LINES,
+            exclusions,
+            source: DiffSource::Staged,
+            index_file: None,
+        })
     }
 
-    /// Get list of staged files from git
-    pub fn get_staged_files(&self) -> Result<Vec<String>> {
-        let mut staged_files = Vec::new();
-        let statuses = self.repo.statuses(None)?;
-
-        for entry in statuses.iter() {
-            let status = entry.status();
-            if status.contains(Status::INDEX_NEW)
-                || status.contains(Status::INDEX_MODIFIED)
-                || status.contains(Status::INDEX_DELETED)
-            {
-                if let Some(path) = entry.path() {
-                    staged_files.push(path.to_string());
-                }
-            }
+    /// Exclude files matching the gitignore patterns from the `exclude` setting
+    pub fn with_exclusions(mut self, patterns: &[String]) -> Result<Self> {
+        if let Some(workdir) = self.repo.workdir() {
+            self.exclusions = ExclusionRules::load_with_patterns(workdir, patterns)?;
         }
+        Ok(self)
+    }
 
-        if staged_files.is_empty() {
-            return Err(ConvComError::NoStagedFilesError);
-        }
+    /// Set the number of context lines collected around each change
+    pub fn with_context_lines(mut self, context_lines: u32) -> Self {
+        self.context_lines = context_lines;
+        self
+    }
 
-        Ok(staged_files)
+    /// Set which two snapshots are compared
+    pub fn with_source(mut self, source: DiffSource) -> Self {
+        self.source = source;
+        self
     }
 
-    /// Get the git status of a specific file
-    pub fn get_file_status(&self, file_path: &str) -> Result<char> {
-        let statuses = self.repo.statuses(None)?;
+    /// Read and commit staged changes from another index file, like git does for `GIT_INDEX_FILE`
+    ///
+    /// `git commit -a` and `git commit <paths>` stage into a temporary index
+    /// and point their hooks at it.
+    pub fn with_index_file(mut self, index_file: PathBuf) -> Self {
+        self.index_file = Some(index_file);
+        self
+    }
 
-        for entry in statuses.iter() {
-            if let Some(path) = entry.path() {
-                if path == file_path {
-                    let status = entry.status();
-                    if status.contains(Status::INDEX_NEW) {
-                        return Ok('A'); // Added
-                    } else if status.contains(Status::INDEX_MODIFIED) {
-                        return Ok('M'); // Modified
-                    } else if status.contains(Status::INDEX_DELETED) {
-                        return Ok('D'); // Deleted
-                    }
-                }
-            }
+    /// Get the tree of the HEAD commit, or None on an unborn branch
+    fn head_tree(&self) -> Result<Option<Tree<'_>>> {
+        match self.repo.head() {
+            Ok(head) => Ok(Some(head.peel_to_tree()?)),
+            Err(e) if matches!(e.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
         }
+    }
 
-        Ok('M') // Default to modified
+    /// Resolve a revision such as `main`, `HEAD~2` or a commit id to its tree
+    fn revision_tree(&self, rev: &str) -> Result<Tree<'_>> {
+        let object = self
+            .repo
+            .revparse_single(rev)
+            .map_err(|_| ConvComError::GitError(format!("Unknown revision '{rev}'")))?;
+
+        Ok(object.peel_to_tree()?)
     }
 
-    /// Get content of a newly added file from the index
-    pub fn get_file_content(&self, file_path: &str) -> Result<String> {
-        // First try to get from index (staged content)
-        if let Ok(index) = self.repo.index() {
-            if let Some(entry) = index.get_path(Path::new(file_path), 0) {
-                if let Ok(blob) = self.repo.find_blob(entry.id) {
-                    if let Ok(content) = std::str::from_utf8(blob.content()) {
-                        return Ok(content.to_string());
-                    }
-                }
+    /// Options shared by every diff: type changes and the configured context
+    fn diff_options(&self) -> DiffOptions {
+        let mut diff_options = DiffOptions::new();
+        diff_options
+            .include_typechange(true)
+            .context_lines(self.context_lines);
+        diff_options
+    }
+
+    /// The index to diff and commit: the alternate index file if one is set
+    fn index(&self) -> Result<Index> {
+        Ok(match &self.index_file {
+            Some(path) => Index::open(path)?,
+            None => self.repo.index()?,
+        })
+    }
+
+    /// Build the diff for the configured source with rename and copy detection
+    ///
+    /// On a repository without commits the index is compared against an empty tree.
+    fn diff(&self) -> Result<Diff<'_>> {
+        let mut diff_options = self.diff_options();
+        let mut diff = match &self.source {
+            DiffSource::Staged => {
+                let tree = self.head_tree()?;
+                self.repo.diff_tree_to_index(
+                    tree.as_ref(),
+                    Some(&self.index()?),
+                    Some(&mut diff_options),
+                )?
             }
-        }
+            DiffSource::Range { from, to } => {
+                let old_tree = self.revision_tree(from)?;
+                let new_tree = self.revision_tree(to)?;
+                self.repo.diff_tree_to_tree(
+                    Some(&old_tree),
+                    Some(&new_tree),
+                    Some(&mut diff_options),
+                )?
+            }
+            DiffSource::Commit(rev) => {
+                let commit = self
+                    .repo
+                    .revparse_single(rev)
+                    .and_then(|object| object.peel_to_commit())
+                    .map_err(|_| ConvComError::GitError(format!("Unknown commit '{rev}'")))?;
+                let parent_tree = match commit.parent(0) {
+                    Ok(parent) => Some(parent.tree()?),
+                    Err(_) => None,
+                };
+                self.repo.diff_tree_to_tree(
+                    parent_tree.as_ref(),
+                    Some(&commit.tree()?),
+                    Some(&mut diff_options),
+                )?
+            }
+            DiffSource::All => {
+                let tree = self.head_tree()?;
+                self.repo
+                    .diff_tree_to_workdir_with_index(tree.as_ref(), Some(&mut diff_options))?
+            }
+            DiffSource::Unstaged => self
+                .repo
+                .diff_index_to_workdir(Some(&self.index()?), Some(&mut diff_options))?,
+        };
 
-        // Fallback: try to read from working directory
-        std::fs::read_to_string(file_path)
-            .map_err(|_| ConvComError::IoError(format!("Could not read file content: {file_path}")))
+        let mut find_options = DiffFindOptions::new();
+        find_options
+            .renames(true)
+            .copies(true)
+            .rename_threshold(SIMILARITY_THRESHOLD)
+            .copy_threshold(SIMILARITY_THRESHOLD);
+        diff.find_similar(Some(&mut find_options))?;
+
+        Ok(diff)
     }
 
-    /// Extract changes from a modified file using git diff
-    pub fn get_file_changes(&self, file_path: &str) -> Result<Vec<String>> {
-        let mut changes = Vec::new();
+    /// Convert a diff delta into a FileChange without content
+    fn file_change_from_delta(delta: &DiffDelta<'_>) -> Option<FileChange> {
+        let kind = match delta.status() {
+            Delta::Added => ChangeKind::Added,
+            Delta::Modified => ChangeKind::Modified,
+            Delta::Deleted => ChangeKind::Deleted,
+            Delta::Renamed => ChangeKind::Renamed,
+            Delta::Copied => ChangeKind::Copied,
+            Delta::Typechange => ChangeKind::TypeChanged,
+            _ => return None,
+        };
 
-        // Get diff between HEAD and index (staged changes)
-        let tree = self.repo.head()?.peel_to_tree()?;
-        let diff = self.repo.diff_tree_to_index(Some(&tree), None, None)?;
+        let path_of =
+            |file: DiffFile<'_>| file.path().map(|path| path.to_string_lossy().into_owned());
+        let old_path = path_of(delta.old_file());
+        let path = path_of(delta.new_file()).or_else(|| old_path.clone())?;
 
-        diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
-            let file_matches = _delta
-                .new_file()
-                .path()
-                .map(|p| p.to_str().unwrap_or("") == file_path)
-                .unwrap_or(false);
+        Some(FileChange {
+            old_path: old_path.filter(|_| matches!(kind, ChangeKind::Renamed | ChangeKind::Copied)),
+            old_mode: u32::from(delta.old_file().mode()),
+            new_mode: u32::from(delta.new_file().mode()),
+            ..FileChange::new(kind, path)
+        })
+    }
 
-            if file_matches {
-                let content = String::from_utf8_lossy(line.content());
-                let origin = line.origin();
+    /// Size and leading bytes of one side of a delta
+    ///
+    /// Only the first `SNIFF_PREFIX_LEN` bytes are copied. Files that were
+    /// never written as blobs (e.g. working tree content) are read from disk
+    /// instead.
+    fn file_prefix(&self, file: &DiffFile<'_>) -> Option<(u64, Vec<u8>)> {
+        if !file.exists() {
+            return None;
+        }
 
-                match origin {
-                    '+' => {
-                        if !content.starts_with("+++") {
-                            changes.push(format!("+ {}", content.trim_end()));
-                        }
+        if let Ok(blob) = self.repo.find_blob(file.id()) {
+            let content = blob.content();
+            let prefix = content[..content.len().min(SNIFF_PREFIX_LEN)].to_vec();
+            return Some((blob.size() as u64, prefix));
+        }
+
+        let path = self.repo.workdir()?.join(file.path()?);
+        let handle = File::open(path).ok()?;
+        let size = match file.size() {
+            0 => handle.metadata().ok()?.len(),
+            size => size,
+        };
+        let mut prefix = Vec::new();
+        handle
+            .take(SNIFF_PREFIX_LEN as u64)
+            .read_to_end(&mut prefix)
+            .ok()?;
+        Some((size, prefix))
+    }
+
+    /// Describe both sides of a binary delta without keeping its content
+    fn binary_info(&self, delta: &DiffDelta<'_>) -> BinaryInfo {
+        let old = self.file_prefix(&delta.old_file());
+        let new = self.file_prefix(&delta.new_file());
+        let dimensions = |side: &Option<(u64, Vec<u8>)>| {
+            side.as_ref()
+                .and_then(|(_, prefix)| image_dimensions(prefix))
+        };
+
+        BinaryInfo {
+            old_size: old.as_ref().map(|(size, _)| *size),
+            new_size: new.as_ref().map(|(size, _)| *size),
+            mime_type: new
+                .as_ref()
+                .or(old.as_ref())
+                .map(|(_, prefix)| sniff_mime(prefix)),
+            old_dimensions: dimensions(&old),
+            new_dimensions: dimensions(&new),
+        }
+    }
+
+    /// Walk a diff exactly once, turning every delta into a FileChange with its hunks
+    ///
+    /// Binary deltas get a size and type summary instead of hunks. Excluded
+    /// files only have their changed lines counted, as they are summarised.
+    fn collect_changes(&self, diff: &Diff<'_>) -> Result<Vec<FileChange>> {
+        let mut changes: Vec<FileChange> = Vec::new();
+        // Whether lines currently being printed belong to the last entry in `changes`
+        let mut collecting = false;
+
+        diff.print(DiffFormat::Patch, |delta, hunk, line| {
+            match line.origin() {
+                // A file header starts every delta
+                'F' => {
+                    collecting = false;
+                    if let Some(mut change) = Self::file_change_from_delta(&delta) {
+                        let header = String::from_utf8_lossy(line.content());
+                        change.similarity = SIMILARITY_INDEX
+                            .captures(&header)
+                            .and_then(|captures| captures[1].parse().ok());
+                        change.excluded = self.exclusion_reason(&change.path);
+                        changes.push(change);
+                        collecting = true;
+                    }
+                }
+                'H' if collecting => {
+                    if let (Some(change), Some(hunk)) = (changes.last_mut(), hunk)
+                        && change.excluded.is_none()
+                    {
+                        change.hunks.push(DiffHunk {
+                            old_start: hunk.old_start(),
+                            old_lines: hunk.old_lines(),
+                            new_start: hunk.new_start(),
+                            new_lines: hunk.new_lines(),
+                            header: String::from_utf8_lossy(hunk.header())
+                                .trim_end()
+                                .to_string(),
+                            lines: Vec::new(),
+                        });
+                    }
+                }
+                // Printed instead of hunks for binary content
+                'B' if collecting => {
+                    if let Some(change) = changes.last_mut() {
+                        change.binary = Some(self.binary_info(&delta));
                     }
-                    '-' => {
-                        if !content.starts_with("---") {
-                            changes.push(format!("- {}", content.trim_end()));
+                }
+                origin @ ('+' | '-' | ' ') if collecting => {
+                    let kind = match origin {
+                        '+' => LineKind::Added,
+                        '-' => LineKind::Removed,
+                        _ => LineKind::Context,
+                    };
+                    let Some(change) = changes.last_mut() else {
+                        return true;
+                    };
+                    if change.excluded.is_some() {
+                        if kind != LineKind::Context {
+                            change.omitted_lines += 1;
                         }
+                    } else if let Some(hunk) = change.hunks.last_mut() {
+                        hunk.lines.push(DiffLine {
+                            kind,
+                            old_lineno: line.old_lineno(),
+                            new_lineno: line.new_lineno(),
+                            content: String::from_utf8_lossy(line.content())
+                                .trim_end_matches(['\n', '\r'])
+                                .to_string(),
+                        });
                     }
-                    _ => {}
                 }
+                _ => {}
             }
             true
         })?;
@@ -118,72 +374,221 @@ impl GitService {
         Ok(changes)
     }
 
-    /// Process changes for a single file based on its status
-    pub fn process_file_changes(&self, file_path: &str, file_status: char) -> Result<Vec<String>> {
-        let mut changes = Vec::new();
+    /// Decide whether a file's content should be summarised instead of shown
+    ///
+    /// Path rules from the defaults and `.convcomignore` are checked first,
+    /// then the `linguist-generated` and `diff` attributes from `.gitattributes`.
+    fn exclusion_reason(&self, path: &str) -> Option<ExclusionReason> {
+        if let Some(reason) = self.exclusions.check(path) {
+            return Some(reason);
+        }
 
-        match file_status {
-            'A' => {
-                // Added file
-                changes.push(format!("NEW FILE: {file_path}"));
-                match self.get_file_content(file_path) {
-                    Ok(content) => {
-                        changes.push("COMPLETE CONTENT:".to_string());
-                        changes.push(content);
-                        changes.push(String::new());
-                    }
-                    Err(_) => {
-                        changes.push("Could not read file content".to_string());
-                        changes.push(String::new());
-                    }
-                }
-            }
-            'D' => {
-                // Deleted file
-                changes.push(format!("DELETED: {file_path}"));
-            }
-            'M' => {
-                // Modified file
-                match self.get_file_changes(file_path) {
-                    Ok(file_changes) => {
-                        if !file_changes.is_empty() {
-                            changes.push(format!("MODIFIED: {file_path}"));
-                            changes.extend(file_changes.into_iter().take(100)); // Limit to 100 changes
-                            changes.push(String::new());
-                        }
-                    }
-                    Err(_) => {
-                        changes.push(format!("MODIFIED: {file_path} (could not get diff)"));
-                        changes.push(String::new());
+        let attribute = |name: &str| {
+            self.repo
+                .get_attr(Path::new(path), name, AttrCheckFlags::INDEX_THEN_FILE)
+                .map(AttrValue::from_string)
+                .unwrap_or(AttrValue::Unspecified)
+        };
+
+        if matches!(
+            attribute("linguist-generated"),
+            AttrValue::True | AttrValue::String("true")
+        ) {
+            Some(ExclusionReason::Generated)
+        } else if attribute("diff") == AttrValue::False {
+            Some(ExclusionReason::DiffDisabled)
+        } else {
+            None
+        }
+    }
+
+    /// Get paths whose working tree content differs from what is staged for them
+    ///
+    /// For these files a message generated from one side will not match the other.
+    pub fn partially_staged_files(&self) -> Result<Vec<String>> {
+        let index = self.index()?;
+        let mut options = DiffOptions::new();
+        options.ignore_submodules(true);
+        let paths = |diff: Diff<'_>| -> HashSet<String> {
+            diff.deltas()
+                .filter_map(|delta| delta.new_file().path().or(delta.old_file().path()))
+                .map(|path| path.to_string_lossy().into_owned())
+                .collect()
+        };
+
+        let tree = self.head_tree()?;
+        let staged = paths(self.repo.diff_tree_to_index(
+            tree.as_ref(),
+            Some(&index),
+            Some(&mut options),
+        )?);
+        let unstaged = paths(
+            self.repo
+                .diff_index_to_workdir(Some(&index), Some(&mut options))?,
+        );
+
+        let mut both: Vec<String> = staged.intersection(&unstaged).cloned().collect();
+        both.sort();
+        Ok(both)
+    }
+
+    /// Resolve the author or committer identity like git does
+    ///
+    /// `GIT_AUTHOR_*`/`GIT_COMMITTER_*` environment variables win over the
+    /// `author.*`/`committer.*` settings, which win over `user.*`.
+    fn identity(&self, role: &str) -> Result<Signature<'static>> {
+        let config = self.repo.config()?;
+        let lookup = |field: &str| {
+            std::env::var(format!(
+                "GIT_{}_{}",
+                role.to_uppercase(),
+                field.to_uppercase()
+            ))
+            .ok()
+            .or_else(|| config.get_string(&format!("{role}.{field}")).ok())
+            .or_else(|| config.get_string(&format!("user.{field}")).ok())
+            .filter(|value| !value.trim().is_empty())
+        };
+
+        match (lookup("name"), lookup("email")) {
+            (Some(name), Some(email)) => Ok(Signature::now(&name, &email)?),
+            _ => Err(ConvComError::ConfigError(format!(
+                "Unknown {role} identity: set user.name and user.email in your git config"
+            ))),
+        }
+    }
+
+    /// Get the repository's git configuration, including global and system settings
+    pub fn config(&self) -> Result<git2::Config> {
+        Ok(self.repo.config()?)
+    }
+
+    /// Directory git runs this repository's hooks from
+    pub fn hooks_dir(&self) -> Result<PathBuf> {
+        hooks::hooks_dir(&self.repo)
+    }
+
+    /// Path of the file git keeps the last commit message in
+    pub fn commit_message_path(&self) -> PathBuf {
+        self.repo.path().join("COMMIT_EDITMSG")
+    }
+
+    /// Commit the index with the given message, like `git commit -F`
+    ///
+    /// The message is written to `.git/COMMIT_EDITMSG` before anything else,
+    /// so it survives a rejected commit. The `pre-commit` and `commit-msg`
+    /// hooks run first, and the commit is signed when `commit.gpgsign` is set.
+    pub fn create_commit(&self, message: &str) -> Result<Oid> {
+        let message_path = self.commit_message_path();
+        std::fs::write(&message_path, format!("{}\n", message.trim_end()))?;
+
+        let mut index = self.index()?;
+        let index_file = match index.path() {
+            Some(path) => path.to_path_buf(),
+            None => self.repo.path().join("index"),
+        };
+        hooks::run_hook(&self.repo, &index_file, "pre-commit", &[])?;
+        hooks::run_hook(
+            &self.repo,
+            &index_file,
+            "commit-msg",
+            &[message_path.as_os_str()],
+        )?;
+
+        // The commit-msg hook may have edited the message
+        let message = git2::message_prettify(std::fs::read_to_string(&message_path)?, None)?;
+        if message.trim().is_empty() {
+            return Err(ConvComError::GitError(
+                "Aborting commit due to empty commit message".to_string(),
+            ));
+        }
+
+        // The pre-commit hook may have staged more changes
+        index.read(true)?;
+        let tree = self.repo.find_tree(index.write_tree_to(&self.repo)?)?;
+        let parent = match self.head_tree()? {
+            Some(_) => Some(self.repo.head()?.peel_to_commit()?),
+            None => None,
+        };
+        let parents: Vec<&git2::Commit<'_>> = parent.iter().collect();
+        let author = self.identity("author")?;
+        let committer = self.identity("committer")?;
+
+        let config = self.repo.config()?;
+        if !config.get_bool("commit.gpgsign").unwrap_or(false) {
+            return Ok(self.repo.commit(
+                Some("HEAD"),
+                &author,
+                &committer,
+                &message,
+                &tree,
+                &parents,
+            )?);
+        }
+
+        let buffer = self
+            .repo
+            .commit_create_buffer(&author, &committer, &message, &tree, &parents)?;
+        let buffer = buffer
+            .as_str()
+            .ok_or_else(|| ConvComError::GitError("Commit is not valid UTF-8".to_string()))?;
+        let signature = signing::sign_buffer(&config, buffer, &committer)?;
+        let oid = self.repo.commit_signed(buffer, &signature, None)?;
+
+        // Signed commits are created detached, so move the branch like `git commit` would,
+        let summary = message.lines().next().unwrap_or_default();
+        let reflog = match parent {
+            Some(_) => format!("commit: {summary}"),
+            None => format!("commit (initial): {summary}"),
+        };
+        // but refuse to overwrite the branch if it moved since the parent was read
+        let head = self.repo.find_reference("HEAD")?;
+        match head.symbolic_target() {
+            Some(branch) => {
+                let updated = match &parent {
+                    Some(parent) => {
+                        self.repo
+                            .reference_matching(branch, oid, true, parent.id(), &reflog)
                     }
-                }
-            }
-            _ => {
-                changes.push(format!("UNKNOWN STATUS: {file_path}"));
+                    None => self.repo.reference(branch, oid, false, &reflog),
+                };
+                updated.map_err(|e| {
+                    ConvComError::GitError(format!(
+                        "{branch} changed while committing, signed commit {oid} was left unreferenced: {}",
+                        e.message()
+                    ))
+                })?;
             }
+            None => self.repo.set_head_detached(oid)?,
         }
 
-        Ok(changes)
+        Ok(oid)
     }
 
-    /// Build the complete diff content from all staged files
-    pub fn build_diff_content(&self) -> Result<String> {
-        let staged_files = self.get_staged_files()?;
-        let mut processed_diff = Vec::new();
-
-        for file_path in staged_files {
-            let file_status = self.get_file_status(&file_path)?;
-            let file_changes = self.process_file_changes(&file_path, file_status)?;
-            processed_diff.extend(file_changes);
+    /// Get all changes of the configured source, including their hunks, from a single diff pass
+    ///
+    /// For staged changes only HEAD and the index are compared, so the
+    /// working tree and any untracked files in it are never scanned.
+    pub fn get_changes(&self) -> Result<Vec<FileChange>> {
+        let diff = self.diff()?;
+        let changes = self.collect_changes(&diff)?;
+        if changes.is_empty() {
+            return Err(match self.source {
+                DiffSource::Staged => ConvComError::NoStagedFilesError,
+                _ => ConvComError::NoChangesError(self.source.to_string()),
+            });
         }
 
-        Ok(processed_diff.join("\n"))
+        Ok(changes)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::diff::DiffStyle;
+    use crate::prompt::diff_renderer::DiffRenderer;
+    use std::path::Path;
 
     #[test]
     fn test_git_service_creation() {
@@ -204,8 +609,762 @@ mod tests {
     #[test]
     fn test_file_status_mapping() {
         // Test that file status characters are correct
-        assert_eq!('A', 'A'); // Added
-        assert_eq!('M', 'M'); // Modified
-        assert_eq!('D', 'D'); // Deleted
+        assert_eq!(ChangeKind::Added.as_char(), 'A'); // Added
+        assert_eq!(ChangeKind::Modified.as_char(), 'M'); // Modified
+        assert_eq!(ChangeKind::Deleted.as_char(), 'D'); // Deleted
+    }
+
+    /// Temporary repository with helpers for staging files
+    struct TestRepo {
+        dir: tempfile::TempDir,
+        service: GitService,
+    }
+
+    impl TestRepo {
+        fn new() -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let repo = Repository::init(dir.path()).unwrap();
+            let mut config = repo.config().unwrap();
+            config.set_str("user.name", "Test User").unwrap();
+            config.set_str("user.email", "test@example.com").unwrap();
+
+            Self {
+                dir,
+                service: GitService {
+                    repo,
+                    context_lines: DEFAULT_CONTEXT_LINES,
+                    exclusions: ExclusionRules::new(),
+                    source: DiffSource::Staged,
+                    index_file: None,
+                },
+            }
+        }
+
+        fn write(&self, path: &str, content: &str) {
+            let full_path = self.dir.path().join(path);
+            std::fs::create_dir_all(full_path.parent().unwrap()).unwrap();
+            std::fs::write(full_path, content).unwrap();
+        }
+
+        fn stage_all(&self) {
+            let mut index = self.service.repo.index().unwrap();
+            index
+                .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+                .unwrap();
+            index.update_all(["*"], None).unwrap();
+            index.write().unwrap();
+        }
+
+        fn commit(&self, message: &str) {
+            self.stage_all();
+            let repo = &self.service.repo;
+            let mut index = repo.index().unwrap();
+            let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+            let signature = repo.signature().unwrap();
+            let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+            let parents: Vec<&git2::Commit> = parent.iter().collect();
+            repo.commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parents,
+            )
+            .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_unborn_branch_with_staged_files() {
+        let test_repo = TestRepo::new();
+        test_repo.write("README.md", "# Project\n");
+        test_repo.write("src/main.rs", "fn main() {}\n");
+        test_repo.stage_all();
+
+        // Working tree edits after staging must not leak into the diff
+        test_repo.write("README.md", "# Unstaged title\n");
+
+        let staged = test_repo.service.get_changes().unwrap();
+        assert_eq!(staged.len(), 2);
+        assert!(staged.iter().all(|file| file.kind == ChangeKind::Added));
+
+        let content = DiffRenderer::default().render(&test_repo.service.get_changes().unwrap());
+        assert!(content.contains("NEW FILE: README.md"));
+        assert!(content.contains("# Project"));
+        assert!(!content.contains("# Unstaged title"));
+        assert!(content.contains("NEW FILE: src/main.rs"));
+    }
+
+    #[test]
+    fn test_unborn_branch_without_staged_files() {
+        let test_repo = TestRepo::new();
+        test_repo.write("untracked.txt", "not staged\n");
+
+        assert!(matches!(
+            test_repo.service.get_changes(),
+            Err(ConvComError::NoStagedFilesError)
+        ));
+    }
+
+    #[test]
+    fn test_unborn_branch_diff_lines() {
+        let test_repo = TestRepo::new();
+        test_repo.write("lib.rs", "pub fn answer() -> u32 {\n    42\n}\n");
+        test_repo.stage_all();
+
+        let staged = test_repo.service.get_changes().unwrap();
+        let lines: Vec<&DiffLine> = staged[0].changed_lines().collect();
+        assert_eq!(lines[0].content, "pub fn answer() -> u32 {");
+        assert_eq!(lines[0].new_lineno, Some(1));
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn test_single_pass_hunks() {
+        let test_repo = TestRepo::new();
+        test_repo.write("a.txt", &numbered_lines(30));
+        test_repo.write("b.txt", "b\n");
+        test_repo.commit("initial");
+
+        test_repo.write(
+            "a.txt",
+            &numbered_lines(30)
+                .replace("line 2\n", "line two\n")
+                .replace("line 25\n", "line twenty-five\n"),
+        );
+        test_repo.write("b.txt", "b\nmore b\n");
+        // Untracked files never show up in the staged changes
+        test_repo.write("target/output.bin", "build output\n");
+        let mut index = test_repo.service.repo.index().unwrap();
+        index.add_path(Path::new("a.txt")).unwrap();
+        index.add_path(Path::new("b.txt")).unwrap();
+        index.write().unwrap();
+
+        let staged = test_repo.service.get_changes().unwrap();
+        let paths: Vec<&str> = staged.iter().map(|file| file.path.as_str()).collect();
+        assert_eq!(paths, vec!["a.txt", "b.txt"]);
+
+        let a = &staged[0];
+        assert_eq!(a.hunks.len(), 2);
+        assert!(a.hunks[0].header.starts_with("@@ -1,5 +1,5 @@"));
+        let removed: Vec<&str> = a
+            .changed_lines()
+            .filter(|line| line.kind == LineKind::Removed)
+            .map(|line| line.content.as_str())
+            .collect();
+        assert_eq!(removed, vec!["line 2", "line 25"]);
+
+        let content = DiffRenderer::default().render(&test_repo.service.get_changes().unwrap());
+        assert!(content.contains("MODIFIED: a.txt\n- line 2\n+ line two\n- line 25"));
+        assert!(content.contains("MODIFIED: b.txt\n+ more b"));
+    }
+
+    #[test]
+    fn test_hunk_header_includes_enclosing_function() {
+        let test_repo = TestRepo::new();
+        let body: String = (1..=12).map(|n| format!("    step({n});\n")).collect();
+        test_repo.write("run.rs", &format!("fn run_pipeline() {{\n{body}}}\n"));
+        test_repo.commit("initial");
+
+        test_repo.write(
+            "run.rs",
+            &format!(
+                "fn run_pipeline() {{\n{}}}\n",
+                body.replace("step(9)", "step(90)")
+            ),
+        );
+        test_repo.stage_all();
+
+        let staged = test_repo.service.get_changes().unwrap();
+        let hunk = &staged[0].hunks[0];
+        assert!(hunk.header.ends_with("fn run_pipeline() {"));
+        // One replaced line with three context lines on each side
+        assert_eq!(hunk.lines.len(), 8);
+    }
+
+    #[test]
+    fn test_context_lines_setting() {
+        let mut test_repo = TestRepo::new();
+        test_repo.write("a.txt", &numbered_lines(20));
+        test_repo.commit("initial");
+
+        test_repo.write("a.txt", &numbered_lines(20).replace("line 10\n", "ten\n"));
+        test_repo.stage_all();
+        test_repo.service.context_lines = 1;
+
+        let staged = test_repo.service.get_changes().unwrap();
+        assert!(staged[0].hunks[0].header.starts_with("@@ -9,3 +9,3 @@"));
+
+        let unified = DiffRenderer::new(DiffStyle::Unified).render(&staged);
+        assert!(unified.contains("-   10      | line 10"));
+        assert!(unified.contains("+        10 | ten"));
+    }
+
+    #[test]
+    fn test_excluded_files_are_summarised() {
+        let mut test_repo = TestRepo::new();
+        test_repo.write(
+            ".gitattributes",
+            "schema.gen.ts linguist-generated\n*.svg -diff\n",
+        );
+        test_repo.write(".convcomignore", "snapshots/\n");
+        test_repo.commit("initial");
+
+        test_repo.write("Cargo.lock", &numbered_lines(812));
+        test_repo.write("schema.gen.ts", &numbered_lines(3));
+        test_repo.write("logo.svg", "<svg></svg>\n");
+        test_repo.write("snapshots/api.txt", &numbered_lines(2));
+        test_repo.write("src/lib.rs", "pub fn lib() {}\n");
+        test_repo.stage_all();
+        test_repo.service.exclusions = ExclusionRules::load(test_repo.dir.path()).unwrap();
+
+        let staged = test_repo.service.get_changes().unwrap();
+        let reason = |path: &str| {
+            staged
+                .iter()
+                .find(|file| file.path == path)
+                .and_then(|file| file.excluded)
+        };
+        assert_eq!(reason("Cargo.lock"), Some(ExclusionReason::Lockfile));
+        assert_eq!(reason("schema.gen.ts"), Some(ExclusionReason::Generated));
+        assert_eq!(reason("logo.svg"), Some(ExclusionReason::DiffDisabled));
+        assert_eq!(reason("snapshots/api.txt"), Some(ExclusionReason::Ignored));
+        assert_eq!(reason("src/lib.rs"), None);
+        let lockfile = staged
+            .iter()
+            .find(|file| file.path == "Cargo.lock")
+            .unwrap();
+        assert!(lockfile.hunks.is_empty());
+        assert_eq!(lockfile.changed_line_count(), 812);
+
+        let content = DiffRenderer::default().render(&test_repo.service.get_changes().unwrap());
+        assert!(content.contains("NEW FILE (lockfile, 812 lines): Cargo.lock"));
+        assert!(content.contains("NEW FILE (generated, 3 lines): schema.gen.ts"));
+        assert!(!content.contains("line 500"));
+        assert!(content.contains("NEW FILE: src/lib.rs"));
+    }
+
+    #[test]
+    fn test_revision_range() {
+        let mut test_repo = TestRepo::new();
+        test_repo.write("a.txt", "one\n");
+        test_repo.commit("initial");
+        test_repo.write("a.txt", "one\ntwo\n");
+        test_repo.commit("second");
+        test_repo.write("b.txt", "bee\n");
+        test_repo.commit("third");
+        // Staged changes are ignored when describing a range
+        test_repo.write("staged.txt", "staged\n");
+        test_repo.stage_all();
+
+        test_repo.service.source = DiffSource::Range {
+            from: "HEAD~2".to_string(),
+            to: "HEAD".to_string(),
+        };
+        let changes = test_repo.service.get_changes().unwrap();
+        let paths: Vec<(&str, ChangeKind)> = changes
+            .iter()
+            .map(|file| (file.path.as_str(), file.kind))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("a.txt", ChangeKind::Modified),
+                ("b.txt", ChangeKind::Added)
+            ]
+        );
+
+        test_repo.service.source = DiffSource::Range {
+            from: "HEAD".to_string(),
+            to: "HEAD".to_string(),
+        };
+        assert!(matches!(
+            test_repo.service.get_changes(),
+            Err(ConvComError::NoChangesError(_))
+        ));
+    }
+
+    #[test]
+    fn test_single_commit() {
+        let mut test_repo = TestRepo::new();
+        test_repo.write("a.txt", "one\n");
+        test_repo.commit("initial");
+        test_repo.write("a.txt", "one\ntwo\n");
+        test_repo.commit("wip");
+
+        test_repo.service.source = DiffSource::Commit("HEAD".to_string());
+        let content = DiffRenderer::default().render(&test_repo.service.get_changes().unwrap());
+        assert_eq!(content, "MODIFIED: a.txt\n+ two\n");
+
+        // The root commit is compared against an empty tree
+        test_repo.service.source = DiffSource::Commit("HEAD~1".to_string());
+        let changes = test_repo.service.get_changes().unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, ChangeKind::Added);
+    }
+
+    #[test]
+    fn test_working_tree_sources() {
+        let mut test_repo = TestRepo::new();
+        test_repo.write("staged.txt", "one\n");
+        test_repo.write("unstaged.txt", "one\n");
+        test_repo.write("both.txt", "one\n");
+        test_repo.commit("initial");
+
+        test_repo.write("staged.txt", "one\nstaged\n");
+        test_repo.write("both.txt", "one\nstaged\n");
+        test_repo.stage_all();
+        test_repo.write("unstaged.txt", "one\nunstaged\n");
+        test_repo.write("both.txt", "one\nstaged\nunstaged\n");
+        test_repo.write("untracked.txt", "new\n");
+
+        let paths = |service: &GitService| -> Vec<String> {
+            service
+                .get_changes()
+                .unwrap()
+                .into_iter()
+                .map(|file| file.path)
+                .collect()
+        };
+
+        test_repo.service.source = DiffSource::All;
+        assert_eq!(
+            paths(&test_repo.service),
+            vec!["both.txt", "staged.txt", "unstaged.txt"]
+        );
+        let content = DiffRenderer::default().render(&test_repo.service.get_changes().unwrap());
+        assert!(content.contains("MODIFIED: both.txt\n+ staged\n+ unstaged"));
+
+        test_repo.service.source = DiffSource::Unstaged;
+        assert_eq!(paths(&test_repo.service), vec!["both.txt", "unstaged.txt"]);
+        let content = DiffRenderer::default().render(&test_repo.service.get_changes().unwrap());
+        assert!(content.contains("MODIFIED: both.txt\n+ unstaged\n"));
+
+        assert_eq!(
+            test_repo.service.partially_staged_files().unwrap(),
+            vec!["both.txt"]
+        );
+    }
+
+    #[test]
+    fn test_alternate_index_file() {
+        let mut test_repo = TestRepo::new();
+        test_repo.write("a.txt", "one\n");
+        test_repo.commit("initial");
+
+        // Like `git commit -a`: a temporary index holds what will be committed
+        test_repo.write("a.txt", "one\ntwo\n");
+        test_repo.stage_all();
+        let git_dir = test_repo.dir.path().join(".git");
+        std::fs::copy(git_dir.join("index"), git_dir.join("index.commit-a")).unwrap();
+        let mut index = test_repo.service.repo.index().unwrap();
+        index
+            .read_tree(&test_repo.service.head_tree().unwrap().unwrap())
+            .unwrap();
+        index.write().unwrap();
+
+        assert!(matches!(
+            test_repo.service.get_changes(),
+            Err(ConvComError::NoStagedFilesError)
+        ));
+
+        test_repo.service.index_file = Some(git_dir.join("index.commit-a"));
+        let changes = test_repo.service.get_changes().unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "a.txt");
+
+        // Unstaged changes and partially staged files follow the same index
+        test_repo.write("a.txt", "one\ntwo\nthree\n");
+        assert_eq!(
+            test_repo.service.partially_staged_files().unwrap(),
+            vec!["a.txt"]
+        );
+        test_repo.service.source = DiffSource::Unstaged;
+        let content = DiffRenderer::default().render(&test_repo.service.get_changes().unwrap());
+        assert!(content.contains("MODIFIED: a.txt\n+ three\n"));
+        assert!(!content.contains("+ two"));
+
+        test_repo.service.index_file = None;
+        assert!(
+            test_repo
+                .service
+                .partially_staged_files()
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_unstaged_binary_is_read_from_disk() {
+        let mut test_repo = TestRepo::new();
+        std::fs::write(test_repo.dir.path().join("icon.png"), png(8, 8)).unwrap();
+        test_repo.commit("initial");
+        // Larger than the prefix that is read for the type and dimensions
+        let mut larger = png(64, 64);
+        larger.resize(SNIFF_PREFIX_LEN * 3, 0);
+        std::fs::write(test_repo.dir.path().join("icon.png"), &larger).unwrap();
+
+        test_repo.service.source = DiffSource::Unstaged;
+        let changes = test_repo.service.get_changes().unwrap();
+        let info = changes[0].binary.as_ref().unwrap();
+        assert_eq!(info.old_dimensions, Some((8, 8)));
+        assert_eq!(info.new_dimensions, Some((64, 64)));
+        assert_eq!(info.new_size, Some(larger.len() as u64));
+        assert_eq!(info.mime_type, Some("image/png"));
+
+        test_repo.service.source = DiffSource::Staged;
+        assert!(matches!(
+            test_repo.service.get_changes(),
+            Err(ConvComError::NoStagedFilesError)
+        ));
+    }
+
+    #[test]
+    fn test_unknown_revision() {
+        let mut test_repo = TestRepo::new();
+        test_repo.write("a.txt", "one\n");
+        test_repo.commit("initial");
+
+        test_repo.service.source = DiffSource::Commit("no-such-branch".to_string());
+        match test_repo.service.get_changes() {
+            Err(ConvComError::GitError(message)) => assert!(message.contains("no-such-branch")),
+            other => panic!("Unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_create_commit() {
+        let test_repo = TestRepo::new();
+        test_repo.write("a.txt", "one\n");
+        test_repo.stage_all();
+
+        // The first commit on an unborn branch has no parent
+        let oid = test_repo
+            .service
+            .create_commit("feat: add a\n\n- Adds a.txt.")
+            .unwrap();
+        let repo = &test_repo.service.repo;
+        let commit = repo.find_commit(oid).unwrap();
+        assert_eq!(commit.message(), Some("feat: add a\n\n- Adds a.txt.\n"));
+        assert_eq!(commit.parent_count(), 0);
+        assert_eq!(repo.head().unwrap().target(), Some(oid));
+        let expected_email =
+            std::env::var("GIT_COMMITTER_EMAIL").unwrap_or_else(|_| "test@example.com".to_string());
+        assert_eq!(commit.committer().email(), Some(expected_email.as_str()));
+
+        test_repo.write("a.txt", "one\ntwo\n");
+        test_repo.stage_all();
+        let second = test_repo.service.create_commit("fix: extend a").unwrap();
+        let second = repo.find_commit(second).unwrap();
+        assert_eq!(second.parent_id(0).unwrap(), oid);
+        assert!(test_repo.service.get_changes().is_err());
+    }
+
+    #[cfg(unix)]
+    fn install_hook(test_repo: &TestRepo, name: &str, script: &str) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = test_repo.service.repo.path().join("hooks").join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rejected_commit_keeps_message() {
+        let test_repo = TestRepo::new();
+        test_repo.write("a.txt", "one\n");
+        test_repo.commit("initial");
+        let head = test_repo.service.repo.head().unwrap().target();
+
+        install_hook(
+            &test_repo,
+            "pre-commit",
+            "#!/bin/sh\necho 'lint failed'\nexit 1\n",
+        );
+        test_repo.write("a.txt", "two\n");
+        test_repo.stage_all();
+
+        assert!(matches!(
+            test_repo.service.create_commit("fix: change a"),
+            Err(ConvComError::HookRejected(hook)) if hook == "pre-commit"
+        ));
+        assert_eq!(test_repo.service.repo.head().unwrap().target(), head);
+        let saved = std::fs::read_to_string(test_repo.service.commit_message_path()).unwrap();
+        assert_eq!(saved, "fix: change a\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_commit_msg_hook_can_edit_message() {
+        let test_repo = TestRepo::new();
+        test_repo.write("a.txt", "one\n");
+        test_repo.stage_all();
+        install_hook(
+            &test_repo,
+            "commit-msg",
+            "#!/bin/sh\nprintf '\\nRefs: #42\\n' >> \"$1\"\n",
+        );
+
+        let oid = test_repo.service.create_commit("feat: add a").unwrap();
+        let commit = test_repo.service.repo.find_commit(oid).unwrap();
+        assert_eq!(commit.message(), Some("feat: add a\n\nRefs: #42\n"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_commit_from_alternate_index() {
+        let mut test_repo = TestRepo::new();
+        test_repo.write("a.txt", "one\n");
+        test_repo.commit("initial");
+
+        // Like `git commit -a`, which commits a temporary index
+        test_repo.write("a.txt", "two\n");
+        test_repo.stage_all();
+        let git_dir = test_repo.service.repo.path().to_path_buf();
+        let temporary = git_dir.join("index.commit-a");
+        std::fs::copy(git_dir.join("index"), &temporary).unwrap();
+        let mut index = test_repo.service.repo.index().unwrap();
+        index
+            .read_tree(&test_repo.service.head_tree().unwrap().unwrap())
+            .unwrap();
+        index.write().unwrap();
+        install_hook(
+            &test_repo,
+            "pre-commit",
+            "#!/bin/sh\nprintf '%s' \"$GIT_INDEX_FILE\" > hook-index\n",
+        );
+
+        test_repo.service.index_file = Some(temporary.clone());
+        let oid = test_repo.service.create_commit("fix: change a").unwrap();
+
+        let hook_index = std::fs::read_to_string(test_repo.dir.path().join("hook-index")).unwrap();
+        assert_eq!(Path::new(&hook_index), temporary);
+        let tree = test_repo
+            .service
+            .repo
+            .find_commit(oid)
+            .unwrap()
+            .tree()
+            .unwrap();
+        let blob = tree
+            .get_name("a.txt")
+            .unwrap()
+            .to_object(&test_repo.service.repo);
+        assert_eq!(blob.unwrap().as_blob().unwrap().content(), b"two\n");
+    }
+
+    /// Sign commits with a fake gpg that runs `before` and prints a fixed signature
+    #[cfg(unix)]
+    fn use_fake_signer(test_repo: &TestRepo, before: &str) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let signer = test_repo.dir.path().join("fake-gpg");
+        std::fs::write(
+            &signer,
+            format!(
+                "#!/bin/sh\n{before}\ncat > /dev/null\nprintf -- '-----BEGIN PGP SIGNATURE-----\\nfake\\n-----END PGP SIGNATURE-----\\n'\n"
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&signer, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut config = test_repo.service.repo.config().unwrap();
+        config.set_bool("commit.gpgsign", true).unwrap();
+        config
+            .set_str("gpg.program", signer.to_str().unwrap())
+            .unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signed_commit() {
+        let test_repo = TestRepo::new();
+        use_fake_signer(&test_repo, "");
+
+        test_repo.write("fake-gpg.txt", "signed\n");
+        let mut index = test_repo.service.repo.index().unwrap();
+        index.add_path(Path::new("fake-gpg.txt")).unwrap();
+        index.write().unwrap();
+
+        let oid = test_repo.service.create_commit("feat: signed").unwrap();
+        let repo = &test_repo.service.repo;
+        assert_eq!(repo.head().unwrap().target(), Some(oid));
+        let commit = repo.find_commit(oid).unwrap();
+        let signature = commit.header_field_bytes("gpgsig").unwrap();
+        assert!(signature.as_str().unwrap().contains("fake"));
+        let reflog = repo.reflog("HEAD").unwrap();
+        assert_eq!(
+            reflog.get(0).unwrap().message(),
+            Some("commit (initial): feat: signed")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_signed_commit_keeps_concurrent_branch_update() {
+        let test_repo = TestRepo::new();
+        let repo = &test_repo.service.repo;
+        let branch = repo.find_reference("HEAD").unwrap();
+        let branch = branch.symbolic_target().unwrap().to_string();
+        let branch_file = test_repo.dir.path().join(".git").join(&branch);
+
+        // Another commit made while signing, on an unborn and on an existing branch
+        for parents in [0, 1] {
+            let head = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+            let head: Vec<&git2::Commit> = head.iter().collect();
+            assert_eq!(head.len(), parents);
+            let signature = repo.signature().unwrap();
+            let tree = repo
+                .find_tree(repo.index().unwrap().write_tree().unwrap())
+                .unwrap();
+            let other = repo
+                .commit(None, &signature, &signature, "other", &tree, &head)
+                .unwrap();
+            use_fake_signer(
+                &test_repo,
+                &format!("printf '{other}\\n' > '{}'", branch_file.display()),
+            );
+
+            test_repo.write("a.txt", &format!("{parents}\n"));
+            let mut index = repo.index().unwrap();
+            index.add_path(Path::new("a.txt")).unwrap();
+            index.write().unwrap();
+
+            let error = test_repo.service.create_commit("feat: signed").unwrap_err();
+            assert!(error.to_string().contains("changed while committing"));
+            assert_eq!(repo.head().unwrap().target(), Some(other));
+        }
+    }
+
+    /// Minimal PNG signature and IHDR chunk for the given size
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut content = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        content.extend_from_slice(&width.to_be_bytes());
+        content.extend_from_slice(&height.to_be_bytes());
+        content.extend_from_slice(&[8, 6, 0, 0, 0]);
+        content
+    }
+
+    #[test]
+    fn test_binary_files_are_described() {
+        let test_repo = TestRepo::new();
+        let path = |name: &str| test_repo.dir.path().join(name);
+        std::fs::write(path("logo.png"), png(16, 16)).unwrap();
+        test_repo.commit("initial");
+
+        let mut larger = png(32, 32);
+        larger.extend_from_slice(&[0; 100]);
+        std::fs::write(path("logo.png"), &larger).unwrap();
+        std::fs::write(path("data.bin"), b"\x00\x01\x02\xff\xfe").unwrap();
+        test_repo.stage_all();
+
+        let staged = test_repo.service.get_changes().unwrap();
+        let logo = staged.iter().find(|file| file.path == "logo.png").unwrap();
+        assert!(logo.hunks.is_empty());
+        assert_eq!(
+            logo.binary,
+            Some(BinaryInfo {
+                old_size: Some(29),
+                new_size: Some(129),
+                mime_type: Some("image/png"),
+                old_dimensions: Some((16, 16)),
+                new_dimensions: Some((32, 32)),
+            })
+        );
+
+        let content = DiffRenderer::default().render(&test_repo.service.get_changes().unwrap());
+        assert!(
+            content
+                .contains("MODIFIED (binary, image/png, 29 B -> 129 B, 16x16 -> 32x32): logo.png")
+        );
+        assert!(content.contains("NEW FILE (binary, application/octet-stream, 5 B): data.bin"));
+        assert!(!content.contains("Binary files"));
+        assert!(!content.contains('\u{fffd}'));
+    }
+
+    fn numbered_lines(count: usize) -> String {
+        (1..=count).map(|n| format!("line {n}\n")).collect()
+    }
+
+    #[test]
+    fn test_renamed_file_with_edits() {
+        let test_repo = TestRepo::new();
+        test_repo.write("src/old_name.rs", &numbered_lines(20));
+        test_repo.commit("initial");
+
+        std::fs::remove_file(test_repo.dir.path().join("src/old_name.rs")).unwrap();
+        test_repo.write(
+            "src/new_name.rs",
+            &numbered_lines(20).replace("line 7\n", "line seven\n"),
+        );
+        test_repo.stage_all();
+
+        let staged = test_repo.service.get_changes().unwrap();
+        assert_eq!(staged.len(), 1);
+        assert_eq!(staged[0].kind, ChangeKind::Renamed);
+        assert_eq!(staged[0].old_path.as_deref(), Some("src/old_name.rs"));
+        assert_eq!(staged[0].path, "src/new_name.rs");
+        assert!(
+            staged[0]
+                .similarity
+                .is_some_and(|s| s >= SIMILARITY_THRESHOLD)
+        );
+
+        let content = DiffRenderer::default().render(&test_repo.service.get_changes().unwrap());
+        assert!(content.contains("RENAMED: src/old_name.rs -> src/new_name.rs ("));
+        assert!(content.contains("- line 7"));
+        assert!(content.contains("+ line seven"));
+    }
+
+    #[test]
+    fn test_copied_file() {
+        let test_repo = TestRepo::new();
+        test_repo.write("template.txt", &numbered_lines(20));
+        test_repo.commit("initial");
+
+        test_repo.write("template.txt", &numbered_lines(21));
+        test_repo.write("copy.txt", &numbered_lines(20));
+        test_repo.stage_all();
+
+        let staged = test_repo.service.get_changes().unwrap();
+        let copy = staged.iter().find(|file| file.path == "copy.txt").unwrap();
+        assert_eq!(copy.kind, ChangeKind::Copied);
+        assert_eq!(copy.old_path.as_deref(), Some("template.txt"));
+        assert_eq!(copy.similarity, Some(100));
+
+        let content = DiffRenderer::default().render(&test_repo.service.get_changes().unwrap());
+        assert!(content.contains("COPIED: template.txt -> copy.txt (100%)"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_mode_and_type_changes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let test_repo = TestRepo::new();
+        test_repo.write("run.sh", "echo hello\n");
+        test_repo.write("link", "target\n");
+        test_repo.commit("initial");
+
+        let script = test_repo.dir.path().join("run.sh");
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let link = test_repo.dir.path().join("link");
+        std::fs::remove_file(&link).unwrap();
+        std::os::unix::fs::symlink("run.sh", &link).unwrap();
+        test_repo.stage_all();
+
+        let staged = test_repo.service.get_changes().unwrap();
+        let kinds: Vec<(&str, ChangeKind)> = staged
+            .iter()
+            .map(|file| (file.path.as_str(), file.kind))
+            .collect();
+        assert!(kinds.contains(&("run.sh", ChangeKind::Modified)));
+        assert!(kinds.contains(&("link", ChangeKind::TypeChanged)));
+
+        let content = DiffRenderer::default().render(&test_repo.service.get_changes().unwrap());
+        assert!(content.contains("MODE CHANGED: run.sh (100644 -> 100755)"));
+        assert!(content.contains("TYPE CHANGED: link (regular file -> symlink)"));
     }
 }
@@ -0,0 +1,333 @@
+use crate::error::{ConvComError, Result};
+use git2::Repository;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory git runs hooks from, honouring `core.hooksPath`
+pub fn hooks_dir(repo: &Repository) -> Result<PathBuf> {
+    let config = repo.config()?;
+
+    Ok(match config.get_path("core.hooksPath") {
+        Ok(path) if path.is_absolute() => path,
+        // Relative paths are resolved from where hooks run: the top of the working tree
+        Ok(path) => repo.workdir().unwrap_or_else(|| repo.path()).join(path),
+        Err(_) => common_dir(repo).join("hooks"),
+    })
+}
+
+/// The git directory shared by all worktrees of a repository
+fn common_dir(repo: &Repository) -> PathBuf {
+    // A linked worktree points at the shared directory from its `commondir` file
+    match std::fs::read_to_string(repo.path().join("commondir")) {
+        Ok(common) if repo.is_worktree() => repo.path().join(common.trim()),
+        _ => repo.path().to_path_buf(),
+    }
+}
+
+/// Check whether a hook file exists and may be run
+pub fn is_executable(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        path.metadata()
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+    }
+
+    #[cfg(not(unix))]
+    {
+        path.is_file()
+    }
+}
+
+/// Run a repository hook the way `git commit` does, if it is installed
+///
+/// The hook runs from the top of the working tree with `GIT_INDEX_FILE`
+/// set to the index being committed, and its output goes to stderr. A
+/// non-zero exit status is reported as a rejection.
+pub fn run_hook(repo: &Repository, index_file: &Path, name: &str, args: &[&OsStr]) -> Result<()> {
+    let path = hooks_dir(repo)?.join(name);
+    if !is_executable(&path) {
+        return Ok(());
+    }
+
+    let status = Command::new(&path)
+        .args(args)
+        .current_dir(repo.workdir().unwrap_or_else(|| repo.path()))
+        .env("GIT_INDEX_FILE", index_file)
+        .stdout(std::io::stderr())
+        .status()
+        .map_err(|e| ConvComError::GitError(format!("Cannot run the {name} hook: {e}")))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(ConvComError::HookRejected(name.to_string()))
+    }
+}
+
+/// First line of every hook convcom installs, used to recognise its own hooks
+const HOOK_MARKER: &str = "# Installed by convcom";
+
+/// How installing a hook went
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookInstall {
+    /// There was no hook yet
+    Installed,
+    /// An earlier convcom hook was replaced
+    Updated,
+    /// An existing hook was kept and now runs before convcom's
+    Chained,
+}
+
+/// Where an existing hook is moved so the convcom hook can run it first
+pub fn chained_hook_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}.convcom-chained"))
+}
+
+/// Check whether a hook file was written by convcom
+fn is_convcom_hook(path: &Path) -> bool {
+    std::fs::read(path).is_ok_and(|content| {
+        content
+            .split(|&byte| byte == b'\n')
+            .any(|line| line == HOOK_MARKER.as_bytes())
+    })
+}
+
+/// Quote a value for a POSIX shell script
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Hook that fills in a generated message before the editor opens
+pub const PREPARE_COMMIT_MSG: &str = "prepare-commit-msg";
+
+/// Hook that checks the final message
+pub const COMMIT_MSG: &str = "commit-msg";
+
+/// Script for a hook that runs `convcom hook <name>`
+///
+/// Any chained hook runs first and can still reject the commit. convcom
+/// itself only rejects it when `may_reject` is set; otherwise a failure,
+/// even a missing program, lets the commit go on.
+pub fn hook_script(name: &str, program: &Path, may_reject: bool) -> String {
+    let program = shell_quote(&program.to_string_lossy());
+    let on_failure = if may_reject { "" } else { " || true" };
+    // `${0%/*}` instead of dirname, so the chained hook runs even with a bare PATH
+    format!(
+        r#"#!/bin/sh
+{HOOK_MARKER}
+# Runs 'convcom hook {name}'. Remove with 'convcom hook uninstall {name}'.
+chained="${{0%/*}}/{name}.convcom-chained"
+if [ -x "$chained" ]; then
+    "$chained" "$@" || exit $?
+fi
+convcom={program}
+[ -x "$convcom" ] || convcom=convcom
+"$convcom" hook {name} "$@" </dev/null{on_failure}
+"#
+    )
+}
+
+/// Write a hook script into `dir`, keeping any hook that is already there
+///
+/// A foreign hook is moved aside to its chained path instead of being
+/// overwritten; an earlier convcom hook is simply replaced.
+pub fn install_hook(dir: &Path, name: &str, script: &str) -> Result<HookInstall> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(name);
+
+    let outcome = if !path.exists() {
+        HookInstall::Installed
+    } else if is_convcom_hook(&path) {
+        HookInstall::Updated
+    } else {
+        let chained = chained_hook_path(dir, name);
+        if chained.exists() {
+            return Err(ConvComError::IoError(format!(
+                "Cannot chain the existing {name} hook: {} already exists",
+                chained.display()
+            )));
+        }
+        std::fs::rename(&path, &chained)?;
+        HookInstall::Chained
+    };
+
+    std::fs::write(&path, script)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok(outcome)
+}
+
+/// Remove a hook installed by convcom and put back the hook it chained, if any
+///
+/// Returns whether a chained hook was restored. Hooks convcom did not
+/// write are left alone.
+pub fn uninstall_hook(dir: &Path, name: &str) -> Result<bool> {
+    let path = dir.join(name);
+    if !is_convcom_hook(&path) {
+        return Err(ConvComError::IoError(format!(
+            "No convcom {name} hook installed in {}",
+            dir.display()
+        )));
+    }
+
+    std::fs::remove_file(&path)?;
+    let chained = chained_hook_path(dir, name);
+    if chained.exists() {
+        std::fs::rename(&chained, &path)?;
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+/// Whether `prepare-commit-msg` should generate a message for this commit source
+///
+/// Messages given with `-m` or `-F`, merges, squashes and amended or reused
+/// commits already have a message, so only plain commits and templates get one.
+pub fn should_prepare_message(source: Option<&str>) -> bool {
+    matches!(source, None | Some("") | Some("template"))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn write_hook(dir: &Path, name: &str, script: &str) {
+        std::fs::create_dir_all(dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_hooks_dir_honours_hooks_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        assert_eq!(hooks_dir(&repo).unwrap(), repo.path().join("hooks"));
+
+        repo.config()
+            .unwrap()
+            .set_str("core.hooksPath", ".githooks")
+            .unwrap();
+        assert_eq!(
+            hooks_dir(&repo).unwrap(),
+            repo.workdir().unwrap().join(".githooks")
+        );
+    }
+
+    #[test]
+    fn test_run_hook() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path()).unwrap();
+        let hooks = repo.path().join("hooks");
+        let index = repo.path().join("index");
+
+        // Missing hooks are skipped
+        run_hook(&repo, &index, "pre-commit", &[]).unwrap();
+
+        write_hook(&hooks, "pre-commit", "#!/bin/sh\nexit 0\n");
+        run_hook(&repo, &index, "pre-commit", &[]).unwrap();
+
+        write_hook(&hooks, "commit-msg", "#!/bin/sh\ntest \"$1\" = ok\n");
+        run_hook(&repo, &index, "commit-msg", &[OsStr::new("ok")]).unwrap();
+        assert!(matches!(
+            run_hook(&repo, &index, "commit-msg", &[OsStr::new("bad")]),
+            Err(ConvComError::HookRejected(hook)) if hook == "commit-msg"
+        ));
+
+        // Hooks without the executable bit are ignored, like git does
+        std::fs::write(hooks.join("pre-push"), "#!/bin/sh\nexit 1\n").unwrap();
+        run_hook(&repo, &index, "pre-push", &[]).unwrap();
+    }
+
+    #[test]
+    fn test_install_and_uninstall_hook() {
+        let dir = tempfile::tempdir().unwrap();
+        let hooks = dir.path().join("hooks");
+        let script = hook_script(
+            PREPARE_COMMIT_MSG,
+            Path::new("/opt/convcom's/convcom"),
+            false,
+        );
+        assert!(script.contains("convcom='/opt/convcom'\\''s/convcom'"));
+
+        assert_eq!(
+            install_hook(&hooks, "prepare-commit-msg", &script).unwrap(),
+            HookInstall::Installed
+        );
+        assert!(is_executable(&hooks.join("prepare-commit-msg")));
+        assert_eq!(
+            install_hook(&hooks, "prepare-commit-msg", &script).unwrap(),
+            HookInstall::Updated
+        );
+
+        assert!(!uninstall_hook(&hooks, "prepare-commit-msg").unwrap());
+        assert!(!hooks.join("prepare-commit-msg").exists());
+        assert!(uninstall_hook(&hooks, "prepare-commit-msg").is_err());
+    }
+
+    #[test]
+    fn test_install_chains_existing_hook() {
+        let dir = tempfile::tempdir().unwrap();
+        let hooks = dir.path().join("hooks");
+        let message = dir.path().join("COMMIT_EDITMSG");
+        std::fs::write(&message, "").unwrap();
+        write_hook(
+            &hooks,
+            "prepare-commit-msg",
+            "#!/bin/sh\necho \"Ticket: 42\" >> \"$1\"\n",
+        );
+
+        // A missing program must not block the commit
+        let script = hook_script(PREPARE_COMMIT_MSG, Path::new("/nonexistent/convcom"), false);
+        assert_eq!(
+            install_hook(&hooks, "prepare-commit-msg", &script).unwrap(),
+            HookInstall::Chained
+        );
+
+        let status = Command::new(hooks.join("prepare-commit-msg"))
+            .arg(&message)
+            .env("PATH", "/nonexistent")
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(std::fs::read_to_string(&message).unwrap(), "Ticket: 42\n");
+
+        // Uninstalling restores the original hook
+        assert!(uninstall_hook(&hooks, "prepare-commit-msg").unwrap());
+        let restored = std::fs::read_to_string(hooks.join("prepare-commit-msg")).unwrap();
+        assert!(restored.contains("Ticket: 42"));
+        assert!(!chained_hook_path(&hooks, "prepare-commit-msg").exists());
+        assert!(uninstall_hook(&hooks, "prepare-commit-msg").is_err());
+
+        // A hook that may reject the commit fails when convcom cannot run
+        let script = hook_script(COMMIT_MSG, Path::new("/nonexistent/convcom"), true);
+        install_hook(&hooks, COMMIT_MSG, &script).unwrap();
+        let status = Command::new(hooks.join(COMMIT_MSG))
+            .arg(&message)
+            .env("PATH", "/nonexistent")
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!status.success());
+    }
+
+    #[test]
+    fn test_should_prepare_message() {
+        assert!(should_prepare_message(None));
+        assert!(should_prepare_message(Some("template")));
+        for source in ["message", "merge", "squash", "commit"] {
+            assert!(!should_prepare_message(Some(source)));
+        }
+    }
+}
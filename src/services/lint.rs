@@ -0,0 +1,444 @@
+use crate::error::{ConvComError, Result};
+use crate::models::commit::{
+    BREAKING_CHANGE, ConventionalCommit, Footer, FooterSeparator, Header, footer_start,
+};
+use std::fmt;
+use std::io::Read;
+
+/// Types from the Conventional Commits spec and the commit template
+pub const DEFAULT_TYPES: &[&str] = &[
+    "feat", "fix", "build", "chore", "ci", "docs", "perf", "refactor", "revert", "style", "test",
+];
+
+/// Default limit for the header line, matching git's one-line summaries
+pub const DEFAULT_MAX_HEADER_LENGTH: usize = 72;
+
+/// Line git puts above the diff in `git commit -v`; everything below it is dropped
+const SCISSORS: &str = "# ------------------------ >8 ------------------------";
+
+/// Which messages the linter accepts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintRules {
+    /// Allowed types; empty allows any
+    pub types: Vec<String>,
+    /// Allowed scopes; empty allows any
+    pub scopes: Vec<String>,
+    pub max_header_length: usize,
+}
+
+impl Default for LintRules {
+    fn default() -> Self {
+        Self {
+            types: DEFAULT_TYPES.iter().map(|t| t.to_string()).collect(),
+            scopes: Vec::new(),
+            max_header_length: DEFAULT_MAX_HEADER_LENGTH,
+        }
+    }
+}
+
+/// A rule violation on a line of the message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// 1-based line number in the original message, comments included
+    pub line: usize,
+    /// Short rule name, e.g. `header-max-length`
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} [{}]", self.line, self.message, self.rule)
+    }
+}
+
+/// Read a commit message from a file, or from stdin when `source` is `-`
+pub fn read_message(source: &str) -> Result<String> {
+    if source == "-" {
+        let mut message = String::new();
+        std::io::stdin().read_to_string(&mut message)?;
+        return Ok(message);
+    }
+
+    std::fs::read_to_string(source)
+        .map_err(|e| ConvComError::IoError(format!("Cannot read message '{source}': {e}")))
+}
+
+/// Numbered lines of one paragraph of the message
+type Paragraph<'a> = Vec<(usize, &'a str)>;
+
+/// Check a commit message against the Conventional Commits rules
+///
+/// Comment lines and everything below the `git commit -v` scissors line
+/// are ignored, like git does when it cleans up the message.
+pub fn lint(message: &str, rules: &LintRules) -> Vec<Diagnostic> {
+    let paragraphs = paragraphs(message);
+    let Some(&(line, header)) = paragraphs.first().and_then(|paragraph| paragraph.first()) else {
+        return vec![Diagnostic {
+            line: 1,
+            rule: "empty-message",
+            message: "the commit message is empty".to_string(),
+        }];
+    };
+
+    let mut diagnostics = lint_header(line, header, rules);
+
+    if let Some(&(line, _)) = paragraphs[0].get(1) {
+        diagnostics.push(Diagnostic {
+            line,
+            rule: "body-leading-blank",
+            message: "the body must be separated from the header by a blank line".to_string(),
+        });
+    }
+
+    // The parser's footers, which may span several trailing paragraphs
+    let first_lines: Vec<&str> = paragraphs[1..]
+        .iter()
+        .map(|paragraph| paragraph[0].1)
+        .collect();
+    let footers = footer_start(&first_lines);
+    if let Some(start) = footers {
+        let lines: Paragraph = paragraphs[start + 1..].concat();
+        lint_footers(&lines, &mut diagnostics);
+    } else if paragraphs.len() > 1 {
+        let last = &paragraphs[paragraphs.len() - 1];
+        if Footer::parse_loose(last[0].1).is_some() {
+            lint_footers(last, &mut diagnostics);
+        } else if let Some(start) = trailing_footers(last) {
+            diagnostics.push(Diagnostic {
+                line: last[start].0,
+                rule: "footer-leading-blank",
+                message: "footers must be separated from the body by a blank line".to_string(),
+            });
+            lint_footers(&last[start..], &mut diagnostics);
+        }
+    }
+
+    diagnostics
+}
+
+/// Take the commit message out of a model's answer
+///
+/// Models sometimes open with a line like `Here is the commit message:` or
+/// wrap the message in a code fence. Everything before the first header
+/// line is dropped, and so is everything after a fence that was opened
+/// before it. Answers without a header line are only trimmed.
+pub fn extract_message(answer: &str) -> String {
+    let is_header = |line: &str| {
+        Header::parse(line.trim()).is_some_and(|header| !header.description.trim().is_empty())
+    };
+    let lines: Vec<&str> = answer.trim().lines().collect();
+    let Some(start) = lines.iter().position(|line| is_header(line)) else {
+        return answer.trim().to_string();
+    };
+
+    let is_fence = |line: &&str| line.trim_start().starts_with("```");
+    let mut end = lines.len();
+    if lines[..start].iter().any(is_fence) {
+        end = lines[start..]
+            .iter()
+            .position(is_fence)
+            .map_or(end, |fence| start + fence);
+    }
+    lines[start..end].join("\n").trim().to_string()
+}
+
+/// Lint a message and parse it, or list everything that is wrong with it
+///
+/// Unlike `lint`, comment lines are not skipped: a message that only passes
+/// once git strips them is rejected by the parser.
+pub fn validate(
+    message: &str,
+    rules: &LintRules,
+) -> std::result::Result<ConventionalCommit, Vec<Diagnostic>> {
+    let diagnostics = lint(message, rules);
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    ConventionalCommit::parse(message).map_err(|e| {
+        vec![Diagnostic {
+            line: 1,
+            rule: "format",
+            message: e.to_string(),
+        }]
+    })
+}
+
+/// Split the cleaned-up message into paragraphs of numbered lines
+fn paragraphs(message: &str) -> Vec<Paragraph<'_>> {
+    let mut paragraphs = vec![Vec::new()];
+    let lines = message
+        .lines()
+        .enumerate()
+        .take_while(|(_, line)| *line != SCISSORS)
+        .filter(|(_, line)| !line.starts_with('#'));
+
+    for (index, line) in lines {
+        let line = line.trim_end();
+        if line.is_empty() {
+            if !paragraphs[paragraphs.len() - 1].is_empty() {
+                paragraphs.push(Vec::new());
+            }
+        } else {
+            let last = paragraphs.len() - 1;
+            paragraphs[last].push((index + 1, line));
+        }
+    }
+
+    paragraphs.retain(|paragraph| !paragraph.is_empty());
+    paragraphs
+}
+
+/// Check the type, scope, description and length of the header
+fn lint_header(line: usize, header: &str, rules: &LintRules) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut report = |rule, message: String| {
+        diagnostics.push(Diagnostic {
+            line,
+            rule,
+            message,
+        })
+    };
+
+    let length = header.chars().count();
+    if length > rules.max_header_length {
+        report(
+            "header-max-length",
+            format!(
+                "the header is {length} characters long, the limit is {}",
+                rules.max_header_length
+            ),
+        );
+    }
+
+    let Some(parsed) = Header::parse(header) else {
+        report(
+            "header-format",
+            "the header must look like 'type(scope): description'".to_string(),
+        );
+        return diagnostics;
+    };
+
+    let commit_type = parsed.commit_type.to_lowercase();
+    if parsed.commit_type != commit_type {
+        report(
+            "type-case",
+            format!("the type '{}' must be lowercase", parsed.commit_type),
+        );
+    }
+    if !rules.types.is_empty() && !rules.types.contains(&commit_type) {
+        report(
+            "type-enum",
+            format!(
+                "unknown type '{}', expected one of: {}",
+                parsed.commit_type,
+                rules.types.join(", ")
+            ),
+        );
+    }
+
+    match parsed.scope.map(str::trim) {
+        Some("") => report(
+            "scope-empty",
+            "the scope is empty; drop the parentheses".to_string(),
+        ),
+        Some(scope) if !rules.scopes.is_empty() && !rules.scopes.iter().any(|s| s == scope) => {
+            report(
+                "scope-enum",
+                format!(
+                    "unknown scope '{scope}', expected one of: {}",
+                    rules.scopes.join(", ")
+                ),
+            )
+        }
+        _ => {}
+    }
+
+    if parsed.description.trim().is_empty() {
+        report(
+            "description-empty",
+            "the description after the colon is missing".to_string(),
+        );
+    }
+
+    diagnostics
+}
+
+/// Find footers at the end of a body paragraph that lack a blank line before them
+fn trailing_footers(paragraph: &[(usize, &str)]) -> Option<usize> {
+    let start = paragraph
+        .iter()
+        .rposition(|(_, line)| Footer::parse(line).is_none())?
+        + 1;
+    (start < paragraph.len()).then_some(start)
+}
+
+/// Check footer tokens, treating other lines as continued values
+fn lint_footers(footers: &[(usize, &str)], diagnostics: &mut Vec<Diagnostic>) {
+    for &(line, text) in footers {
+        let Some(footer) = Footer::parse_loose(text) else {
+            continue;
+        };
+
+        if footer.is_breaking_change() {
+            if footer.token != BREAKING_CHANGE && footer.token != "BREAKING-CHANGE" {
+                diagnostics.push(Diagnostic {
+                    line,
+                    rule: "breaking-change-case",
+                    message: format!("'{}' must be written '{BREAKING_CHANGE}'", footer.token),
+                });
+            } else if footer.separator != FooterSeparator::Colon {
+                diagnostics.push(Diagnostic {
+                    line,
+                    rule: "footer-token",
+                    message: format!("'{}' must be followed by ': '", footer.token),
+                });
+            }
+        } else if Footer::parse(text).is_none() {
+            diagnostics.push(Diagnostic {
+                line,
+                rule: "footer-token",
+                message: format!(
+                    "the footer token '{}' must use '-' instead of spaces, e.g. '{}'",
+                    footer.token,
+                    footer.token.replace(' ', "-")
+                ),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules_of(message: &str) -> Vec<(usize, &'static str)> {
+        lint(message, &LintRules::default())
+            .into_iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.rule))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_message() {
+        let message = "feat(parser)!: accept quoted paths\n\n\
+                       * Unquote C-style paths.\n\n\
+                       BREAKING CHANGE: paths are now unquoted\n\
+                       and compared byte by byte\n\
+                       Refs #12\n";
+        assert_eq!(rules_of(message), vec![]);
+    }
+
+    #[test]
+    fn test_header_rules() {
+        assert_eq!(rules_of("Add a parser"), vec![(1, "header-format")]);
+        assert_eq!(
+            rules_of("Feature: add a parser"),
+            vec![(1, "type-case"), (1, "type-enum")]
+        );
+        assert_eq!(
+            rules_of("fix(): "),
+            vec![(1, "scope-empty"), (1, "description-empty")]
+        );
+        assert_eq!(
+            rules_of(&format!("fix: {}", "x".repeat(70))),
+            vec![(1, "header-max-length")]
+        );
+
+        let rules = LintRules {
+            scopes: vec!["api".to_string()],
+            ..LintRules::default()
+        };
+        let diagnostics = lint("fix(cli): handle errors", &rules);
+        assert_eq!(diagnostics[0].rule, "scope-enum");
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "1: unknown scope 'cli', expected one of: api [scope-enum]"
+        );
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        assert_eq!(
+            rules_of("fix: handle errors\n* Return early.\n"),
+            vec![(2, "body-leading-blank")]
+        );
+        assert_eq!(
+            rules_of("fix: handle errors\n\n* Return early.\nRefs #4\n"),
+            vec![(4, "footer-leading-blank")]
+        );
+    }
+
+    #[test]
+    fn test_footer_rules() {
+        assert_eq!(
+            rules_of("fix: a\n\nbody\n\nReviewed by: Alice\nBreaking change: the API\n"),
+            vec![(5, "footer-token"), (6, "breaking-change-case")]
+        );
+        assert_eq!(
+            rules_of("fix: a\n\nBREAKING CHANGE #12\n"),
+            vec![(3, "footer-token")]
+        );
+        // Footers span the trailing footer paragraphs, as in the parser
+        assert_eq!(
+            rules_of(
+                "fix: a\n\nbreaking-change: a\n\nmore about it\n\nRefs #1\n\nbreaking-change: b\n"
+            ),
+            vec![(9, "breaking-change-case")]
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let commit = validate(
+            "fix: handle errors\n\n* Return early.\n",
+            &LintRules::default(),
+        )
+        .unwrap();
+        assert_eq!(commit.description, "handle errors");
+
+        let diagnostics = validate(
+            "Here is your commit message:\n\nfix: handle errors",
+            &LintRules::default(),
+        )
+        .unwrap_err();
+        assert_eq!(diagnostics[0].rule, "header-format");
+
+        let diagnostics =
+            validate("# Generated\nfix: handle errors", &LintRules::default()).unwrap_err();
+        assert_eq!(diagnostics[0].rule, "format");
+    }
+
+    #[test]
+    fn test_extract_message() {
+        let message = "fix: handle errors\n\n* Return early.";
+        assert_eq!(extract_message(message), message);
+        assert_eq!(
+            extract_message(&format!("Here is the commit message:\n\n{message}\n")),
+            message
+        );
+        assert_eq!(
+            extract_message(&format!(
+                "Sure!\n```text\n{message}\n```\nLet me know if you want changes."
+            )),
+            message
+        );
+
+        // Fences after the header are part of the body
+        let with_code = "docs: show the config\n\n```toml\nmodel = \"haiku\"\n```";
+        assert_eq!(extract_message(with_code), with_code);
+        assert_eq!(
+            extract_message("  I could not find any changes.\n"),
+            "I could not find any changes."
+        );
+    }
+
+    #[test]
+    fn test_comments_are_ignored() {
+        let message = "# Please enter the commit message\nfix: a\n\n# Comment\n* Body.\n\
+                       # ------------------------ >8 ------------------------\ndiff --git a b\n";
+        assert_eq!(rules_of(message), vec![]);
+        assert_eq!(rules_of("# only a comment\n\n"), vec![(1, "empty-message")]);
+    }
+}
@@ -1,3 +1,11 @@
 pub mod ai_service;
+pub mod binary;
+pub mod exclusions;
 pub mod git_service;
+pub mod hooks;
+pub mod lint;
+pub mod patch;
 pub mod providers;
+pub mod retry;
+pub mod signing;
+pub mod stream;
@@ -0,0 +1,483 @@
+use crate::error::{ConvComError, Result};
+use crate::models::diff::{BinaryInfo, ChangeKind, DiffHunk, DiffLine, FileChange, LineKind};
+use crate::services::exclusions::ExclusionRules;
+use regex::Regex;
+use std::io::Read;
+use std::sync::LazyLock;
+
+/// Start and length of each side from a hunk's `@@` header
+static HUNK_HEADER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^@@ -(\d+)(?:,(\d+))? \+(\d+)(?:,(\d+))? @@").unwrap());
+
+/// Read a patch from a file, or from stdin when `source` is `-`, and parse it
+///
+/// Files matching the exclusion rules are marked for summarising just like
+/// changes collected from a repository.
+pub fn load_patch(source: &str, exclusions: &ExclusionRules) -> Result<Vec<FileChange>> {
+    let input = if source == "-" {
+        let mut input = String::new();
+        std::io::stdin().read_to_string(&mut input)?;
+        input
+    } else {
+        std::fs::read_to_string(source)
+            .map_err(|e| ConvComError::IoError(format!("Cannot read patch '{source}': {e}")))?
+    };
+
+    let mut changes = parse_patch(&input)?;
+    for file in &mut changes {
+        file.excluded = exclusions.check(&file.path);
+    }
+
+    Ok(changes)
+}
+
+/// Parse a unified or git-format patch into per-file changes
+///
+/// Anything before the first file header, such as the message of a
+/// `git format-patch` email, is skipped. Hunk lines are consumed by the
+/// counts in their `@@` header, so removed lines starting with `--` are
+/// never mistaken for a file header.
+pub fn parse_patch(input: &str) -> Result<Vec<FileChange>> {
+    let mut changes: Vec<FileChange> = Vec::new();
+    // Whether the last file came from `diff --git` and is still in its extended header
+    let mut in_git_header = false;
+    // Lines still expected in the current hunk on the old and new side
+    let mut remaining = (0, 0);
+    let mut lineno = (0, 0);
+    let mut lines = input.lines().enumerate().peekable();
+
+    while let Some((index, line)) = lines.next() {
+        let invalid =
+            |message: &str| ConvComError::PatchError(format!("line {}: {message}", index + 1));
+
+        if remaining != (0, 0) {
+            let (kind, old_lineno, new_lineno) = match line.chars().next() {
+                Some('+') if remaining.1 > 0 => {
+                    remaining.1 -= 1;
+                    lineno.1 += 1;
+                    (LineKind::Added, None, Some(lineno.1))
+                }
+                Some('-') if remaining.0 > 0 => {
+                    remaining.0 -= 1;
+                    lineno.0 += 1;
+                    (LineKind::Removed, Some(lineno.0), None)
+                }
+                // Some tools strip the single space from empty context lines
+                Some(' ') | None if remaining.0 > 0 && remaining.1 > 0 => {
+                    remaining = (remaining.0 - 1, remaining.1 - 1);
+                    lineno = (lineno.0 + 1, lineno.1 + 1);
+                    (LineKind::Context, Some(lineno.0), Some(lineno.1))
+                }
+                // `\ No newline at end of file`
+                Some('\\') => continue,
+                _ => return Err(invalid("hunk is shorter than its header says")),
+            };
+
+            if let Some(hunk) = changes.last_mut().and_then(|c| c.hunks.last_mut()) {
+                hunk.lines.push(DiffLine {
+                    kind,
+                    old_lineno,
+                    new_lineno,
+                    content: line.get(1..).unwrap_or_default().to_string(),
+                });
+            }
+            continue;
+        }
+
+        if let Some(paths) = line.strip_prefix("diff --git ") {
+            let (old_path, new_path) = split_git_paths(paths);
+            changes.push(FileChange {
+                old_path: Some(old_path),
+                ..FileChange::new(ChangeKind::Modified, new_path)
+            });
+            in_git_header = true;
+        } else if let Some(old_header) = line.strip_prefix("--- ")
+            && let Some((_, new_line)) = lines.peek()
+            && let Some(new_header) = new_line.strip_prefix("+++ ")
+        {
+            let old_path = header_path(old_header, "a/");
+            let new_path = header_path(new_header, "b/");
+            lines.next();
+
+            if !in_git_header {
+                changes.push(FileChange::new(ChangeKind::Modified, String::new()));
+            }
+            let change = changes.last_mut().expect("file change was just pushed");
+            match (old_path, new_path) {
+                (None, Some(new_path)) => {
+                    change.kind = ChangeKind::Added;
+                    change.path = new_path;
+                }
+                (Some(old_path), None) => {
+                    change.kind = ChangeKind::Deleted;
+                    change.path = old_path;
+                }
+                (Some(old_path), Some(new_path)) => {
+                    change.old_path = Some(old_path);
+                    change.path = new_path;
+                }
+                (None, None) => return Err(invalid("both sides of the file are /dev/null")),
+            }
+            in_git_header = false;
+        } else if let Some(captures) = HUNK_HEADER.captures(line) {
+            let number = |group: usize| {
+                captures
+                    .get(group)
+                    .map_or(Ok(1), |m| m.as_str().parse::<u32>())
+                    .map_err(|_| invalid("line number out of range in hunk header"))
+            };
+            let (old_start, old_lines) = (number(1)?, number(2)?);
+            let (new_start, new_lines) = (number(3)?, number(4)?);
+            let change = changes
+                .last_mut()
+                .ok_or_else(|| invalid("hunk before any file header"))?;
+
+            change.hunks.push(DiffHunk {
+                old_start,
+                old_lines,
+                new_start,
+                new_lines,
+                header: line.trim_end().to_string(),
+                lines: Vec::new(),
+            });
+            remaining = (old_lines, new_lines);
+            // A hunk covering no lines on one side starts after line `start` instead of at it
+            lineno = (
+                old_start.saturating_sub(u32::from(old_lines > 0)),
+                new_start.saturating_sub(u32::from(new_lines > 0)),
+            );
+            in_git_header = false;
+        } else if in_git_header && let Some(change) = changes.last_mut() {
+            apply_extended_header(change, line);
+        }
+    }
+
+    for change in &mut changes {
+        if !matches!(change.kind, ChangeKind::Renamed | ChangeKind::Copied) {
+            change.old_path = None;
+        }
+    }
+
+    if changes.is_empty() {
+        return Err(ConvComError::NoChangesError("in the patch".to_string()));
+    }
+
+    Ok(changes)
+}
+
+/// Apply one extended header line of a git-format patch, e.g. `rename from old.rs`
+fn apply_extended_header(change: &mut FileChange, line: &str) {
+    let mode = |value: &str| u32::from_str_radix(value.trim(), 8).unwrap_or(0);
+
+    if let Some(value) = line.strip_prefix("new file mode ") {
+        change.kind = ChangeKind::Added;
+        change.new_mode = mode(value);
+    } else if let Some(value) = line.strip_prefix("deleted file mode ") {
+        change.kind = ChangeKind::Deleted;
+        change.old_mode = mode(value);
+        change.path = change.old_path.clone().unwrap_or_default();
+    } else if let Some(value) = line.strip_prefix("old mode ") {
+        change.old_mode = mode(value);
+    } else if let Some(value) = line.strip_prefix("new mode ") {
+        change.new_mode = mode(value);
+    } else if let Some(value) = line.strip_prefix("index ") {
+        // `index 1a2b3c4..5d6e7f8 100644` carries the mode of unchanged-mode files
+        if let Some((_, value)) = value.split_once(' ') {
+            change.old_mode = mode(value);
+            change.new_mode = change.old_mode;
+        }
+    } else if let Some(value) = line
+        .strip_prefix("similarity index ")
+        .and_then(|value| value.strip_suffix('%'))
+    {
+        change.similarity = value.parse().ok();
+    } else if let Some(path) = line.strip_prefix("rename from ") {
+        change.kind = ChangeKind::Renamed;
+        change.old_path = Some(unquote(path));
+    } else if let Some(path) = line.strip_prefix("rename to ") {
+        change.path = unquote(path);
+    } else if let Some(path) = line.strip_prefix("copy from ") {
+        change.kind = ChangeKind::Copied;
+        change.old_path = Some(unquote(path));
+    } else if let Some(path) = line.strip_prefix("copy to ") {
+        change.path = unquote(path);
+    } else if line.starts_with("Binary files ") || line == "GIT binary patch" {
+        change.binary.get_or_insert_with(BinaryInfo::default);
+    } else if let Some(size) = line.strip_prefix("literal ")
+        && let Some(info) = change.binary.as_mut()
+    {
+        // The forward (new) literal comes first, then the reverse (old) one
+        let size = size.parse().ok();
+        if info.new_size.is_none() {
+            info.new_size = size;
+        } else {
+            info.old_size = size;
+        }
+    }
+}
+
+/// Split the `a/old b/new` part of a `diff --git` line into prefix-free paths
+fn split_git_paths(paths: &str) -> (String, String) {
+    if paths.starts_with('"') {
+        let tokens = quoted_tokens(paths);
+        if let [old, new] = tokens.as_slice() {
+            return (strip_prefix(old, "a/"), strip_prefix(new, "b/"));
+        }
+    }
+
+    // Without renames both halves are equal, which also handles paths with spaces
+    let middle = paths.len() / 2;
+    if paths.len() % 2 == 1 && paths.is_char_boundary(middle) {
+        let (old, new) = (&paths[..middle], &paths[middle + 1..]);
+        if old.strip_prefix("a/") == new.strip_prefix("b/") {
+            return (strip_prefix(old, "a/"), strip_prefix(new, "b/"));
+        }
+    }
+
+    match paths.rsplit_once(" b/") {
+        Some((old, new)) => (strip_prefix(old, "a/"), new.to_string()),
+        None => match paths.split_once(' ') {
+            Some((old, new)) => (old.to_string(), new.to_string()),
+            None => (paths.to_string(), paths.to_string()),
+        },
+    }
+}
+
+/// Path from a `---` or `+++` header, or None for `/dev/null`
+fn header_path(header: &str, prefix: &str) -> Option<String> {
+    // Plain `diff -u` output appends a tab and a timestamp
+    let path = header.split('\t').next().unwrap_or(header).trim_end();
+    if path == "/dev/null" {
+        return None;
+    }
+
+    Some(strip_prefix(&unquote(path), prefix))
+}
+
+fn strip_prefix(path: &str, prefix: &str) -> String {
+    path.strip_prefix(prefix).unwrap_or(path).to_string()
+}
+
+/// Remove the C-style quoting git applies to paths with special characters
+fn unquote(path: &str) -> String {
+    quoted_tokens(path)
+        .into_iter()
+        .next()
+        .filter(|_| path.starts_with('"'))
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Split a string into double-quoted tokens, resolving backslash escapes
+fn quoted_tokens(input: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '"' {
+            continue;
+        }
+        let mut token = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('t') => token.push('\t'),
+                    Some('n') => token.push('\n'),
+                    Some(other) => token.push(other),
+                    None => {}
+                },
+                other => token.push(other),
+            }
+        }
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIT_PATCH: &str = "\
+From 1234567 Mon Sep 17 00:00:00 2001
+Subject: [PATCH] Tidy things up
+
+---
+ src/lib.rs | 3 ++-
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,4 @@ mod config;
+ pub mod a;
+--- old comment
++pub mod b;
++pub mod c;
+ pub mod d;
+\\ No newline at end of file
+diff --git a/docs/new.md b/docs/new.md
+new file mode 100644
+index 0000000..3333333
+--- /dev/null
++++ b/docs/new.md
+@@ -0,0 +1,2 @@
++# New
++
+diff --git a/old.txt b/old.txt
+deleted file mode 100644
+index 4444444..0000000
+--- a/old.txt
++++ /dev/null
+@@ -1 +0,0 @@
+-gone
+diff --git a/src/before.rs b/src/after.rs
+similarity index 90%
+rename from src/before.rs
+rename to src/after.rs
+index 5555555..6666666 100644
+--- a/src/before.rs
++++ b/src/after.rs
+@@ -10,2 +10,2 @@ fn main() {
+-    old();
++    new();
+     done();
+diff --git a/run.sh b/run.sh
+old mode 100644
+new mode 100755
+diff --git a/logo.png b/logo.png
+index 7777777..8888888 100644
+GIT binary patch
+literal 1423
+zcmV;A1#tR_P)<h;3K|Lk000e1NJLTq001BW001Be1^@s6qoY5r0003
+
+literal 980
+zcmV-?1B3jDP)<h;3K|Lk000e1NJLTq0000W0000W2m$~A07rsn0003
+";
+
+    #[test]
+    fn test_git_patch() {
+        let changes = parse_patch(GIT_PATCH).unwrap();
+        let kinds: Vec<(&str, ChangeKind)> = changes
+            .iter()
+            .map(|file| (file.path.as_str(), file.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("src/lib.rs", ChangeKind::Modified),
+                ("docs/new.md", ChangeKind::Added),
+                ("old.txt", ChangeKind::Deleted),
+                ("src/after.rs", ChangeKind::Renamed),
+                ("run.sh", ChangeKind::Modified),
+                ("logo.png", ChangeKind::Modified),
+            ]
+        );
+
+        let lib = &changes[0];
+        assert_eq!(lib.old_path, None);
+        assert_eq!(lib.hunks[0].header, "@@ -1,3 +1,4 @@ mod config;");
+        let removed: Vec<&DiffLine> = lib
+            .changed_lines()
+            .filter(|line| line.kind == LineKind::Removed)
+            .collect();
+        assert_eq!(removed[0].content, "-- old comment");
+        assert_eq!(removed[0].old_lineno, Some(2));
+        assert_eq!(lib.hunks[0].lines.len(), 5);
+
+        let new_doc = &changes[1];
+        assert_eq!(new_doc.new_mode, 0o100644);
+        assert_eq!(new_doc.hunks[0].lines[1].new_lineno, Some(2));
+
+        let renamed = &changes[3];
+        assert_eq!(renamed.old_path.as_deref(), Some("src/before.rs"));
+        assert_eq!(renamed.similarity, Some(90));
+        assert_eq!(renamed.hunks[0].lines[2].old_lineno, Some(11));
+
+        assert!(changes[4].mode_changed());
+        assert_eq!(
+            changes[5].binary,
+            Some(BinaryInfo {
+                old_size: Some(980),
+                new_size: Some(1423),
+                ..BinaryInfo::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_plain_unified_patch() {
+        let patch = "\
+--- notes.txt\t2024-05-01 10:00:00
++++ notes.txt\t2024-05-02 10:00:00
+@@ -1,2 +1,2 @@
+ keep
+-before
++after
+--- /dev/null
++++ b/added file.txt
+@@ -0,0 +1 @@
++hello
+";
+        let changes = parse_patch(patch).unwrap();
+
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].path, "notes.txt");
+        assert_eq!(changes[0].kind, ChangeKind::Modified);
+        assert_eq!(changes[1].path, "added file.txt");
+        assert_eq!(changes[1].kind, ChangeKind::Added);
+        assert_eq!(changes[1].hunks[0].lines[0].new_lineno, Some(1));
+    }
+
+    #[test]
+    fn test_quoted_and_spaced_paths() {
+        assert_eq!(
+            split_git_paths("a/my file.txt b/my file.txt"),
+            ("my file.txt".to_string(), "my file.txt".to_string())
+        );
+        assert_eq!(
+            split_git_paths(r#""a/tab\there" "b/tab\there""#),
+            ("tab\there".to_string(), "tab\there".to_string())
+        );
+        assert_eq!(
+            split_git_paths("a/old.rs b/new.rs"),
+            ("old.rs".to_string(), "new.rs".to_string())
+        );
+    }
+
+    #[test]
+    fn test_invalid_patches() {
+        let truncated = "--- a/x\n+++ b/x\n@@ -1,2 +1,2 @@\n-one\nnot a diff line\n";
+        assert!(matches!(
+            parse_patch(truncated),
+            Err(ConvComError::PatchError(message)) if message.starts_with("line 5:")
+        ));
+        assert!(matches!(
+            parse_patch("just some text\n"),
+            Err(ConvComError::NoChangesError(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_patch_applies_exclusions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("change.patch");
+        std::fs::write(
+            &path,
+            "--- a/Cargo.lock\n+++ b/Cargo.lock\n@@ -1 +1 @@\n-a\n+b\n",
+        )
+        .unwrap();
+
+        let changes = load_patch(path.to_str().unwrap(), &ExclusionRules::new()).unwrap();
+        assert_eq!(
+            changes[0].excluded,
+            Some(crate::models::diff::ExclusionReason::Lockfile)
+        );
+
+        let missing = dir.path().join("missing.patch");
+        assert!(matches!(
+            load_patch(missing.to_str().unwrap(), &ExclusionRules::new()),
+            Err(ConvComError::IoError(_))
+        ));
+    }
+}
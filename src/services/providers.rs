@@ -1,36 +1,93 @@
+use crate::config::secrets::ApiKey;
+use crate::config::settings::{DEFAULT_TEMPERATURE, OllamaSettings, OpenAiSettings};
 use crate::error::{ConvComError, Result};
-use crate::models::ai::{ChatCompletionRequest, ChatCompletionResponse, ChatMessage};
-use crate::models::providers::{AiProvider, ModelName};
+use crate::models::ai::{
+    ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, ChatMessage, Completion,
+    OllamaChatChunk, OllamaTags, StreamOptions, TokenUsage,
+};
+use crate::models::providers::{AiProvider, Model};
+use crate::services::retry::RetryPolicy;
+use crate::services::stream::{ThinkFilter, TokenSink, read_events, read_lines, strip_thinking};
 use async_trait::async_trait;
-use regex::Regex;
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder, Response};
 use serde_json::{Value, json};
 use std::time::Duration;
 
+/// Base URL of Groq's OpenAI-compatible API
+const GROQ_BASE_URL: &str = "https://api.groq.com/openai/v1";
+
+/// Anthropic Messages API endpoint
+const ANTHROPIC_MESSAGES_URL: &str = "https://api.anthropic.com/v1/messages";
+
+/// Longest wait for a connection to a provider
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest wait for a whole answer from a hosted API, or between two pieces of a streamed one
+const HOSTED_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Like `HOSTED_TIMEOUT` for Ollama, as loading a model from disk takes far longer
+const OLLAMA_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// An HTTP client for provider requests
+///
+/// Only connecting has a limit here: streamed answers may take as long as
+/// they keep arriving, so each request sets its own timeout.
+fn http_client() -> Result<Client> {
+    Client::builder()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .build()
+        .map_err(|e| ConvComError::HttpClientError(e.to_string()))
+}
+
 /// Trait for AI providers
 #[async_trait]
 pub trait AiProviderTrait: Send + Sync {
-    async fn generate_message(&self, prompt: String, model: ModelName) -> Result<String>;
+    async fn generate_message(&self, prompt: String, model: &Model) -> Result<Completion>;
+
+    /// Generate a message, passing the cleaned text to `sink` as it arrives
+    ///
+    /// Returns the same completion as `generate_message` for the same answer.
+    async fn stream_message(
+        &self,
+        prompt: String,
+        model: &Model,
+        sink: &mut dyn TokenSink,
+    ) -> Result<Completion>;
+
     fn clean_output(&self, raw_output: &str) -> String;
 }
 
-/// Groq AI Provider implementation
-pub struct GroqProvider {
+/// Provider for any OpenAI-compatible chat completions endpoint
+pub struct OpenAiCompatibleProvider {
     client: Client,
-    api_key: String,
+    settings: OpenAiSettings,
+    temperature: f32,
+    retry: RetryPolicy,
 }
 
-impl GroqProvider {
-    pub fn new(api_key: String) -> Result<Self> {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()
-            .map_err(|e| ConvComError::HttpClientError(e.to_string()))?;
+impl OpenAiCompatibleProvider {
+    pub fn new(settings: OpenAiSettings) -> Result<Self> {
+        Ok(Self {
+            client: http_client()?,
+            settings,
+            temperature: DEFAULT_TEMPERATURE,
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    /// Sample with `temperature` instead of the default
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature;
+        self
+    }
 
-        Ok(Self { client, api_key })
+    /// Retry failed requests according to `retry` instead of the default policy
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
     }
 
-    fn build_chat_request(&self, prompt: String, model: ModelName) -> ChatCompletionRequest {
+    fn build_chat_request(&self, prompt: String, model: &Model) -> ChatCompletionRequest {
         let messages = vec![
             ChatMessage::system(
                 "You are a helpful AI assistant that generates conventional commit messages.",
@@ -39,41 +96,50 @@ impl GroqProvider {
         ];
 
         ChatCompletionRequest {
-            model: model.as_str().to_string(),
+            model: model.id.clone(),
             messages,
-            temperature: 0.5,
-            max_tokens: 1024,
+            temperature: self.temperature,
+            max_tokens: model.output_tokens(),
+            stream: false,
+            stream_options: None,
         }
     }
 
-    async fn make_groq_request(
-        &self,
-        request: ChatCompletionRequest,
-    ) -> Result<ChatCompletionResponse> {
-        let url = "https://api.groq.com/openai/v1/chat/completions";
+    async fn send_chat_request(&self, request: &ChatCompletionRequest) -> Result<Response> {
+        let url = format!(
+            "{}/chat/completions",
+            self.settings.base_url.trim_end_matches('/')
+        );
 
-        let response = self
+        let mut request_builder = self
             .client
             .post(url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
             .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| ConvComError::ApiRequestError(e.to_string()))?;
+            .json(request);
+        if !request.stream {
+            request_builder = request_builder.timeout(self.settings.timeout);
+        }
+        if let Some(api_key) = &self.settings.api_key {
+            let value = if self
+                .settings
+                .auth_header
+                .eq_ignore_ascii_case("Authorization")
+            {
+                format!("Bearer {}", api_key.expose())
+            } else {
+                api_key.expose().to_string()
+            };
+            request_builder = request_builder.header(self.settings.auth_header.as_str(), value);
+        }
 
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
+        self.retry.send(request_builder).await
+    }
 
-            return Err(ConvComError::ApiError {
-                status_code: status.as_u16(),
-                message: error_text,
-            });
-        }
+    async fn make_chat_request(
+        &self,
+        request: ChatCompletionRequest,
+    ) -> Result<ChatCompletionResponse> {
+        let response = self.send_chat_request(&request).await?;
 
         let chat_response: ChatCompletionResponse = response
             .json()
@@ -82,19 +148,11 @@ impl GroqProvider {
 
         Ok(chat_response)
     }
-}
-
-#[async_trait]
-impl AiProviderTrait for GroqProvider {
-    async fn generate_message(&self, prompt: String, model: ModelName) -> Result<String> {
-        if model.provider() != AiProvider::Groq {
-            return Err(ConvComError::ConfigError(format!(
-                "Model {model} is not supported by Groq provider"
-            )));
-        }
 
+    /// Send the prompt to the endpoint and clean the first choice
+    async fn complete(&self, prompt: String, model: &Model) -> Result<Completion> {
         let request = self.build_chat_request(prompt, model);
-        let response = self.make_groq_request(request).await?;
+        let response = self.make_chat_request(request).await?;
 
         let raw_output = response
             .choices
@@ -103,77 +161,234 @@ impl AiProviderTrait for GroqProvider {
             .map(|choice| choice.message.content)
             .ok_or(ConvComError::EmptyResponseError)?;
 
-        Ok(self.clean_output(&raw_output))
+        Ok(Completion {
+            content: self.clean_output(&raw_output),
+            usage: response.usage,
+        })
+    }
+
+    /// Stream the answer as server-sent events, passing on the cleaned text
+    async fn stream(
+        &self,
+        prompt: String,
+        model: &Model,
+        sink: &mut dyn TokenSink,
+    ) -> Result<Completion> {
+        let request = ChatCompletionRequest {
+            stream: true,
+            stream_options: Some(StreamOptions {
+                include_usage: true,
+            }),
+            ..self.build_chat_request(prompt, model)
+        };
+        let mut response = self.send_chat_request(&request).await?;
+
+        let mut filter = ThinkFilter::new("think");
+        let mut raw_output = String::new();
+        let mut usage = None;
+        read_events(&mut response, self.settings.timeout, |data| {
+            if data == "[DONE]" {
+                return Ok(());
+            }
+            let chunk: ChatCompletionChunk = serde_json::from_str(data)
+                .map_err(|e| ConvComError::ResponseParseError(e.to_string()))?;
+            if let Some(error) = chunk.error {
+                return Err(stream_error(&error));
+            }
+
+            let text = chunk
+                .choices
+                .into_iter()
+                .next()
+                .and_then(|choice| choice.delta.content);
+            if let Some(text) = text {
+                sink.token(&filter.push(&text));
+                raw_output.push_str(&text);
+            }
+            usage = chunk
+                .usage
+                .or(chunk.x_groq.and_then(|groq| groq.usage))
+                .or(usage);
+            Ok(())
+        })
+        .await?;
+        sink.token(&filter.finish());
+
+        if raw_output.is_empty() {
+            return Err(ConvComError::EmptyResponseError);
+        }
+        Ok(Completion {
+            content: self.clean_output(&raw_output),
+            usage,
+        })
+    }
+
+    fn check_model(&self, model: &Model) -> Result<()> {
+        if model.provider != AiProvider::OpenAi {
+            return Err(ConvComError::ConfigError(format!(
+                "Model {model} is not supported by the OpenAI-compatible provider"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AiProviderTrait for OpenAiCompatibleProvider {
+    async fn generate_message(&self, prompt: String, model: &Model) -> Result<Completion> {
+        self.check_model(model)?;
+        self.complete(prompt, model).await
+    }
+
+    async fn stream_message(
+        &self,
+        prompt: String,
+        model: &Model,
+        sink: &mut dyn TokenSink,
+    ) -> Result<Completion> {
+        self.check_model(model)?;
+        self.stream(prompt, model, sink).await
     }
 
     fn clean_output(&self, raw_output: &str) -> String {
-        // Remove thinking tags (similar to Python's regex)
-        let thinking_regex = Regex::new(r"(?s)<think>.*?</think>").unwrap();
-        let cleaned = thinking_regex.replace_all(raw_output, "");
+        strip_thinking("think", raw_output)
+    }
+}
+
+/// Groq AI Provider implementation, using Groq's OpenAI-compatible endpoint
+pub struct GroqProvider {
+    endpoint: OpenAiCompatibleProvider,
+}
+
+impl GroqProvider {
+    pub fn new(api_key: ApiKey) -> Result<Self> {
+        let endpoint = OpenAiCompatibleProvider::new(OpenAiSettings {
+            base_url: GROQ_BASE_URL.to_string(),
+            timeout: HOSTED_TIMEOUT,
+            ..OpenAiSettings::openai(api_key)
+        })?;
+
+        Ok(Self { endpoint })
+    }
+
+    /// Sample with `temperature` instead of the default
+    pub fn with_temperature(self, temperature: f32) -> Self {
+        Self {
+            endpoint: self.endpoint.with_temperature(temperature),
+        }
+    }
+
+    /// Retry failed requests according to `retry` instead of the default policy
+    pub fn with_retry_policy(self, retry: RetryPolicy) -> Self {
+        Self {
+            endpoint: self.endpoint.with_retry_policy(retry),
+        }
+    }
+
+    fn check_model(model: &Model) -> Result<()> {
+        if model.provider != AiProvider::Groq {
+            return Err(ConvComError::ConfigError(format!(
+                "Model {model} is not supported by Groq provider"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl AiProviderTrait for GroqProvider {
+    async fn generate_message(&self, prompt: String, model: &Model) -> Result<Completion> {
+        Self::check_model(model)?;
+        self.endpoint.complete(prompt, model).await
+    }
 
-        // Remove any remaining thinking tags
-        let cleaned = cleaned.replace("<think>", "").replace("</think>", "");
+    async fn stream_message(
+        &self,
+        prompt: String,
+        model: &Model,
+        sink: &mut dyn TokenSink,
+    ) -> Result<Completion> {
+        Self::check_model(model)?;
+        self.endpoint.stream(prompt, model, sink).await
+    }
 
-        // Trim leading whitespace
-        cleaned.trim_start().to_string()
+    fn clean_output(&self, raw_output: &str) -> String {
+        self.endpoint.clean_output(raw_output)
     }
 }
 
 /// Anthropic AI Provider implementation
 pub struct AnthropicProvider {
     client: Client,
-    api_key: String,
+    api_key: ApiKey,
+    url: String,
+    temperature: f32,
+    retry: RetryPolicy,
 }
 
 impl AnthropicProvider {
-    pub fn new(api_key: String) -> Result<Self> {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()
-            .map_err(|e| ConvComError::HttpClientError(e.to_string()))?;
+    pub fn new(api_key: ApiKey) -> Result<Self> {
+        Ok(Self {
+            client: http_client()?,
+            api_key,
+            url: ANTHROPIC_MESSAGES_URL.to_string(),
+            temperature: DEFAULT_TEMPERATURE,
+            retry: RetryPolicy::default(),
+        })
+    }
 
-        Ok(Self { client, api_key })
+    /// Sample with `temperature` instead of the default
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature;
+        self
     }
 
-    async fn make_anthropic_request(&self, prompt: String, model: ModelName) -> Result<String> {
-        let url = "https://api.anthropic.com/v1/messages";
+    #[cfg(test)]
+    fn with_url(self, url: String) -> Self {
+        Self { url, ..self }
+    }
+
+    /// Retry failed requests according to `retry` instead of the default policy
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
 
+    async fn send_anthropic_request(
+        &self,
+        prompt: String,
+        model: &Model,
+        stream: bool,
+    ) -> Result<Response> {
         let request_body = json!({
-            "model": model.as_str(),
-            "max_tokens": 1024,
-            "temperature": 0.5,
+            "model": model.id,
+            "max_tokens": model.output_tokens(),
+            "temperature": self.temperature,
             "system": "You are a helpful AI assistant that generates conventional commit messages.",
             "messages": [
                 {
                     "role": "user",
                     "content": prompt
                 }
-            ]
+            ],
+            "stream": stream
         });
 
-        let response = self
+        let mut request = self
             .client
-            .post(url)
-            .header("x-api-key", &self.api_key)
+            .post(&self.url)
+            .header("x-api-key", self.api_key.expose())
             .header("anthropic-version", "2023-06-01")
             .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| ConvComError::ApiRequestError(e.to_string()))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response
-                .text()
-                .await
-                .unwrap_or_else(|_| "Unknown error".to_string());
-
-            return Err(ConvComError::ApiError {
-                status_code: status.as_u16(),
-                message: error_text,
-            });
+            .json(&request_body);
+        if !stream {
+            request = request.timeout(HOSTED_TIMEOUT);
         }
+        self.retry.send(request).await
+    }
+
+    async fn make_anthropic_request(&self, prompt: String, model: &Model) -> Result<Completion> {
+        let response = self.send_anthropic_request(prompt, model, false).await?;
 
         let response_json: Value = response
             .json()
@@ -189,38 +404,340 @@ impl AnthropicProvider {
             .and_then(|text| text.as_str())
             .ok_or(ConvComError::EmptyResponseError)?;
 
-        Ok(content.to_string())
+        // Anthropic names the counts after input and output
+        let usage = response_json.get("usage").map(|usage| {
+            let count = |key: &str| usage.get(key).and_then(Value::as_u64).unwrap_or(0) as u32;
+            TokenUsage {
+                prompt_tokens: count("input_tokens"),
+                completion_tokens: count("output_tokens"),
+            }
+        });
+
+        Ok(Completion {
+            content: content.to_string(),
+            usage,
+        })
+    }
+
+    /// Stream the answer as server-sent events, passing on the cleaned text
+    async fn stream_anthropic_request(
+        &self,
+        prompt: String,
+        model: &Model,
+        sink: &mut dyn TokenSink,
+    ) -> Result<Completion> {
+        let mut response = self.send_anthropic_request(prompt, model, true).await?;
+
+        let mut filter = ThinkFilter::new("thinking");
+        let mut raw_output = String::new();
+        let mut usage = TokenUsage::default();
+        read_events(&mut response, HOSTED_TIMEOUT, |data| {
+            let event: Value = serde_json::from_str(data)
+                .map_err(|e| ConvComError::ResponseParseError(e.to_string()))?;
+            let count = |usage: Option<&Value>, key: &str| {
+                usage
+                    .and_then(|usage| usage.get(key))
+                    .and_then(Value::as_u64)
+            };
+
+            match event.get("type").and_then(Value::as_str) {
+                Some("message_start") => {
+                    let start_usage = event.pointer("/message/usage");
+                    usage.prompt_tokens = count(start_usage, "input_tokens").unwrap_or(0) as u32;
+                }
+                Some("content_block_delta") => {
+                    if let Some(text) = event.pointer("/delta/text").and_then(Value::as_str) {
+                        sink.token(&filter.push(text));
+                        raw_output.push_str(text);
+                    }
+                }
+                // The output count grows with each delta; the last one is the total
+                Some("message_delta") => {
+                    if let Some(tokens) = count(event.get("usage"), "output_tokens") {
+                        usage.completion_tokens = tokens as u32;
+                    }
+                }
+                // Such as overloaded_error, after the response has started
+                Some("error") => return Err(stream_error(&event)),
+                _ => {}
+            }
+            Ok(())
+        })
+        .await?;
+        sink.token(&filter.finish());
+
+        if raw_output.is_empty() {
+            return Err(ConvComError::EmptyResponseError);
+        }
+        Ok(Completion {
+            content: self.clean_output(&raw_output),
+            usage: Some(usage),
+        })
+    }
+
+    fn check_model(model: &Model) -> Result<()> {
+        if model.provider != AiProvider::Anthropic {
+            return Err(ConvComError::ConfigError(format!(
+                "Model {model} is not supported by Anthropic provider"
+            )));
+        }
+        Ok(())
     }
 }
 
 #[async_trait]
 impl AiProviderTrait for AnthropicProvider {
-    async fn generate_message(&self, prompt: String, model: ModelName) -> Result<String> {
-        if model.provider() != AiProvider::Anthropic {
+    async fn generate_message(&self, prompt: String, model: &Model) -> Result<Completion> {
+        Self::check_model(model)?;
+
+        let completion = self.make_anthropic_request(prompt, model).await?;
+        Ok(Completion {
+            content: self.clean_output(&completion.content),
+            ..completion
+        })
+    }
+
+    async fn stream_message(
+        &self,
+        prompt: String,
+        model: &Model,
+        sink: &mut dyn TokenSink,
+    ) -> Result<Completion> {
+        Self::check_model(model)?;
+        self.stream_anthropic_request(prompt, model, sink).await
+    }
+
+    fn clean_output(&self, raw_output: &str) -> String {
+        strip_thinking("thinking", raw_output)
+    }
+}
+
+/// Ollama provider, talking to a local daemon through its native chat API
+pub struct OllamaProvider {
+    client: Client,
+    settings: OllamaSettings,
+    temperature: f32,
+    retry: RetryPolicy,
+}
+
+impl OllamaProvider {
+    pub fn new(settings: OllamaSettings) -> Result<Self> {
+        Ok(Self {
+            client: http_client()?,
+            settings,
+            temperature: DEFAULT_TEMPERATURE,
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    /// Sample with `temperature` instead of the default
+    pub fn with_temperature(mut self, temperature: f32) -> Self {
+        self.temperature = temperature;
+        self
+    }
+
+    /// Retry failed requests according to `retry` instead of the default policy
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    fn url(&self, endpoint: &str) -> String {
+        format!(
+            "{}/api/{endpoint}",
+            self.settings.host.trim_end_matches('/')
+        )
+    }
+
+    fn build_chat_request(&self, prompt: String, model: &Model) -> Value {
+        let mut request = json!({
+            "model": model.id,
+            "messages": [
+                ChatMessage::system(
+                    "You are a helpful AI assistant that generates conventional commit messages.",
+                ),
+                ChatMessage::user(prompt),
+            ],
+            "stream": true,
+            "options": {
+                "temperature": self.temperature,
+                "num_predict": model.output_tokens(),
+                "num_ctx": model.context_window,
+            },
+        });
+
+        // Ollama reads a bare number as seconds and a string as a duration like "5m"
+        if let Some(keep_alive) = &self.settings.keep_alive {
+            request["keep_alive"] = match keep_alive.parse::<i64>() {
+                Ok(seconds) => json!(seconds),
+                Err(_) => json!(keep_alive),
+            };
+        }
+
+        request
+    }
+
+    async fn send(&self, retry: &RetryPolicy, request: RequestBuilder) -> Result<Response> {
+        retry.send(request).await.map_err(|e| match e {
+            ConvComError::NetworkError(e) => ConvComError::NetworkError(format!(
+                "{e} (is Ollama running at {}?)",
+                self.settings.host
+            )),
+            e => e,
+        })
+    }
+
+    /// List the names of the models installed in the daemon
+    pub async fn list_models(&self) -> Result<Vec<String>> {
+        // Listing is only informational, so a missing daemon is reported at once
+        let response = self
+            .send(
+                &RetryPolicy::none(),
+                self.client.get(self.url("tags")).timeout(OLLAMA_TIMEOUT),
+            )
+            .await?;
+        let tags: OllamaTags = response
+            .json()
+            .await
+            .map_err(|e| ConvComError::ResponseParseError(e.to_string()))?;
+
+        Ok(tags.models.into_iter().map(|model| model.name).collect())
+    }
+
+    /// Stream a chat response, passing each piece of raw text to `on_text`
+    async fn make_ollama_request(
+        &self,
+        prompt: String,
+        model: &Model,
+        on_text: &mut (dyn FnMut(&str) + Send),
+    ) -> Result<Completion> {
+        let request = self.build_chat_request(prompt, model);
+        let mut response = self
+            .send(
+                &self.retry,
+                self.client.post(self.url("chat")).json(&request),
+            )
+            .await?;
+
+        // Each line of the body is one JSON chunk
+        let mut completion = Completion {
+            content: String::new(),
+            usage: None,
+        };
+        let mut done = false;
+        read_lines(&mut response, OLLAMA_TIMEOUT, |line| {
+            done |= Self::read_chunk(line, &mut completion, on_text)?;
+            Ok(())
+        })
+        .await?;
+
+        if !done {
+            return Err(ConvComError::ResponseParseError(
+                "the response stream ended before the message was complete".to_string(),
+            ));
+        }
+        if completion.content.trim().is_empty() {
+            return Err(ConvComError::EmptyResponseError);
+        }
+        Ok(completion)
+    }
+
+    /// Add one streamed line to the completion, returning whether it was the last
+    fn read_chunk(
+        line: &str,
+        completion: &mut Completion,
+        on_text: &mut (dyn FnMut(&str) + Send),
+    ) -> Result<bool> {
+        if line.trim().is_empty() {
+            return Ok(false);
+        }
+
+        let chunk: OllamaChatChunk = serde_json::from_str(line)
+            .map_err(|e| ConvComError::ResponseParseError(e.to_string()))?;
+        if let Some(error) = chunk.error {
+            return Err(stream_error(&json!({ "error": error })));
+        }
+        if let Some(message) = chunk.message {
+            on_text(&message.content);
+            completion.content.push_str(&message.content);
+        }
+        if chunk.done {
+            completion.usage = Some(TokenUsage {
+                prompt_tokens: chunk.prompt_eval_count.unwrap_or(0),
+                completion_tokens: chunk.eval_count.unwrap_or(0),
+            });
+        }
+
+        Ok(chunk.done)
+    }
+
+    fn check_model(model: &Model) -> Result<()> {
+        if model.provider != AiProvider::Ollama {
             return Err(ConvComError::ConfigError(format!(
-                "Model {model} is not supported by Anthropic provider"
+                "Model {model} is not supported by Ollama provider"
             )));
         }
+        Ok(())
+    }
+}
 
-        let raw_output = self.make_anthropic_request(prompt, model).await?;
-        Ok(self.clean_output(&raw_output))
+#[async_trait]
+impl AiProviderTrait for OllamaProvider {
+    async fn generate_message(&self, prompt: String, model: &Model) -> Result<Completion> {
+        self.stream_message(prompt, model, &mut |_: &str| {}).await
+    }
+
+    async fn stream_message(
+        &self,
+        prompt: String,
+        model: &Model,
+        sink: &mut dyn TokenSink,
+    ) -> Result<Completion> {
+        Self::check_model(model)?;
+
+        // Ollama always streams, so both ways read the same chunks
+        let mut filter = ThinkFilter::new("think");
+        let completion = self
+            .make_ollama_request(prompt, model, &mut |text| sink.token(&filter.push(text)))
+            .await?;
+        sink.token(&filter.finish());
+
+        Ok(Completion {
+            content: self.clean_output(&completion.content),
+            ..completion
+        })
     }
 
     fn clean_output(&self, raw_output: &str) -> String {
-        // Remove thinking tags (similar to Python's regex)
-        let thinking_regex = Regex::new(r"(?s)<thinking>.*?</thinking>").unwrap();
-        let cleaned = thinking_regex.replace_all(raw_output, "");
+        strip_thinking("think", raw_output)
+    }
+}
 
-        // Remove any remaining thinking tags
-        let cleaned = cleaned.replace("<thinking>", "").replace("</thinking>", "");
+/// Turn an error reported inside a streamed response into an `ApiError`
+///
+/// The response started with a success status, so the error type decides
+/// whether another attempt may help: only overloads and rate limits do.
+fn stream_error(error: &Value) -> ConvComError {
+    let details = error.get("error").unwrap_or(error);
+    let kind = details
+        .get("type")
+        .or_else(|| details.get("code"))
+        .and_then(Value::as_str);
+    let status_code = match kind {
+        Some("overloaded_error") => 529,
+        Some("rate_limit_error" | "rate_limit_exceeded") => 429,
+        _ => 400,
+    };
 
-        // Trim leading whitespace
-        cleaned.trim_start().to_string()
+    ConvComError::ApiError {
+        status_code,
+        message: error.to_string(),
     }
 }
 
 /// Factory function to create the appropriate provider
-pub fn create_provider(provider: AiProvider, api_key: String) -> Result<Box<dyn AiProviderTrait>> {
+#[allow(dead_code)]
+pub fn create_provider(provider: AiProvider, api_key: ApiKey) -> Result<Box<dyn AiProviderTrait>> {
     match provider {
         AiProvider::Groq => {
             let groq_provider = GroqProvider::new(api_key)?;
@@ -230,46 +747,329 @@ pub fn create_provider(provider: AiProvider, api_key: String) -> Result<Box<dyn
             let anthropic_provider = AnthropicProvider::new(api_key)?;
             Ok(Box::new(anthropic_provider))
         }
+        AiProvider::OpenAi => {
+            let openai_provider = OpenAiCompatibleProvider::new(OpenAiSettings::openai(api_key))?;
+            Ok(Box::new(openai_provider))
+        }
+        // Ollama takes no key and runs on the default host
+        AiProvider::Ollama => {
+            let ollama_provider = OllamaProvider::new(OllamaSettings::default())?;
+            Ok(Box::new(ollama_provider))
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::models::ai::MAX_OUTPUT_TOKENS;
+    use crate::models::providers::ModelName;
+
+    fn local_model(provider: AiProvider, context_window: u32) -> Model {
+        Model {
+            provider,
+            id: "qwen2.5-coder".to_string(),
+            context_window,
+            max_output_tokens: MAX_OUTPUT_TOKENS,
+            output_limit: MAX_OUTPUT_TOKENS,
+            chat: true,
+        }
+    }
 
     #[test]
     fn test_groq_provider_creation() {
-        let provider = GroqProvider::new("test_key".to_string());
+        let provider = GroqProvider::new("test_key".into());
         assert!(provider.is_ok());
     }
 
     #[test]
     fn test_anthropic_provider_creation() {
-        let provider = AnthropicProvider::new("test_key".to_string());
+        let provider = AnthropicProvider::new("test_key".into());
         assert!(provider.is_ok());
     }
 
     #[test]
     fn test_provider_factory() {
-        let groq_provider = create_provider(AiProvider::Groq, "test_key".to_string());
+        let groq_provider = create_provider(AiProvider::Groq, "test_key".into());
         assert!(groq_provider.is_ok());
 
-        let anthropic_provider = create_provider(AiProvider::Anthropic, "test_key".to_string());
+        let anthropic_provider = create_provider(AiProvider::Anthropic, "test_key".into());
         assert!(anthropic_provider.is_ok());
     }
 
     #[test]
     fn test_clean_output_groq() {
-        let provider = GroqProvider::new("test_key".to_string()).unwrap();
+        let provider = GroqProvider::new("test_key".into()).unwrap();
 
         let input = "<think>Some thoughts</think>feat: add new feature";
         let expected = "feat: add new feature";
         assert_eq!(provider.clean_output(input), expected);
     }
 
+    #[tokio::test]
+    async fn test_openai_compatible_request() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("api-key", "local-key")
+            .match_header("authorization", mockito::Matcher::Missing)
+            .match_body(mockito::Matcher::PartialJson(json!({ "model": "qwen2.5-coder" })))
+            .with_body(
+                json!({
+                    "choices": [{ "message": { "content": "<think>hmm</think>\nfix: handle nulls" } }],
+                    "usage": { "prompt_tokens": 120, "completion_tokens": 8 }
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let provider = OpenAiCompatibleProvider::new(OpenAiSettings {
+            base_url: format!("{}/v1/", server.url()),
+            api_key: Some("local-key".into()),
+            auth_header: "api-key".to_string(),
+            model: "unused".to_string(),
+            timeout: Duration::from_secs(5),
+        })
+        .unwrap();
+        let completion = provider
+            .generate_message("diff".to_string(), &local_model(AiProvider::OpenAi, 8_192))
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(completion.content, "fix: handle nulls");
+        assert_eq!(
+            completion.usage,
+            Some(TokenUsage {
+                prompt_tokens: 120,
+                completion_tokens: 8,
+            })
+        );
+        assert!(
+            provider
+                .generate_message("diff".to_string(), &ModelName::Claude35Haiku.into())
+                .await
+                .is_err()
+        );
+    }
+
+    /// Collect the text a provider streams
+    fn collect(tokens: &mut String) -> impl FnMut(&str) + Send + '_ {
+        |text| tokens.push_str(text)
+    }
+
+    #[tokio::test]
+    async fn test_openai_compatible_stream() {
+        let mut server = mockito::Server::new_async().await;
+        let event = |data: Value| format!("data: {data}\n\n");
+        let delta =
+            |content: &str| event(json!({ "choices": [{ "delta": { "content": content } }] }));
+        let body = [
+            delta("<thi"),
+            delta("nk>plan</th"),
+            delta("ink>\nfix"),
+            delta(": handle nulls"),
+            event(json!({
+                "choices": [{ "delta": {}, "finish_reason": "stop" }],
+                "x_groq": { "usage": { "prompt_tokens": 120, "completion_tokens": 8 } }
+            })),
+            "data: [DONE]\n\n".to_string(),
+        ]
+        .concat();
+        let mock = server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "stream": true,
+                "stream_options": { "include_usage": true }
+            })))
+            .with_header("content-type", "text/event-stream")
+            .with_body(body)
+            .create_async()
+            .await;
+
+        let provider = OpenAiCompatibleProvider::new(OpenAiSettings {
+            base_url: format!("{}/v1", server.url()),
+            ..OpenAiSettings::openai("key".into())
+        })
+        .unwrap();
+        let mut tokens = String::new();
+        let completion = provider
+            .stream_message(
+                "diff".to_string(),
+                &local_model(AiProvider::OpenAi, 8_192),
+                &mut collect(&mut tokens),
+            )
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(tokens, "fix: handle nulls");
+        assert_eq!(completion.content, tokens);
+        assert_eq!(
+            completion.usage,
+            Some(TokenUsage {
+                prompt_tokens: 120,
+                completion_tokens: 8,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_stream() {
+        let mut server = mockito::Server::new_async().await;
+        let event = |data: Value| format!("event: {}\ndata: {data}\n\n", data["type"]);
+        let delta = |text: &str| {
+            event(json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": { "type": "text_delta", "text": text }
+            }))
+        };
+        let body = [
+            event(json!({ "type": "message_start", "message": { "usage": { "input_tokens": 310, "output_tokens": 1 } } })),
+            event(json!({ "type": "ping" })),
+            delta("<thinking>short</thinking>feat"),
+            delta("(auth): add login"),
+            event(json!({ "type": "message_delta", "usage": { "output_tokens": 12 } })),
+            event(json!({ "type": "message_stop" })),
+        ]
+        .concat();
+        server
+            .mock("POST", "/v1/messages")
+            .match_header("x-api-key", "key")
+            .match_body(mockito::Matcher::PartialJson(json!({ "stream": true })))
+            .with_body(body)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/overloaded")
+            .with_body(concat!(
+                "event: error\n",
+                r#"data: {"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+                "\n\n",
+            ))
+            .create_async()
+            .await;
+
+        let provider = AnthropicProvider::new("key".into())
+            .unwrap()
+            .with_url(format!("{}/v1/messages", server.url()));
+        let mut tokens = String::new();
+        let completion = provider
+            .stream_message(
+                "diff".to_string(),
+                &ModelName::Claude35Haiku.into(),
+                &mut collect(&mut tokens),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(tokens, "feat(auth): add login");
+        assert_eq!(completion.content, tokens);
+        assert_eq!(
+            completion.usage,
+            Some(TokenUsage {
+                prompt_tokens: 310,
+                completion_tokens: 12,
+            })
+        );
+
+        let provider = provider.with_url(format!("{}/overloaded", server.url()));
+        let error = provider
+            .stream_message(
+                "diff".to_string(),
+                &ModelName::Claude35Haiku.into(),
+                &mut |_: &str| {},
+            )
+            .await
+            .unwrap_err();
+        assert!(error.is_retryable());
+        assert!(error.to_string().contains("overloaded_error"));
+    }
+
+    #[tokio::test]
+    async fn test_ollama_streamed_chat() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/api/chat")
+            .match_body(mockito::Matcher::PartialJson(json!({
+                "model": "qwen2.5-coder",
+                "stream": true,
+                "keep_alive": -1,
+                "options": { "num_ctx": 16384 }
+            })))
+            .with_body(concat!(
+                r#"{"message":{"role":"assistant","content":"<think>ok</think>feat"},"done":false}"#,
+                "\n",
+                r#"{"message":{"role":"assistant","content":": add login"},"done":false}"#,
+                "\n",
+                r#"{"message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":310,"eval_count":12}"#,
+                "\n",
+            ))
+            .create_async()
+            .await;
+
+        let provider = OllamaProvider::new(OllamaSettings {
+            host: server.url(),
+            keep_alive: Some("-1".to_string()),
+            ..OllamaSettings::default()
+        })
+        .unwrap();
+        let completion = provider
+            .generate_message("diff".to_string(), &local_model(AiProvider::Ollama, 16_384))
+            .await
+            .unwrap();
+
+        mock.assert_async().await;
+        assert_eq!(completion.content, "feat: add login");
+        assert_eq!(
+            completion.usage,
+            Some(TokenUsage {
+                prompt_tokens: 310,
+                completion_tokens: 12,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ollama_errors_and_models() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/api/chat")
+            .with_body(concat!(
+                r#"{"message":{"role":"assistant","content":"feat"},"done":false}"#,
+                "\n",
+                r#"{"error":"model ran out of memory"}"#,
+            ))
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/api/tags")
+            .with_body(r#"{"models":[{"name":"qwen2.5-coder:latest"},{"name":"llama3.2:3b"}]}"#)
+            .create_async()
+            .await;
+
+        let provider = OllamaProvider::new(OllamaSettings {
+            host: format!("{}/", server.url()),
+            ..OllamaSettings::default()
+        })
+        .unwrap();
+
+        let error = provider
+            .generate_message("diff".to_string(), &local_model(AiProvider::Ollama, 8_192))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("model ran out of memory"));
+        assert_eq!(
+            provider.list_models().await.unwrap(),
+            ["qwen2.5-coder:latest", "llama3.2:3b"]
+        );
+    }
+
     #[test]
     fn test_clean_output_anthropic() {
-        let provider = AnthropicProvider::new("test_key".to_string()).unwrap();
+        let provider = AnthropicProvider::new("test_key".into()).unwrap();
 
         let input = "<thinking>Some thoughts</thinking>feat: add new feature";
         let expected = "feat: add new feature";
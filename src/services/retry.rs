@@ -0,0 +1,372 @@
+use crate::error::{ConvComError, Result};
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, Response};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How requests to a provider are retried after transient failures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for each retry after it
+    pub initial_backoff: Duration,
+    /// Longest wait between attempts; a server asking for more ends the retries
+    pub max_backoff: Duration,
+    /// Time after which no new attempt is started
+    pub deadline: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(20),
+            deadline: Duration::from_secs(60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that sends every request once
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Send a request, retrying rate limits, overloads, timeouts and server errors
+    ///
+    /// Waits as long as a `Retry-After` or Groq `x-ratelimit-reset-*` header
+    /// asks, and otherwise backs off exponentially with jitter. Responses
+    /// with an error status become `ApiError`. Requests that cannot be
+    /// built, such as ones with an invalid URL or header, fail at once.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let started = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let Some(attempt_request) = request.try_clone() else {
+                // Invalid requests cannot be cloned either; building one says why
+                return Err(match request.build() {
+                    Err(e) => e.into(),
+                    Ok(_) => ConvComError::HttpClientError(
+                        "the request body cannot be resent".to_string(),
+                    ),
+                });
+            };
+
+            let (error, requested) = match attempt_request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let requested = requested_wait(response.headers());
+                    let status = response.status();
+                    let error_text = response
+                        .text()
+                        .await
+                        .unwrap_or_else(|_| "Unknown error".to_string());
+                    let error = ConvComError::ApiError {
+                        status_code: status.as_u16(),
+                        message: error_text,
+                    };
+                    (error, requested)
+                }
+                Err(e) => (ConvComError::from(e), None),
+            };
+
+            if !error.is_retryable() || attempt >= self.max_attempts {
+                return Err(error);
+            }
+            let wait = requested.unwrap_or_else(|| self.backoff(attempt));
+            if wait > self.max_backoff || started.elapsed() + wait > self.deadline {
+                return Err(error);
+            }
+
+            tokio::time::sleep(wait).await;
+            attempt += 1;
+        }
+    }
+
+    /// Exponential backoff before the given retry, half of it random
+    fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(1 << (retry - 1).min(16))
+            .min(self.max_backoff);
+        let half = exponential / 2;
+        half + half.mul_f64(fastrand::f64())
+    }
+}
+
+/// The wait a failed response asks for before the next request
+///
+/// `Retry-After` may be seconds or an HTTP date. Groq marks the exhausted
+/// limit with a zero `x-ratelimit-remaining-*` header and gives the time
+/// until it resets, like `2m59.56s`, in `x-ratelimit-reset-*`.
+fn requested_wait(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| headers.get(name)?.to_str().ok().map(str::trim);
+
+    if let Some(retry_after) = header("retry-after") {
+        if let Ok(seconds) = retry_after.parse::<f64>() {
+            return Duration::try_from_secs_f64(seconds).ok();
+        }
+        if let Ok(date) = httpdate::parse_http_date(retry_after) {
+            return Some(date.duration_since(SystemTime::now()).unwrap_or_default());
+        }
+    }
+
+    ["requests", "tokens"]
+        .into_iter()
+        .filter(|limit| header(&format!("x-ratelimit-remaining-{limit}")) == Some("0"))
+        .filter_map(|limit| parse_duration(header(&format!("x-ratelimit-reset-{limit}"))?))
+        .max()
+}
+
+/// Parse a duration like `1h2m`, `7.66s` or `120ms`
+fn parse_duration(value: &str) -> Option<Duration> {
+    let mut rest = value;
+    let mut seconds = 0.0;
+    if rest.is_empty() {
+        return None;
+    }
+
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..number_end].parse().ok()?;
+        rest = &rest[number_end..];
+
+        let unit_end = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let unit = match &rest[..unit_end] {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 1e-3,
+            "us" | "µs" => 1e-6,
+            "ns" => 1e-9,
+            _ => return None,
+        };
+        seconds += number * unit;
+        rest = &rest[unit_end..];
+    }
+
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(2),
+            deadline: Duration::from_secs(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_errors() {
+        let mut server = mockito::Server::new_async().await;
+        let overloaded = server
+            .mock("POST", "/v1/messages")
+            .with_status(529)
+            .with_body(
+                r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#,
+            )
+            .expect(1)
+            .create_async()
+            .await;
+        let unavailable = server
+            .mock("POST", "/v1/messages")
+            .with_status(503)
+            .expect(1)
+            .create_async()
+            .await;
+        let success = server
+            .mock("POST", "/v1/messages")
+            .with_body("{}")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let request = Client::new()
+            .post(format!("{}/v1/messages", server.url()))
+            .body("{}");
+        let response = fast_policy().send(request).await.unwrap();
+
+        assert!(response.status().is_success());
+        overloaded.assert_async().await;
+        unavailable.assert_async().await;
+        success.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_honours_retry_after() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/")
+            .with_status(429)
+            .with_header("retry-after", "1")
+            .expect(1)
+            .create_async()
+            .await;
+        server.mock("GET", "/").with_body("ok").create_async().await;
+
+        let started = Instant::now();
+        let response = fast_policy()
+            .send(Client::new().get(server.url()))
+            .await
+            .unwrap();
+
+        assert_eq!(response.text().await.unwrap(), "ok");
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_gives_up() {
+        let mut server = mockito::Server::new_async().await;
+        let unauthorized = server
+            .mock("GET", "/auth")
+            .with_status(401)
+            .with_body("invalid x-api-key")
+            .expect(1)
+            .create_async()
+            .await;
+        let failing = server
+            .mock("GET", "/failing")
+            .with_status(502)
+            .expect(3)
+            .create_async()
+            .await;
+        let limited = server
+            .mock("GET", "/limited")
+            .with_status(429)
+            .with_header("retry-after", "3600")
+            .expect(1)
+            .create_async()
+            .await;
+
+        let client = Client::new();
+        let policy = fast_policy();
+        let error = policy
+            .send(client.get(format!("{}/auth", server.url())))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("401"));
+        assert!(
+            policy
+                .send(client.get(format!("{}/failing", server.url())))
+                .await
+                .is_err()
+        );
+        // Waiting an hour is longer than the policy allows
+        assert!(
+            policy
+                .send(client.get(format!("{}/limited", server.url())))
+                .await
+                .is_err()
+        );
+
+        unauthorized.assert_async().await;
+        failing.assert_async().await;
+        limited.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_retries_only_network_errors() {
+        let mut server = mockito::Server::new_async().await;
+        let never = server.mock("GET", "/").expect(0).create_async().await;
+        let slow_policy = RetryPolicy {
+            initial_backoff: Duration::from_secs(5),
+            ..fast_policy()
+        };
+
+        let started = Instant::now();
+        let invalid_header = Client::new()
+            .get(server.url())
+            .header("x-api-key", "line\nbreak");
+        let error = slow_policy.send(invalid_header).await.unwrap_err();
+        assert!(matches!(error, ConvComError::ApiRequestError(_)), "{error}");
+        let error = slow_policy
+            .send(Client::new().get("ftp://example.com/"))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ConvComError::ApiRequestError(_)), "{error}");
+        assert!(started.elapsed() < Duration::from_secs(1));
+        never.assert_async().await;
+
+        // Nothing listens on a port that was just released
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let error = fast_policy()
+            .send(Client::new().get(format!("http://127.0.0.1:{port}/")))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ConvComError::NetworkError(_)), "{error}");
+    }
+
+    #[test]
+    fn test_requested_wait() {
+        let headers = |pairs: &[(&'static str, &str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, value.parse().unwrap());
+            }
+            headers
+        };
+
+        assert_eq!(
+            requested_wait(&headers(&[("retry-after", "2")])),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            requested_wait(&headers(&[(
+                "retry-after",
+                "Wed, 21 Oct 2015 07:28:00 GMT"
+            )])),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(
+            requested_wait(&headers(&[
+                ("x-ratelimit-remaining-requests", "14"),
+                ("x-ratelimit-reset-requests", "2m59.56s"),
+                ("x-ratelimit-remaining-tokens", "0"),
+                ("x-ratelimit-reset-tokens", "7.66s"),
+            ])),
+            Some(Duration::from_millis(7660))
+        );
+        assert_eq!(requested_wait(&headers(&[])), None);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("1h2m3s"), Some(Duration::from_secs(3723)));
+        assert_eq!(parse_duration("120ms"), Some(Duration::from_millis(120)));
+        assert_eq!(
+            parse_duration("2m59.5s"),
+            Some(Duration::from_millis(179_500))
+        );
+        assert_eq!(parse_duration("soon"), None);
+        assert_eq!(parse_duration(""), None);
+    }
+
+    #[test]
+    fn test_backoff_grows_with_jitter() {
+        let policy = RetryPolicy::default();
+        for retry in 1..=3 {
+            let base = policy.initial_backoff * (1 << (retry - 1));
+            let backoff = policy.backoff(retry);
+            assert!(backoff >= base / 2 && backoff <= base, "{backoff:?}");
+        }
+        assert!(policy.backoff(30) <= policy.max_backoff);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::error::{ConvComError, Result};
+use git2::{Config, Signature};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// Sign a commit buffer with the program git is configured to use
+///
+/// Follows `gpg.format` (`openpgp`, `x509` or `ssh`), the matching
+/// `gpg.*.program` and `user.signingkey`, like `git commit -S` does. The
+/// returned armored signature is ready for the `gpgsig` header.
+pub fn sign_buffer(config: &Config, buffer: &str, committer: &Signature<'_>) -> Result<String> {
+    let format = config
+        .get_string("gpg.format")
+        .unwrap_or_else(|_| "openpgp".to_string());
+    let setting = |key: &str| config.get_string(key).ok();
+    let signing_key = setting("user.signingkey");
+    // Literal SSH keys are written to a file for the duration of the signing
+    let mut literal_key_file: Option<PathBuf> = None;
+
+    let (program, args) = match format.as_str() {
+        "openpgp" | "x509" => {
+            let program = if format == "openpgp" {
+                setting("gpg.openpgp.program")
+                    .or_else(|| setting("gpg.program"))
+                    .unwrap_or_else(|| "gpg".to_string())
+            } else {
+                setting("gpg.x509.program").unwrap_or_else(|| "gpgsm".to_string())
+            };
+            // Without a configured key, gpg picks one matching the committer
+            let key = signing_key.unwrap_or_else(|| {
+                format!(
+                    "{} <{}>",
+                    committer.name().unwrap_or_default(),
+                    committer.email().unwrap_or_default()
+                )
+            });
+            (
+                program,
+                vec!["--status-fd=2".to_string(), "-bsau".to_string(), key],
+            )
+        }
+        "ssh" => {
+            let mut key = signing_key.ok_or_else(|| {
+                ConvComError::ConfigError(
+                    "commit.gpgsign with gpg.format=ssh requires user.signingkey".to_string(),
+                )
+            })?;
+            let literal = key
+                .strip_prefix("key::")
+                .map(str::to_string)
+                .or_else(|| key.starts_with("ssh-").then(|| key.clone()));
+            if let Some(literal) = literal {
+                let path = std::env::temp_dir()
+                    .join(format!("convcom-signing-key-{}.pub", std::process::id()));
+                std::fs::write(&path, format!("{literal}\n"))?;
+                key = path.to_string_lossy().into_owned();
+                literal_key_file = Some(path);
+            }
+
+            let program = setting("gpg.ssh.program").unwrap_or_else(|| "ssh-keygen".to_string());
+            let args = ["-Y", "sign", "-n", "git", "-f", key.as_str()];
+            (program, args.iter().map(|arg| arg.to_string()).collect())
+        }
+        other => {
+            return Err(ConvComError::ConfigError(format!(
+                "Unsupported gpg.format '{other}'"
+            )));
+        }
+    };
+
+    let signature = run_signer(&program, &args, buffer);
+    if let Some(path) = literal_key_file {
+        let _ = std::fs::remove_file(path);
+    }
+    signature
+}
+
+/// Feed the buffer to a signing program and collect the signature from its output
+fn run_signer(program: &str, args: &[String], buffer: &str) -> Result<String> {
+    let signing_error = |detail: String| {
+        ConvComError::GitError(format!(
+            "Signing the commit with {program} failed: {detail}"
+        ))
+    };
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| signing_error(e.to_string()))?;
+
+    // Write from a separate thread so a chatty signer cannot block on a full pipe
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = buffer.to_string();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| signing_error(e.to_string()))?;
+    let written = writer
+        .join()
+        .map_err(|_| signing_error("could not write the commit".to_string()))?;
+
+    // A signer that gives up early closes its input, so its own error comes first
+    let signature = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() || signature.trim().is_empty() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(signing_error(stderr.trim().to_string()));
+    }
+    written.map_err(|e| signing_error(e.to_string()))?;
+
+    Ok(signature)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn config_with(dir: &std::path::Path, entries: &[(&str, &str)]) -> Config {
+        let mut config = Config::open(&dir.join("config")).unwrap();
+        for (key, value) in entries {
+            config.set_str(key, value).unwrap();
+        }
+        config
+    }
+
+    fn fake_signer(dir: &std::path::Path, script: &str) -> String {
+        let path = dir.join("signer");
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_openpgp_signing() {
+        let dir = tempfile::tempdir().unwrap();
+        // Echo the key argument so the test can check which key was requested
+        let signer = fake_signer(
+            dir.path(),
+            "#!/bin/sh\ncat > /dev/null\necho \"-----BEGIN PGP SIGNATURE-----\"\necho \"$3\"\necho \"-----END PGP SIGNATURE-----\"\n",
+        );
+        let config = config_with(dir.path(), &[("gpg.program", &signer)]);
+        let committer = Signature::now("Test User", "test@example.com").unwrap();
+
+        let signature = sign_buffer(&config, "tree 123\n", &committer).unwrap();
+        assert!(signature.starts_with("-----BEGIN PGP SIGNATURE-----"));
+        assert!(signature.contains("Test User <test@example.com>"));
+    }
+
+    #[test]
+    fn test_failed_signing() {
+        let dir = tempfile::tempdir().unwrap();
+        let signer = fake_signer(dir.path(), "#!/bin/sh\necho 'no secret key' >&2\nexit 2\n");
+        let config = config_with(dir.path(), &[("gpg.program", &signer)]);
+        let committer = Signature::now("Test User", "test@example.com").unwrap();
+
+        match sign_buffer(&config, "tree 123\n", &committer) {
+            Err(ConvComError::GitError(message)) => {
+                assert!(message.contains("no secret key"), "{message}")
+            }
+            other => panic!("Unexpected result: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_ssh_signing_requires_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config_with(dir.path(), &[("gpg.format", "ssh")]);
+        let committer = Signature::now("Test User", "test@example.com").unwrap();
+
+        assert!(matches!(
+            sign_buffer(&config, "tree 123\n", &committer),
+            Err(ConvComError::ConfigError(_))
+        ));
+    }
+}
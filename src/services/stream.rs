@@ -0,0 +1,376 @@
+use crate::error::{ConvComError, Result};
+use reqwest::Response;
+use std::time::Duration;
+
+/// Receives a message while it is being generated
+pub trait TokenSink: Send {
+    /// The next piece of cleaned message text
+    fn token(&mut self, text: &str);
+
+    /// Forget the text so far, as another attempt starts over
+    fn restart(&mut self) {}
+}
+
+impl<F: FnMut(&str) + Send> TokenSink for F {
+    fn token(&mut self, text: &str) {
+        self(text)
+    }
+}
+
+/// Removes thinking sections from streamed text
+///
+/// A `<tag>...</tag>` section is dropped once it is closed; an unclosed one
+/// is held back until the end, when only its tags are removed. Stray tags
+/// and leading whitespace are removed too. Text that may be the start of a
+/// tag is held back until the next piece shows whether it is one.
+pub struct ThinkFilter {
+    open: String,
+    close: String,
+    /// Raw text not yet searched for complete sections
+    pending: String,
+    /// Whether `pending` starts with an opening tag
+    inside: bool,
+    stray_open: TagRemover,
+    stray_close: TagRemover,
+    /// Whether any text has been let through, so whitespace is kept again
+    started: bool,
+}
+
+impl ThinkFilter {
+    /// A filter for `<tag>` sections, such as `think` or `thinking`
+    pub fn new(tag: &str) -> Self {
+        let open = format!("<{tag}>");
+        let close = format!("</{tag}>");
+        Self {
+            stray_open: TagRemover::new(&open),
+            stray_close: TagRemover::new(&close),
+            open,
+            close,
+            pending: String::new(),
+            inside: false,
+            started: false,
+        }
+    }
+
+    /// Add raw text and return the cleaned text that is certain by now
+    pub fn push(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+
+        let mut kept = String::new();
+        loop {
+            if self.inside {
+                match self.pending[self.open.len()..].find(&self.close) {
+                    Some(end) => {
+                        self.pending
+                            .drain(..self.open.len() + end + self.close.len());
+                        self.inside = false;
+                    }
+                    None => break,
+                }
+            } else {
+                match self.pending.find(&self.open) {
+                    Some(start) => {
+                        kept.extend(self.pending.drain(..start));
+                        self.inside = true;
+                    }
+                    None => {
+                        let held = held_back(&self.pending, &self.open);
+                        kept.extend(self.pending.drain(..self.pending.len() - held));
+                        break;
+                    }
+                }
+            }
+        }
+
+        let kept = self.stray_open.push(&kept);
+        let kept = self.stray_close.push(&kept);
+        self.trim_start(kept)
+    }
+
+    /// Return the cleaned text that was held back
+    pub fn finish(&mut self) -> String {
+        // An unclosed section is kept, only without its tags
+        let rest = std::mem::take(&mut self.pending);
+        self.inside = false;
+
+        let rest = self.stray_open.push(&rest) + &self.stray_open.finish();
+        let rest = self.stray_close.push(&rest) + &self.stray_close.finish();
+        self.trim_start(rest)
+    }
+
+    /// Drop whitespace before the first visible text
+    fn trim_start(&mut self, text: String) -> String {
+        if self.started {
+            return text;
+        }
+        let trimmed = text.trim_start();
+        self.started = !trimmed.is_empty();
+        trimmed.to_string()
+    }
+}
+
+/// Remove `<tag>...</tag>` sections from a complete answer, as `ThinkFilter` does
+pub fn strip_thinking(tag: &str, text: &str) -> String {
+    let mut filter = ThinkFilter::new(tag);
+    filter.push(text) + &filter.finish()
+}
+
+/// Removes every occurrence of a tag from streamed text
+struct TagRemover {
+    tag: String,
+    /// Text ending in what may be the start of the tag
+    pending: String,
+}
+
+impl TagRemover {
+    fn new(tag: &str) -> Self {
+        Self {
+            tag: tag.to_string(),
+            pending: String::new(),
+        }
+    }
+
+    fn push(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+        // A tag cannot overlap itself, so the held end is never part of a match
+        let held = held_back(&self.pending, &self.tag);
+        let rest = self.pending.split_off(self.pending.len() - held);
+        std::mem::replace(&mut self.pending, rest).replace(&self.tag, "")
+    }
+
+    fn finish(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// Length of the longest end of `text` that could begin `tag`
+fn held_back(text: &str, tag: &str) -> usize {
+    (1..tag.len().min(text.len() + 1))
+        .rev()
+        .find(|&len| {
+            let start = text.len() - len;
+            text.is_char_boundary(start) && tag.starts_with(&text[start..])
+        })
+        .unwrap_or(0)
+}
+
+/// Read a streamed body line by line, without line endings
+///
+/// Network reads may split lines, so partial lines wait for the next read.
+/// The stream may take as long as it needs, as long as no read waits more
+/// than `idle_timeout`.
+pub async fn read_lines(
+    response: &mut Response,
+    idle_timeout: Duration,
+    mut on_line: impl FnMut(&str) -> Result<()>,
+) -> Result<()> {
+    let mut pending: Vec<u8> = Vec::new();
+    let mut handle = |line: &[u8]| {
+        let line = std::str::from_utf8(line)
+            .map_err(|e| ConvComError::ResponseParseError(e.to_string()))?;
+        on_line(line.trim_end_matches(['\r', '\n']))
+    };
+
+    loop {
+        let chunk = tokio::time::timeout(idle_timeout, response.chunk())
+            .await
+            .map_err(|_| {
+                ConvComError::NetworkError(format!(
+                    "no data received for {} seconds",
+                    idle_timeout.as_secs()
+                ))
+            })?;
+        let Some(bytes) = chunk.map_err(ConvComError::from)? else {
+            break;
+        };
+        pending.extend_from_slice(&bytes);
+        while let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            handle(&line)?;
+        }
+    }
+    if !pending.is_empty() {
+        handle(&pending)?;
+    }
+
+    Ok(())
+}
+
+/// Read the data of each server-sent event in a streamed body
+pub async fn read_events(
+    response: &mut Response,
+    idle_timeout: Duration,
+    mut on_event: impl FnMut(&str) -> Result<()>,
+) -> Result<()> {
+    let mut data = String::new();
+    read_lines(response, idle_timeout, |line| {
+        if line.is_empty() {
+            if !data.is_empty() {
+                on_event(&std::mem::take(&mut data))?;
+            }
+        } else if let Some(value) = line.strip_prefix("data:") {
+            if !data.is_empty() {
+                data.push('\n');
+            }
+            data.push_str(value.strip_prefix(' ').unwrap_or(value));
+        }
+        Ok(())
+    })
+    .await?;
+
+    if !data.is_empty() {
+        on_event(&data)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed the text split at `split` and at every later tenth byte
+    fn filtered(tag: &str, text: &str, split: usize) -> String {
+        let mut filter = ThinkFilter::new(tag);
+        let mut output = String::new();
+        let mut start = 0;
+        for end in (split..text.len()).step_by(10).chain([text.len()]) {
+            if text.is_char_boundary(end) && end >= start {
+                output += &filter.push(&text[start..end]);
+                start = end;
+            }
+        }
+        output + &filter.finish()
+    }
+
+    #[test]
+    fn test_think_filter_at_any_split() {
+        let cases = [
+            ("feat: add streaming", "feat: add streaming"),
+            (
+                "<think>Some thoughts</think>feat: add new feature",
+                "feat: add new feature",
+            ),
+            (
+                "  \n<think>a</think>\n\n<think>b</think>  fix(api): handle errors\n\nbody </think>",
+                "fix(api): handle errors\n\nbody ",
+            ),
+            (
+                "<think>unclosed thoughts\nfeat: keep them",
+                "unclosed thoughts\nfeat: keep them",
+            ),
+            (
+                "docs: explain <thi tags <think> and </think> in text",
+                "docs: explain <thi tags  in text",
+            ),
+            (
+                "<<think>x</think>think>y</think>feat: nested",
+                "yfeat: nested",
+            ),
+            (
+                "<thi<think>x</think>nk>feat: joined tag",
+                "feat: joined tag",
+            ),
+            (
+                "chore: café ☕ <think>ça</think>done",
+                "chore: café ☕ done",
+            ),
+        ];
+
+        for (input, expected) in cases {
+            let thinking = input.replace("think>", "thinking>");
+            for split in 0..=input.len() {
+                assert_eq!(
+                    filtered("think", input, split),
+                    expected,
+                    "{input:?} split at {split}"
+                );
+                assert_eq!(
+                    filtered("thinking", &thinking, split),
+                    strip_thinking("thinking", &thinking),
+                    "{thinking:?} split at {split}"
+                );
+            }
+            assert_eq!(strip_thinking("think", input), expected);
+        }
+    }
+
+    #[test]
+    fn test_think_filter_streams_visible_text() {
+        let mut filter = ThinkFilter::new("think");
+        assert_eq!(filter.push("<think>plan"), "");
+        assert_eq!(filter.push("ning</think>\nfeat: "), "feat: ");
+        assert_eq!(filter.push("add <"), "add ");
+        assert_eq!(filter.push("b>"), "<b>");
+        assert_eq!(filter.finish(), "");
+    }
+
+    #[tokio::test]
+    async fn test_read_events() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/")
+            .with_body(
+                "event: a\r\ndata: one\r\n\r\n: comment\ndata: two\ndata: lines\n\ndata:[DONE]",
+            )
+            .create_async()
+            .await;
+
+        let mut response = reqwest::get(server.url()).await.unwrap();
+        let mut events = Vec::new();
+        read_events(&mut response, Duration::from_secs(5), |data| {
+            events.push(data.to_string());
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(events, ["one", "two\nlines", "[DONE]"]);
+    }
+
+    #[tokio::test]
+    async fn test_read_lines_waits_while_data_arrives() {
+        let mut server = mockito::Server::new_async().await;
+        let gaps = |gap: u64| {
+            move |body: &mut dyn std::io::Write| {
+                for line in ["one\n", "two\n", "three\n"] {
+                    std::thread::sleep(Duration::from_millis(gap));
+                    body.write_all(line.as_bytes())?;
+                    body.flush()?;
+                }
+                Ok(())
+            }
+        };
+        server
+            .mock("GET", "/steady")
+            .with_chunked_body(gaps(150))
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/stalled")
+            .with_chunked_body(gaps(600))
+            .create_async()
+            .await;
+        let idle_timeout = Duration::from_millis(400);
+
+        // Longer in total than the timeout, but never idle for that long
+        let mut response = reqwest::get(format!("{}/steady", server.url()))
+            .await
+            .unwrap();
+        let mut lines = Vec::new();
+        read_lines(&mut response, idle_timeout, |line| {
+            lines.push(line.to_string());
+            Ok(())
+        })
+        .await
+        .unwrap();
+        assert_eq!(lines, ["one", "two", "three"]);
+
+        let mut response = reqwest::get(format!("{}/stalled", server.url()))
+            .await
+            .unwrap();
+        let error = read_lines(&mut response, idle_timeout, |_| Ok(()))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ConvComError::NetworkError(_)), "{error}");
+    }
+}
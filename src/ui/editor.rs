@@ -0,0 +1,74 @@
+use crate::error::{ConvComError, Result};
+use std::path::Path;
+use std::process::Command;
+
+/// Editor used when nothing is configured, matching git's default
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Pick the editor the way `git commit` does
+///
+/// `GIT_EDITOR` wins over `core.editor`, then `VISUAL` and `EDITOR`.
+pub fn resolve_editor(config: Option<&git2::Config>) -> String {
+    let env = |name: &str| std::env::var(name).ok();
+
+    env("GIT_EDITOR")
+        .or_else(|| config.and_then(|config| config.get_string("core.editor").ok()))
+        .or_else(|| env("VISUAL"))
+        .or_else(|| env("EDITOR"))
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string())
+}
+
+/// Open a file in the editor and wait for it to close
+///
+/// The editor string may contain arguments, e.g. `code --wait`, and is run
+/// through the shell like git does.
+pub fn edit_file(editor: &str, path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    let mut command = {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(format!("{editor} \"$@\""))
+            .arg(editor);
+        command
+    };
+
+    #[cfg(not(unix))]
+    let mut command = {
+        let mut parts = editor.split_whitespace();
+        let mut command = Command::new(parts.next().unwrap_or(DEFAULT_EDITOR));
+        command.args(parts);
+        command
+    };
+
+    let status = command
+        .arg(path)
+        .status()
+        .map_err(|e| ConvComError::IoError(format!("Cannot start editor '{editor}': {e}")))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(ConvComError::IoError(format!(
+            "Editor '{editor}' exited with {status}"
+        )))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edit_file_with_arguments() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("message");
+        std::fs::write(&path, "feat: add thing\n").unwrap();
+
+        edit_file("sed -i s/feat/fix/", &path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fix: add thing\n");
+
+        assert!(edit_file("false", &path).is_err());
+    }
+}
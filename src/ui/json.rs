@@ -0,0 +1,208 @@
+use crate::error::ConvComError;
+use crate::models::ai::TokenUsage;
+use crate::models::commit::ConventionalCommit;
+use crate::models::diff::FileChange;
+use crate::models::providers::Model;
+use crate::prompt::diff_renderer::Truncation;
+use serde_json::{Value, json};
+use std::time::Duration;
+
+/// Code for errors that do not come from convcom itself
+const INTERNAL_ERROR: &str = "internal_error";
+
+/// Everything reported about a generated message in JSON output
+pub struct MessageReport<'a> {
+    pub commit: &'a ConventionalCommit,
+    /// The model that wrote the message
+    pub model: &'a Model,
+    /// Models that could not answer, with their errors
+    pub fallbacks: &'a [(Model, ConvComError)],
+    /// Tokens summed over all attempts, if the provider reported them
+    pub usage: Option<TokenUsage>,
+    pub latency: Duration,
+    /// Number of requests, including corrections
+    pub attempts: u32,
+    pub files: &'a [FileChange],
+    pub truncations: &'a [Truncation],
+    /// Id of the commit created with the message, if any
+    pub created_commit: Option<String>,
+}
+
+impl MessageReport<'_> {
+    /// Build the JSON object with the parsed message and its metadata
+    pub fn to_json(&self) -> Value {
+        let commit = self.commit;
+        let footers: Vec<Value> = commit
+            .footers
+            .iter()
+            .map(|footer| {
+                json!({
+                    "token": footer.token,
+                    "separator": footer.separator.as_str(),
+                    "value": footer.value,
+                })
+            })
+            .collect();
+        let fallbacks: Vec<Value> = self
+            .fallbacks
+            .iter()
+            .map(|(model, error)| {
+                json!({
+                    "model": model.id,
+                    "provider": model.provider.to_string(),
+                    "error": { "code": error.code(), "message": error.to_string() },
+                })
+            })
+            .collect();
+        let (included, excluded): (Vec<&FileChange>, Vec<&FileChange>) =
+            self.files.iter().partition(|file| file.excluded.is_none());
+
+        json!({
+            "message": {
+                "type": commit.commit_type,
+                "scope": commit.scope,
+                "breaking": commit.is_breaking(),
+                "description": commit.description,
+                "body": commit.body,
+                "footers": footers,
+                "text": commit.to_string(),
+            },
+            "metadata": {
+                "model": self.model.id,
+                "provider": self.model.provider.to_string(),
+                "fallbacks": fallbacks,
+                "prompt_tokens": self.usage.map(|usage| usage.prompt_tokens),
+                "completion_tokens": self.usage.map(|usage| usage.completion_tokens),
+                "latency_ms": self.latency.as_millis() as u64,
+                "attempts": self.attempts,
+                "files": {
+                    "included": included.iter().map(|file| &file.path).collect::<Vec<_>>(),
+                    "excluded": excluded
+                        .iter()
+                        .map(|file| json!({
+                            "path": file.path,
+                            "reason": file.excluded.map(|reason| reason.to_string()),
+                        }))
+                        .collect::<Vec<_>>(),
+                },
+                "truncated": self.truncations,
+            },
+            "commit": self.created_commit,
+        })
+    }
+}
+
+/// Build the JSON object for an error, with a stable code per `ConvComError` variant
+pub fn error_report(error: &anyhow::Error) -> Value {
+    let Some(convcom_error) = error.downcast_ref::<ConvComError>() else {
+        return json!({
+            "error": { "code": INTERNAL_ERROR, "message": format!("{error:#}") }
+        });
+    };
+
+    let mut report = json!({
+        "code": convcom_error.code(),
+        "message": convcom_error.to_string(),
+    });
+    if let ConvComError::ApiError { status_code, .. } = convcom_error {
+        report["status_code"] = json!(status_code);
+    }
+
+    json!({ "error": report })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::diff::{ChangeKind, ExclusionReason};
+    use crate::models::providers::ModelName;
+
+    #[test]
+    fn test_message_report() {
+        let commit =
+            ConventionalCommit::parse("feat(api): add login\n\n* Add a route.\n\nRefs #7").unwrap();
+        let mut lockfile = FileChange::new(ChangeKind::Modified, "Cargo.lock");
+        lockfile.excluded = Some(ExclusionReason::Lockfile);
+        let files = [FileChange::new(ChangeKind::Added, "src/api.rs"), lockfile];
+        let truncations = [Truncation {
+            path: "src/api.rs".to_string(),
+            omitted_lines: 12,
+            omitted_hunks: 2,
+        }];
+
+        let report = MessageReport {
+            commit: &commit,
+            model: &ModelName::Llama3370BVersatile.into(),
+            fallbacks: &[(
+                ModelName::Claude35Haiku.into(),
+                ConvComError::ApiError {
+                    status_code: 529,
+                    message: "overloaded".to_string(),
+                },
+            )],
+            usage: Some(TokenUsage {
+                prompt_tokens: 900,
+                completion_tokens: 40,
+            }),
+            latency: Duration::from_millis(1250),
+            attempts: 1,
+            files: &files,
+            truncations: &truncations,
+            created_commit: None,
+        }
+        .to_json();
+
+        assert_eq!(report["message"]["type"], "feat");
+        assert_eq!(report["message"]["scope"], "api");
+        assert_eq!(report["message"]["breaking"], false);
+        assert_eq!(report["message"]["body"], json!(["* Add a route."]));
+        assert_eq!(
+            report["message"]["footers"],
+            json!([{ "token": "Refs", "separator": " #", "value": "7" }])
+        );
+        assert_eq!(report["metadata"]["model"], "llama-3.3-70b-versatile");
+        assert_eq!(report["metadata"]["provider"], "groq");
+        assert_eq!(
+            report["metadata"]["fallbacks"][0]["model"],
+            "claude-3-5-haiku-20241022"
+        );
+        assert_eq!(
+            report["metadata"]["fallbacks"][0]["error"]["code"],
+            "api_error"
+        );
+        assert_eq!(report["metadata"]["prompt_tokens"], 900);
+        assert_eq!(report["metadata"]["latency_ms"], 1250);
+        assert_eq!(
+            report["metadata"]["files"]["included"],
+            json!(["src/api.rs"])
+        );
+        assert_eq!(
+            report["metadata"]["files"]["excluded"],
+            json!([{ "path": "Cargo.lock", "reason": "lockfile" }])
+        );
+        assert_eq!(report["metadata"]["truncated"][0]["omitted_lines"], 12);
+        assert!(report["commit"].is_null());
+    }
+
+    #[test]
+    fn test_error_report() {
+        let report = error_report(&ConvComError::NoStagedFilesError.into());
+        assert_eq!(
+            report,
+            json!({ "error": { "code": "no_staged_files", "message": "No staged files found" } })
+        );
+
+        let report = error_report(
+            &ConvComError::ApiError {
+                status_code: 429,
+                message: "slow down".to_string(),
+            }
+            .into(),
+        );
+        assert_eq!(report["error"]["code"], "api_error");
+        assert_eq!(report["error"]["status_code"], 429);
+
+        let report = error_report(&anyhow::anyhow!("boom"));
+        assert_eq!(report["error"]["code"], INTERNAL_ERROR);
+    }
+}
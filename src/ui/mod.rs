@@ -0,0 +1,4 @@
+pub mod editor;
+pub mod json;
+pub mod preview;
+pub mod review;
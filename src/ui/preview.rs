@@ -0,0 +1,93 @@
+use crate::services::stream::TokenSink;
+use std::io::{IsTerminal, Stderr, Write};
+
+/// Shows a message in the terminal while it is generated, and erases it afterwards
+///
+/// Line wrapping is turned off while the preview is shown, so each line of
+/// the message takes one row and the rows to erase are known.
+pub struct LivePreview<W: Write + Send> {
+    out: W,
+    /// Line breaks written since the preview was last erased
+    rows: usize,
+    shown: bool,
+}
+
+impl LivePreview<Stderr> {
+    /// A preview on standard error, if it is a terminal
+    pub fn stderr() -> Option<Self> {
+        let stderr = std::io::stderr();
+        stderr.is_terminal().then(|| Self::new(stderr))
+    }
+}
+
+impl<W: Write + Send> LivePreview<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            rows: 0,
+            shown: false,
+        }
+    }
+
+    /// Erase the text shown so far
+    pub fn clear(&mut self) {
+        if !self.shown {
+            return;
+        }
+        let up = match self.rows {
+            0 => String::new(),
+            rows => format!("\x1b[{rows}A"),
+        };
+        // Errors only leave stale text on the screen
+        let _ = write!(self.out, "\x1b[0m\r{up}\x1b[J\x1b[?7h");
+        let _ = self.out.flush();
+        self.rows = 0;
+        self.shown = false;
+    }
+}
+
+impl<W: Write + Send> TokenSink for LivePreview<W> {
+    fn token(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if !self.shown {
+            // Dim, without line wrapping
+            let _ = write!(self.out, "\x1b[?7l\x1b[2m");
+            self.shown = true;
+        }
+        self.rows += text.matches('\n').count();
+        let _ = write!(self.out, "{text}");
+        let _ = self.out.flush();
+    }
+
+    fn restart(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_erases_its_rows() {
+        let mut preview = LivePreview::new(Vec::new());
+        preview.clear();
+        assert!(preview.out.is_empty());
+
+        preview.token("feat: add");
+        preview.token("");
+        preview.token(" login\n\nbody");
+        preview.restart();
+        preview.token("fix: typo");
+        preview.clear();
+
+        let output = String::from_utf8(preview.out).unwrap();
+        assert_eq!(
+            output,
+            "\x1b[?7l\x1b[2mfeat: add login\n\nbody\x1b[0m\r\x1b[2A\x1b[J\x1b[?7h\
+             \x1b[?7l\x1b[2mfix: typo\x1b[0m\r\x1b[J\x1b[?7h"
+        );
+    }
+}
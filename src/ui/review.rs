@@ -0,0 +1,279 @@
+use crate::error::Result;
+use crate::ui::editor::edit_file;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+
+/// Instructions appended below the message while it is being edited
+const EDIT_INSTRUCTIONS: &str = "\n# Edit the commit message above. Lines starting with '#' are ignored,\n# and an empty message keeps the current candidate.\n";
+
+/// A commit message offered for review
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub message: String,
+    /// Where the message came from, e.g. the model name or `edited`
+    pub origin: String,
+}
+
+/// What the caller should do after the user picked an action
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReviewStep {
+    /// Use this message
+    Accept(String),
+    /// Generate another candidate, optionally with another model or an extra focus hint
+    Regenerate {
+        /// A model name or `provider:model` spec, checked by the caller
+        model: Option<String>,
+        hint: Option<String>,
+    },
+    /// Stop without using any message
+    Abort,
+}
+
+/// Interactive review of generated messages, keeping every candidate
+pub struct Review {
+    candidates: Vec<Candidate>,
+    current: usize,
+    editor: String,
+    edit_path: PathBuf,
+}
+
+impl Review {
+    /// Create a review that edits messages in `edit_path` with `editor`
+    pub fn new(editor: String, edit_path: PathBuf) -> Self {
+        Self {
+            candidates: Vec::new(),
+            current: 0,
+            editor,
+            edit_path,
+        }
+    }
+
+    /// Add a candidate and make it the current one
+    pub fn push(&mut self, candidate: Candidate) {
+        self.candidates.push(candidate);
+        self.current = self.candidates.len() - 1;
+    }
+
+    /// Show the current candidate and ask for actions until one needs the caller
+    ///
+    /// Editing and switching between earlier candidates are handled here.
+    /// The end of the input counts as aborting.
+    pub fn next_step(
+        &mut self,
+        input: &mut impl BufRead,
+        output: &mut impl Write,
+    ) -> Result<ReviewStep> {
+        loop {
+            self.show(output)?;
+            let count = self.candidates.len();
+            let menu = if count > 1 {
+                format!(
+                    "[a]ccept  [e]dit  [r]egenerate  [m]odel  [1-{count}] show candidate  [q]uit: "
+                )
+            } else {
+                "[a]ccept  [e]dit  [r]egenerate  [m]odel  [q]uit: ".to_string()
+            };
+            let Some(choice) = ask(input, output, &menu)? else {
+                return Ok(ReviewStep::Abort);
+            };
+
+            match choice.to_lowercase().as_str() {
+                "a" | "accept" => {
+                    return Ok(ReviewStep::Accept(
+                        self.candidates[self.current].message.clone(),
+                    ));
+                }
+                "e" | "edit" => self.edit(output)?,
+                "r" | "regenerate" => {
+                    let hint = ask(
+                        input,
+                        output,
+                        "Extra focus for the new message (Enter to skip): ",
+                    )?;
+                    return Ok(ReviewStep::Regenerate {
+                        model: None,
+                        hint: hint.filter(|hint| !hint.is_empty()),
+                    });
+                }
+                "m" | "model" => {
+                    let Some(name) = ask(input, output, "Model name or provider:model: ")? else {
+                        return Ok(ReviewStep::Abort);
+                    };
+                    if !name.is_empty() {
+                        return Ok(ReviewStep::Regenerate {
+                            model: Some(name),
+                            hint: None,
+                        });
+                    }
+                }
+                "q" | "quit" | "abort" => return Ok(ReviewStep::Abort),
+                other => match other.parse::<usize>() {
+                    Ok(number) if (1..=count).contains(&number) => self.current = number - 1,
+                    _ => writeln!(output, "Unknown choice '{other}'")?,
+                },
+            }
+        }
+    }
+
+    /// Print the current candidate with its position and origin
+    fn show(&self, output: &mut impl Write) -> Result<()> {
+        let candidate = &self.candidates[self.current];
+        writeln!(
+            output,
+            "\n--- Candidate {} of {} ({}) ---\n{}\n---",
+            self.current + 1,
+            self.candidates.len(),
+            candidate.origin,
+            candidate.message.trim_end()
+        )?;
+        Ok(())
+    }
+
+    /// Edit the current candidate and add the result as a new candidate
+    fn edit(&mut self, output: &mut impl Write) -> Result<()> {
+        let message = &self.candidates[self.current].message;
+        std::fs::write(
+            &self.edit_path,
+            format!("{}\n{EDIT_INSTRUCTIONS}", message.trim_end()),
+        )?;
+
+        if let Err(e) = edit_file(&self.editor, &self.edit_path) {
+            writeln!(output, "{e}")?;
+            return Ok(());
+        }
+
+        let edited = std::fs::read_to_string(&self.edit_path)?;
+        let edited = git2::message_prettify(edited, Some(b'#')).unwrap_or_default();
+        if edited.trim().is_empty() {
+            writeln!(output, "Empty message, keeping the current candidate.")?;
+        } else if edited.trim_end() != message.trim_end() {
+            self.push(Candidate {
+                message: edited.trim_end().to_string(),
+                origin: "edited".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Print a prompt and read one trimmed line, or None at the end of the input
+fn ask(input: &mut impl BufRead, output: &mut impl Write, prompt: &str) -> Result<Option<String>> {
+    write!(output, "{prompt}")?;
+    output.flush()?;
+
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review_with(messages: &[&str]) -> Review {
+        let mut review = Review::new("true".to_string(), std::env::temp_dir().join("unused"));
+        for message in messages {
+            review.push(Candidate {
+                message: message.to_string(),
+                origin: "llama-3.3-70b-versatile".to_string(),
+            });
+        }
+        review
+    }
+
+    fn run(review: &mut Review, input: &str) -> (ReviewStep, String) {
+        let mut output = Vec::new();
+        let step = review
+            .next_step(&mut input.as_bytes(), &mut output)
+            .unwrap();
+        (step, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn test_accept() {
+        let mut review = review_with(&["feat: first"]);
+        let (step, output) = run(&mut review, "a\n");
+
+        assert_eq!(step, ReviewStep::Accept("feat: first".to_string()));
+        assert!(output.contains("--- Candidate 1 of 1 (llama-3.3-70b-versatile) ---\nfeat: first"));
+    }
+
+    #[test]
+    fn test_previous_candidates_stay_available() {
+        let mut review = review_with(&["feat: first", "feat: second"]);
+        let (step, output) = run(&mut review, "9\n1\na\n");
+
+        assert!(output.contains("[1-2] show candidate"));
+        assert!(output.contains("Unknown choice '9'"));
+        assert!(output.contains("--- Candidate 1 of 2"));
+        assert_eq!(step, ReviewStep::Accept("feat: first".to_string()));
+    }
+
+    #[test]
+    fn test_regenerate_with_hint() {
+        let mut review = review_with(&["feat: first"]);
+
+        let (step, _) = run(&mut review, "r\nmention the parser\n");
+        assert_eq!(
+            step,
+            ReviewStep::Regenerate {
+                model: None,
+                hint: Some("mention the parser".to_string())
+            }
+        );
+
+        let (step, _) = run(&mut review, "r\n\n");
+        assert_eq!(
+            step,
+            ReviewStep::Regenerate {
+                model: None,
+                hint: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_switch_model() {
+        let mut review = review_with(&["feat: first"]);
+        let (step, output) = run(&mut review, "m\n\nm\nollama:llama3.2\n");
+
+        assert_eq!(output.matches("Model name").count(), 2);
+        assert_eq!(
+            step,
+            ReviewStep::Regenerate {
+                model: Some("ollama:llama3.2".to_string()),
+                hint: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_quit_and_end_of_input_abort() {
+        let mut review = review_with(&["feat: first"]);
+        assert_eq!(run(&mut review, "q\n").0, ReviewStep::Abort);
+        assert_eq!(run(&mut review, "").0, ReviewStep::Abort);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_edit_adds_candidate() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut review = Review::new(
+            "sed -i s/feat/fix/".to_string(),
+            dir.path().join("COMMIT_EDITMSG"),
+        );
+        review.push(Candidate {
+            message: "feat: first".to_string(),
+            origin: "llama-3.3-70b-versatile".to_string(),
+        });
+
+        let (step, output) = run(&mut review, "e\n1\na\n");
+        assert!(output.contains("--- Candidate 2 of 2 (edited) ---\nfix: first"));
+        // The original message is still there after editing
+        assert_eq!(step, ReviewStep::Accept("feat: first".to_string()));
+        assert_eq!(review.candidates.len(), 2);
+    }
+}